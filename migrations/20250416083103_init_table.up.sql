@@ -5,7 +5,7 @@ CREATE TABLE users (
         email TEXT UNIQUE NOT NULL,
         password_hash TEXT NOT NULL,
         is_active BOOLEAN NOT NULL DEFAULT false,
-        is_verified BOOLEAND NOT NULL DEFAULT false,
+        is_verified BOOLEAN NOT NULL DEFAULT false,
         updated_at TIMESTAMP DEFAULT now(),
         created_at TIMESTAMP DEFAULT now()
 );
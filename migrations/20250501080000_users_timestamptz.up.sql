@@ -0,0 +1,6 @@
+-- `User` decodes timestamps as DateTime<Utc>, which requires TIMESTAMPTZ
+ALTER TABLE users
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ,
+    ALTER COLUMN created_at SET NOT NULL,
+    ALTER COLUMN updated_at TYPE TIMESTAMPTZ,
+    ALTER COLUMN updated_at SET NOT NULL;
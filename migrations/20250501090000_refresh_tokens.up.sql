@@ -0,0 +1,15 @@
+-- Refresh tokens, rotated on every use and grouped in families for reuse detection
+CREATE TABLE refresh_tokens (
+       id UUID PRIMARY KEY,
+       user_id UUID NOT NULL,
+       family_id UUID NOT NULL,
+       token_hash VARCHAR(64) NOT NULL,
+       expires_at TIMESTAMPTZ NOT NULL,
+       used_at TIMESTAMPTZ,
+       revoked_at TIMESTAMPTZ,
+       created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+       UNIQUE(token_hash)
+);
+
+CREATE INDEX refresh_tokens_family_id_idx ON refresh_tokens (family_id);
@@ -0,0 +1,3 @@
+-- Verification tokens are shared by account activation and password reset
+ALTER TABLE verification_tokens
+    ADD COLUMN purpose VARCHAR(32) NOT NULL DEFAULT 'activation';
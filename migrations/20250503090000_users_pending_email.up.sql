@@ -0,0 +1,2 @@
+-- New email address awaiting confirmation through an `email_change` token
+ALTER TABLE users ADD COLUMN pending_email TEXT;
@@ -0,0 +1,5 @@
+DROP TABLE mfa_challenges;
+ALTER TABLE users
+    DROP COLUMN totp_secret,
+    DROP COLUMN totp_enabled_at,
+    DROP COLUMN totp_last_step;
@@ -0,0 +1,17 @@
+-- TOTP secrets are AES-GCM encrypted; enrollment is pending until totp_enabled_at is set
+ALTER TABLE users
+    ADD COLUMN totp_secret TEXT,
+    ADD COLUMN totp_enabled_at TIMESTAMPTZ,
+    ADD COLUMN totp_last_step BIGINT;
+
+-- Issued by a password login when MFA is enabled, redeemed at /user/login/mfa
+CREATE TABLE mfa_challenges (
+       id UUID PRIMARY KEY,
+       user_id UUID NOT NULL,
+       token_hash VARCHAR(64) NOT NULL,
+       attempts INTEGER NOT NULL DEFAULT 0,
+       expires_at TIMESTAMPTZ NOT NULL,
+       created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+       UNIQUE(token_hash)
+);
@@ -0,0 +1,11 @@
+-- Single-use MFA recovery codes, stored as Argon2 hashes
+CREATE TABLE recovery_codes (
+       id UUID PRIMARY KEY,
+       user_id UUID NOT NULL,
+       code_hash TEXT NOT NULL,
+       used_at TIMESTAMPTZ,
+       created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX recovery_codes_user_id_idx ON recovery_codes (user_id);
@@ -0,0 +1,11 @@
+-- Short-lived one-time codes for passwordless login, one pending code per user
+CREATE TABLE email_login_codes (
+       id UUID PRIMARY KEY,
+       user_id UUID NOT NULL,
+       code_hash TEXT NOT NULL,
+       attempts INTEGER NOT NULL DEFAULT 0,
+       expires_at TIMESTAMPTZ NOT NULL,
+       created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+       UNIQUE(user_id)
+);
@@ -0,0 +1,27 @@
+-- WebAuthn credentials; public_key holds the COSE_Key from the attestation
+CREATE TABLE passkey_credentials (
+       id UUID PRIMARY KEY,
+       user_id UUID NOT NULL,
+       credential_id TEXT NOT NULL,
+       public_key BYTEA NOT NULL,
+       sign_count BIGINT NOT NULL DEFAULT 0,
+       name VARCHAR(100),
+       created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       last_used_at TIMESTAMPTZ,
+       FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+       UNIQUE(credential_id)
+);
+
+CREATE INDEX passkey_credentials_user_id_idx ON passkey_credentials (user_id);
+
+-- Outstanding registration/authentication challenges, user_id is unset for discoverable logins
+CREATE TABLE webauthn_challenges (
+       id UUID PRIMARY KEY,
+       user_id UUID,
+       ceremony VARCHAR(16) NOT NULL,
+       challenge_hash VARCHAR(64) NOT NULL,
+       expires_at TIMESTAMPTZ NOT NULL,
+       created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+       UNIQUE(challenge_hash)
+);
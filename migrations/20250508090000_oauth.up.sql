@@ -0,0 +1,27 @@
+-- Clients registered with the OpenID Connect provider; public clients have no secret
+CREATE TABLE oauth_clients (
+       id UUID PRIMARY KEY,
+       client_id VARCHAR(64) NOT NULL,
+       client_secret_hash TEXT,
+       name VARCHAR(100) NOT NULL,
+       redirect_uris TEXT[] NOT NULL DEFAULT '{}',
+       created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       UNIQUE(client_id)
+);
+
+-- Issued by /oauth/authorize and exchanged once at /oauth/token
+CREATE TABLE oauth_authorization_codes (
+       id UUID PRIMARY KEY,
+       code_hash VARCHAR(64) NOT NULL,
+       client_id VARCHAR(64) NOT NULL,
+       user_id UUID NOT NULL,
+       redirect_uri TEXT NOT NULL,
+       scope TEXT NOT NULL,
+       nonce TEXT,
+       code_challenge VARCHAR(128) NOT NULL,
+       expires_at TIMESTAMPTZ NOT NULL,
+       created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       FOREIGN KEY (client_id) REFERENCES oauth_clients(client_id) ON DELETE CASCADE,
+       FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+       UNIQUE(code_hash)
+);
@@ -0,0 +1,3 @@
+ALTER TABLE oauth_clients
+    DROP COLUMN scopes,
+    DROP COLUMN revoked_at;
@@ -0,0 +1,4 @@
+-- Scopes a client may request with the client_credentials grant, and soft revocation
+ALTER TABLE oauth_clients
+    ADD COLUMN scopes TEXT[] NOT NULL DEFAULT '{}',
+    ADD COLUMN revoked_at TIMESTAMPTZ;
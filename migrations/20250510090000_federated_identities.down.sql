@@ -0,0 +1,2 @@
+DROP TABLE federation_states;
+DROP TABLE federated_identities;
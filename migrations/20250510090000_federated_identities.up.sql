@@ -0,0 +1,25 @@
+-- Accounts at external identity providers, keyed by the provider's subject
+CREATE TABLE federated_identities (
+       id UUID PRIMARY KEY,
+       user_id UUID NOT NULL,
+       provider VARCHAR(50) NOT NULL,
+       subject TEXT NOT NULL,
+       email TEXT,
+       created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       last_login_at TIMESTAMPTZ,
+       FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+       UNIQUE(provider, subject)
+);
+
+CREATE INDEX federated_identities_user_id_idx ON federated_identities (user_id);
+
+-- Outstanding authorization requests sent to identity providers
+CREATE TABLE federation_states (
+       id UUID PRIMARY KEY,
+       state_hash VARCHAR(64) NOT NULL,
+       provider VARCHAR(50) NOT NULL,
+       code_verifier TEXT NOT NULL,
+       expires_at TIMESTAMPTZ NOT NULL,
+       created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       UNIQUE(state_hash)
+);
@@ -0,0 +1,6 @@
+-- Access tokens revoked before their expiry through /oauth/revoke
+CREATE TABLE revoked_tokens (
+       token_hash VARCHAR(64) PRIMARY KEY,
+       expires_at TIMESTAMPTZ NOT NULL,
+       revoked_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
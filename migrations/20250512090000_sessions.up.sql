@@ -0,0 +1,24 @@
+-- Login sessions. The id doubles as the refresh token family and as the `jti`
+-- of every access token issued within the session.
+CREATE TABLE sessions (
+       id UUID PRIMARY KEY,
+       user_id UUID NOT NULL,
+       device VARCHAR(100),
+       user_agent TEXT,
+       ip_address VARCHAR(45),
+       created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       last_seen_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       expires_at TIMESTAMPTZ NOT NULL,
+       revoked_at TIMESTAMPTZ,
+       FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX sessions_user_id_idx ON sessions (user_id);
+
+-- Keep refresh token families issued before sessions existed usable
+INSERT INTO sessions (id, user_id, created_at, last_seen_at, expires_at)
+SELECT family_id, user_id, MIN(created_at), MAX(created_at), MAX(expires_at)
+FROM refresh_tokens
+WHERE revoked_at IS NULL
+GROUP BY family_id, user_id
+HAVING MAX(expires_at) > NOW();
@@ -0,0 +1,10 @@
+CREATE TABLE revoked_tokens (
+       token_hash VARCHAR(64) PRIMARY KEY,
+       expires_at TIMESTAMPTZ NOT NULL,
+       revoked_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO revoked_tokens (token_hash, expires_at, revoked_at)
+SELECT jti, expires_at, created_at FROM token_denylist WHERE length(jti) = 64;
+
+DROP TABLE token_denylist;
@@ -0,0 +1,14 @@
+-- Access tokens denied before their expiry, keyed by `jti`, or by the token's
+-- SHA-256 for tokens without one. Replaces revoked_tokens.
+CREATE TABLE token_denylist (
+       jti VARCHAR(64) PRIMARY KEY,
+       expires_at TIMESTAMPTZ NOT NULL,
+       created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX token_denylist_expires_at_idx ON token_denylist (expires_at);
+
+INSERT INTO token_denylist (jti, expires_at, created_at)
+SELECT token_hash, expires_at, revoked_at FROM revoked_tokens WHERE expires_at > NOW();
+
+DROP TABLE revoked_tokens;
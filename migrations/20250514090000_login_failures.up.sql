@@ -0,0 +1,10 @@
+-- Consecutive failed logins per account and per client address, driving
+-- login backoff and lockout.
+CREATE TABLE login_failures (
+       scope VARCHAR(16) NOT NULL,
+       subject VARCHAR(64) NOT NULL,
+       failures INT NOT NULL DEFAULT 0,
+       blocked_until TIMESTAMPTZ,
+       last_failed_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       PRIMARY KEY (scope, subject)
+);
@@ -0,0 +1,38 @@
+CREATE TABLE roles (
+       id UUID PRIMARY KEY,
+       name VARCHAR(64) NOT NULL UNIQUE,
+       description TEXT NOT NULL DEFAULT '',
+       created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Permissions are checked by name in code, e.g. `roles:manage`.
+CREATE TABLE permissions (
+       id UUID PRIMARY KEY,
+       name VARCHAR(100) NOT NULL UNIQUE,
+       description TEXT NOT NULL DEFAULT ''
+);
+
+CREATE TABLE role_permissions (
+       role_id UUID NOT NULL REFERENCES roles(id) ON DELETE CASCADE,
+       permission_id UUID NOT NULL REFERENCES permissions(id) ON DELETE CASCADE,
+       PRIMARY KEY (role_id, permission_id)
+);
+
+CREATE TABLE user_roles (
+       user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+       role_id UUID NOT NULL REFERENCES roles(id) ON DELETE CASCADE,
+       created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       PRIMARY KEY (user_id, role_id)
+);
+
+CREATE INDEX user_roles_role_id_idx ON user_roles (role_id);
+
+INSERT INTO permissions (id, name, description) VALUES
+       (gen_random_uuid(), 'roles:manage', 'Create roles and assign them to users'),
+       (gen_random_uuid(), 'oauth_clients:manage', 'Register and revoke OAuth clients');
+
+INSERT INTO roles (id, name, description)
+VALUES (gen_random_uuid(), 'admin', 'Every permission');
+
+INSERT INTO role_permissions (role_id, permission_id)
+SELECT roles.id, permissions.id FROM roles CROSS JOIN permissions WHERE roles.name = 'admin';
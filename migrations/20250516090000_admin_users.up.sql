@@ -0,0 +1,10 @@
+INSERT INTO permissions (id, name, description)
+VALUES (gen_random_uuid(), 'users:manage', 'List, update and delete user accounts');
+
+INSERT INTO role_permissions (role_id, permission_id)
+SELECT roles.id, permissions.id FROM roles, permissions
+WHERE roles.name = 'admin' AND permissions.name = 'users:manage';
+
+-- Verification only set is_active so far; accounts can now be deactivated
+-- independently of having verified their email.
+UPDATE users SET is_verified = TRUE WHERE is_active;
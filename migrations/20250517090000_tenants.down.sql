@@ -0,0 +1,13 @@
+ALTER TABLE federation_states DROP COLUMN tenant_id;
+
+ALTER TABLE federated_identities
+    DROP CONSTRAINT federated_identities_tenant_id_provider_subject_key,
+    ADD CONSTRAINT federated_identities_provider_subject_key UNIQUE (provider, subject);
+ALTER TABLE federated_identities DROP COLUMN tenant_id;
+
+ALTER TABLE users
+    DROP CONSTRAINT users_tenant_id_username_key,
+    DROP CONSTRAINT users_tenant_id_email_key,
+    ADD CONSTRAINT users_username_key UNIQUE (username),
+    ADD CONSTRAINT users_email_key UNIQUE (email);
+ALTER TABLE users DROP COLUMN tenant_id;
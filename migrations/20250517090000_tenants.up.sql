@@ -0,0 +1,15 @@
+-- Users belong to a tenant; emails and usernames are only unique within one.
+ALTER TABLE users ADD COLUMN tenant_id VARCHAR(64) NOT NULL DEFAULT 'default';
+ALTER TABLE users
+    DROP CONSTRAINT users_username_key,
+    DROP CONSTRAINT users_email_key,
+    ADD CONSTRAINT users_tenant_id_username_key UNIQUE (tenant_id, username),
+    ADD CONSTRAINT users_tenant_id_email_key UNIQUE (tenant_id, email);
+
+-- The same external account may sign in to several tenants, as separate users.
+ALTER TABLE federated_identities ADD COLUMN tenant_id VARCHAR(64) NOT NULL DEFAULT 'default';
+ALTER TABLE federated_identities
+    DROP CONSTRAINT federated_identities_provider_subject_key,
+    ADD CONSTRAINT federated_identities_tenant_id_provider_subject_key UNIQUE (tenant_id, provider, subject);
+
+ALTER TABLE federation_states ADD COLUMN tenant_id VARCHAR(64) NOT NULL DEFAULT 'default';
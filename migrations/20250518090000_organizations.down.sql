@@ -0,0 +1,4 @@
+ALTER TABLE sessions DROP COLUMN organization_id;
+DROP TABLE organization_invitations;
+DROP TABLE organization_members;
+DROP TABLE organizations;
@@ -0,0 +1,35 @@
+CREATE TABLE organizations (
+       id UUID PRIMARY KEY,
+       tenant_id VARCHAR(64) NOT NULL DEFAULT 'default',
+       name VARCHAR(100) NOT NULL,
+       created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Owners manage everything, admins manage members and invitations.
+CREATE TABLE organization_members (
+       organization_id UUID NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+       user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+       role VARCHAR(16) NOT NULL CHECK (role IN ('member', 'admin', 'owner')),
+       created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       PRIMARY KEY (organization_id, user_id)
+);
+
+CREATE INDEX organization_members_user_id_idx ON organization_members (user_id);
+
+CREATE TABLE organization_invitations (
+       id UUID PRIMARY KEY,
+       organization_id UUID NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+       email TEXT NOT NULL,
+       role VARCHAR(16) NOT NULL CHECK (role IN ('member', 'admin', 'owner')),
+       token_hash VARCHAR(64) NOT NULL UNIQUE,
+       invited_by UUID REFERENCES users(id) ON DELETE SET NULL,
+       expires_at TIMESTAMPTZ NOT NULL,
+       accepted_at TIMESTAMPTZ,
+       revoked_at TIMESTAMPTZ,
+       created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX organization_invitations_organization_id_idx ON organization_invitations (organization_id);
+
+-- The organization a session acts in, issued in its access tokens.
+ALTER TABLE sessions ADD COLUMN organization_id UUID REFERENCES organizations(id) ON DELETE SET NULL;
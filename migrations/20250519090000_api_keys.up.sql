@@ -0,0 +1,16 @@
+-- Personal API keys, `ak_<prefix>_<secret>`; the prefix finds the key and the
+-- secret is only kept as an Argon2 hash.
+CREATE TABLE api_keys (
+       id UUID PRIMARY KEY,
+       user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+       name VARCHAR(100) NOT NULL,
+       prefix VARCHAR(16) NOT NULL UNIQUE,
+       secret_hash TEXT NOT NULL,
+       scopes TEXT[] NOT NULL DEFAULT '{}',
+       expires_at TIMESTAMPTZ,
+       last_used_at TIMESTAMPTZ,
+       revoked_at TIMESTAMPTZ,
+       created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX api_keys_user_id_idx ON api_keys (user_id);
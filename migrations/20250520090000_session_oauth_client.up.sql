@@ -0,0 +1,3 @@
+-- OAuth client a session was started for through /oauth/token. Its refresh
+-- tokens are only redeemed by that client; NULL for first-party logins.
+ALTER TABLE sessions ADD COLUMN client_id VARCHAR(64) REFERENCES oauth_clients(client_id) ON DELETE CASCADE;
@@ -1,13 +1,98 @@
+use crate::config::Config;
+use crate::services::api_keys::ApiKeys;
 use crate::services::authentication::Authentication;
 use crate::services::email::EmailService;
+use crate::services::federation::Federation;
+use crate::services::mfa::Mfa;
+use crate::services::oauth::OAuth;
+use crate::services::organizations::Organizations;
+use crate::services::passkeys::Passkeys;
+use crate::services::roles::Roles;
 use crate::services::users::Users;
+use std::sync::Arc;
 
 pub struct AppState {
+    pub(crate) config: Arc<Config>,
     pub(crate) services: Services,
 }
 
 pub struct Services {
+    pub(crate) api_key_service: ApiKeys,
     pub(crate) auth_service: Authentication,
     pub(crate) email_service: EmailService,
+    pub(crate) federation_service: Federation,
+    pub(crate) mfa_service: Mfa,
+    pub(crate) oauth_service: OAuth,
+    pub(crate) organization_service: Organizations,
+    pub(crate) passkey_service: Passkeys,
+    pub(crate) role_service: Roles,
     pub(crate) user_service: Users,
 }
+
+#[cfg(test)]
+pub fn test_state() -> AppState {
+    use crate::config::test_config;
+
+    let pool = sqlx::PgPool::connect_lazy(&test_config().database.url).unwrap();
+    test_state_with_pool(pool)
+}
+
+/// Like [`test_state`], on a pool of a database set up by `#[sqlx::test]`.
+#[cfg(test)]
+pub fn test_state_with_pool(pool: sqlx::PgPool) -> AppState {
+    use crate::config::test_config;
+    use crate::services::denylist::PostgresDenylist;
+    use crate::services::jwt_keys::{JwtKey, JwtKeyring};
+    use crate::utils::clock::SystemClock;
+
+    let config = Arc::new(test_config());
+    AppState {
+        config: config.clone(),
+        services: Services {
+            api_key_service: ApiKeys::new(pool.clone()),
+            auth_service: Authentication::new(
+                pool.clone(),
+                config.clone(),
+                JwtKeyring::new(JwtKey::from_secret(None, &config.jwt.secret), vec![]).unwrap(),
+                Arc::new(PostgresDenylist::new(pool.clone())),
+            ),
+            email_service: EmailService::new(config.clone()),
+            federation_service: Federation::new(pool.clone(), config.clone()).unwrap(),
+            mfa_service: Mfa::new(pool.clone(), config.clone(), Arc::new(SystemClock)).unwrap(),
+            oauth_service: OAuth::new(pool.clone(), config.clone()),
+            organization_service: Organizations::new(pool.clone(), config.clone()),
+            passkey_service: Passkeys::new(pool.clone(), config.clone()),
+            role_service: Roles::new(pool.clone()),
+            user_service: Users::new(pool, config.clone()),
+        },
+    }
+}
+
+/// Signs in a new active user of `tenant` holding `role`, if any, and returns
+/// their access token.
+#[cfg(test)]
+pub async fn test_access_token(state: &AppState, pool: &sqlx::PgPool, tenant: &str, role: Option<&str>) -> String {
+    use crate::extractors::client_context::ClientContext;
+    use uuid::Uuid;
+
+    let user_id = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO users (id, tenant_id, username, email, password_hash, is_active) VALUES ($1, $2, $3, $4, '', true)",
+    )
+    .bind(user_id)
+    .bind(tenant)
+    .bind(user_id.simple().to_string())
+    .bind(format!("{}@example.com", user_id.simple()))
+    .execute(pool)
+    .await
+    .unwrap();
+    if let Some(role) = role {
+        state.services.role_service.assign_role(tenant, &user_id, role).await.unwrap();
+    }
+    let user = state.services.user_service.get_user_by_id(&user_id).await.unwrap();
+    let client = ClientContext {
+        user_agent: None,
+        ip_address: None,
+    };
+    state.services.auth_service.start_session(&user, &client, None).await.unwrap().access_token
+}
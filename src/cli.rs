@@ -0,0 +1,34 @@
+use crate::config::{Config, DEFAULT_TENANT};
+use crate::error::user::UserError;
+use crate::services::roles::Roles;
+use crate::services::users::Users;
+use anyhow::{anyhow, bail};
+use sqlx::PgPool;
+use std::sync::Arc;
+
+const USAGE: &str = "usage: auth-service grant-role <email> <role> [tenant]";
+
+/// Runs a maintenance command given on the command line instead of serving,
+/// such as `grant-role`, which bootstraps the first administrator:
+/// `auth-service grant-role admin@example.com admin`.
+pub async fn run(args: &[String], pool: PgPool, config: Arc<Config>) -> anyhow::Result<()> {
+    match args {
+        [command, email, role, rest @ ..] if command == "grant-role" && rest.len() <= 1 => {
+            let tenant = rest.first().map_or(DEFAULT_TENANT, String::as_str);
+            let user = Users::new(pool.clone(), config)
+                .get_user_by_email(tenant, email)
+                .await
+                .map_err(|e| match e {
+                    UserError::UserNotFound(_) => anyhow!("No user {} in tenant {}", email, tenant),
+                    e => anyhow!("Failed to look up {}: {}", email, e),
+                })?;
+            Roles::new(pool)
+                .assign_role(tenant, &user.id, role)
+                .await
+                .map_err(|e| anyhow!("Failed to grant {} to {}: {}", role, email, e))?;
+            println!("Granted {} to {} in tenant {}", role, email, tenant);
+            Ok(())
+        }
+        _ => bail!(USAGE),
+    }
+}
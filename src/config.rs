@@ -1,5 +1,8 @@
+use chrono::{DateTime, Utc};
 use config::{Config as RawConfig, ConfigError, Environment, File};
+use jsonwebtoken::Algorithm;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::env;
 
 #[derive(Debug, Deserialize)]
@@ -9,18 +12,87 @@ pub struct Config {
     pub jwt: JwtConfig,
     pub smtp: SmtpConfig,
     pub app: AppConfig,
+    pub mfa: MfaConfig,
+    pub webauthn: WebauthnConfig,
+    pub oidc: OidcConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub federation: FederationConfig,
+    #[serde(default)]
+    pub denylist: DenylistConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub tenants: Vec<TenantConfig>,
+}
+
+/// Tenant of users who registered without one, and of requests no configured tenant claims.
+pub const DEFAULT_TENANT: &str = "default";
+
+impl Config {
+    pub fn tenant(&self, tenant_id: &str) -> Option<&TenantConfig> {
+        self.tenants.iter().find(|tenant| tenant.id == tenant_id)
+    }
+
+    /// Frontend links used in emails to users of `tenant_id`.
+    pub fn app_for(&self, tenant_id: &str) -> &AppConfig {
+        self.tenant(tenant_id)
+            .and_then(|tenant| tenant.app.as_ref())
+            .unwrap_or(&self.app)
+    }
+
+    /// Name and address emails to users of `tenant_id` are sent from.
+    pub fn sender_for(&self, tenant_id: &str) -> (&str, &str) {
+        let tenant = self.tenant(tenant_id);
+        (
+            tenant
+                .and_then(|tenant| tenant.from_name.as_deref())
+                .unwrap_or(&self.smtp.from_name),
+            tenant
+                .and_then(|tenant| tenant.from_email.as_deref())
+                .unwrap_or(&self.smtp.from_email),
+        )
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct AppConfig {
     pub verification_url: String,
+    pub password_reset_url: String,
+    pub email_change_url: String,
+    pub magic_link_url: String,
+    /// Page that accepts an organization invitation, posting its token to
+    /// `/user/organizations/invitations/accept` once the user is signed in.
+    pub invitation_url: String,
+}
+
+/// An organisation with its own users, served by the same deployment. Emails
+/// and usernames only need to be unique within a tenant.
+#[derive(Debug, Deserialize)]
+pub struct TenantConfig {
+    /// Stored with the tenant's users and issued as the `tid` claim.
+    pub id: String,
+    /// `Host` headers served as this tenant. Any tenant can also be reached
+    /// under the `/t/{id}` path prefix.
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    /// Links in emails to the tenant's users, in place of `app`.
+    pub app: Option<AppConfig>,
+    /// Sender of emails to the tenant's users, in place of `smtp.from_*`.
+    pub from_name: Option<String>,
+    pub from_email: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ServerConfig {
+    #[serde(default = "default_host")]
     pub host: String,
     #[serde(default = "default_port")]
     pub port: u16,
+    /// Take client IPs from `X-Forwarded-For`; only enable behind a proxy that sets it.
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
 }
 
 fn default_host() -> String {
@@ -37,13 +109,219 @@ pub struct DatabaseConfig {
 
 #[derive(Debug, Deserialize)]
 pub struct JwtConfig {
+    /// Shared secret, only used when `algorithm` is HS256.
+    #[serde(default)]
     pub secret: String,
     #[serde(default = "default_jwt_expiration")]
     pub expiration: i64,
+    #[serde(default = "default_refresh_expiration")]
+    pub refresh_expiration: i64,
+    #[serde(default = "default_jwt_algorithm")]
+    pub algorithm: Algorithm,
+    /// Published as `kid`; derived from the public key thumbprint when unset.
+    pub key_id: Option<String>,
+    /// PKCS#8 PEM files for RS256, ES256 and EdDSA.
+    pub private_key_path: Option<String>,
+    pub public_key_path: Option<String>,
+    /// Previous keys that still verify tokens but no longer sign them.
+    #[serde(default)]
+    pub verification_keys: Vec<VerificationKeyConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerificationKeyConfig {
+    pub key_id: String,
+    pub algorithm: Algorithm,
+    pub secret: Option<String>,
+    pub public_key_path: Option<String>,
+    /// The key is dropped from the keyring and JWKS after this instant.
+    pub retire_at: Option<DateTime<Utc>>,
+}
+
+/// Login throttling. Each failed login blocks further attempts for the account
+/// and the client address, doubling from `login_backoff_base_seconds`; at the
+/// thresholds they are locked out for `lockout_seconds`. Failure counts start
+/// over after `lockout_seconds` without failures.
+#[derive(Debug, Deserialize)]
+pub struct AuthConfig {
+    #[serde(default = "default_max_failed_logins")]
+    pub max_failed_logins: u32,
+    #[serde(default = "default_max_failed_logins_per_ip")]
+    pub max_failed_logins_per_ip: u32,
+    #[serde(default = "default_login_backoff_base")]
+    pub login_backoff_base_seconds: i64,
+    #[serde(default = "default_lockout")]
+    pub lockout_seconds: i64,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            max_failed_logins: default_max_failed_logins(),
+            max_failed_logins_per_ip: default_max_failed_logins_per_ip(),
+            login_backoff_base_seconds: default_login_backoff_base(),
+            lockout_seconds: default_lockout(),
+        }
+    }
+}
+
+fn default_max_failed_logins() -> u32 {
+    5
+}
+
+fn default_max_failed_logins_per_ip() -> u32 {
+    20
+}
+
+fn default_login_backoff_base() -> i64 {
+    1
+}
+
+fn default_lockout() -> i64 {
+    900
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MfaConfig {
+    /// Shown as the account issuer in authenticator apps.
+    #[serde(default = "default_mfa_issuer")]
+    pub issuer: String,
+    /// Base64-encoded 32 byte key encrypting TOTP secrets at rest.
+    pub secret: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebauthnConfig {
+    /// Relying party id passkeys are scoped to, usually the site's registrable domain.
+    pub rp_id: String,
+    #[serde(default = "default_mfa_issuer")]
+    pub rp_name: String,
+    /// Origin the browser reports in client data, e.g. `https://example.com`.
+    pub origin: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcConfig {
+    /// Public base URL of this service, used as the `iss` of ID tokens.
+    pub issuer: String,
+    /// First-party login page `/oauth/authorize` sends unauthenticated users to.
+    /// It receives the authorization request as query parameters and, once the
+    /// user has signed in, posts them back to `/oauth/authorize`.
+    pub login_url: String,
+    /// `aud` of client_credentials tokens, identifying the APIs that accept them.
+    pub client_token_audience: String,
 }
 
 #[derive(Debug, Deserialize)]
-pub struct AuthConfig {}
+pub struct DenylistConfig {
+    /// How long a token found not to be denied is trusted without asking the
+    /// store again. Denials made on other instances take up to this long to apply.
+    #[serde(default = "default_denylist_cache_ttl")]
+    pub cache_ttl_seconds: u64,
+    /// Interval between purges of entries whose tokens have expired.
+    #[serde(default = "default_denylist_sweep_interval")]
+    pub sweep_interval_seconds: u64,
+}
+
+impl Default for DenylistConfig {
+    fn default() -> Self {
+        Self {
+            cache_ttl_seconds: default_denylist_cache_ttl(),
+            sweep_interval_seconds: default_denylist_sweep_interval(),
+        }
+    }
+}
+
+fn default_denylist_cache_ttl() -> u64 {
+    5
+}
+
+fn default_denylist_sweep_interval() -> u64 {
+    300
+}
+
+/// Request limits for the `/user` routes, keyed by route path such as
+/// `/user/login`. Routes without an entry are not limited. Buckets are kept in
+/// memory by each process, so N replicas together allow N times the budget.
+#[derive(Debug, Deserialize)]
+pub struct RateLimitConfig {
+    #[serde(default)]
+    pub routes: HashMap<String, RouteRateLimit>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RouteRateLimit {
+    /// Bucket per client address.
+    pub per_ip: Option<TokenBucketConfig>,
+    /// Bucket per value of `identity_field` in the JSON body, e.g. the email
+    /// a request would send mail to.
+    pub per_identity: Option<TokenBucketConfig>,
+    pub identity_field: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct TokenBucketConfig {
+    /// Requests allowed at once.
+    pub burst: u32,
+    /// Requests the bucket regains per minute.
+    pub per_minute: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        let limit = |ip: (u32, u32), identity: (u32, u32), field: &str| RouteRateLimit {
+            per_ip: Some(TokenBucketConfig { burst: ip.0, per_minute: ip.1 }),
+            per_identity: Some(TokenBucketConfig { burst: identity.0, per_minute: identity.1 }),
+            identity_field: Some(field.to_string()),
+        };
+        let routes = HashMap::from([
+            ("/user/register".to_string(), limit((10, 10), (3, 1), "email")),
+            ("/user/resend-token".to_string(), limit((10, 10), (3, 1), "user_id")),
+            ("/user/login".to_string(), limit((30, 30), (10, 10), "identity")),
+            ("/user/password/forgot".to_string(), limit((10, 10), (3, 1), "email")),
+            ("/user/login/email-code".to_string(), limit((10, 10), (3, 1), "email")),
+            ("/user/login/magic-link".to_string(), limit((10, 10), (3, 1), "email")),
+            // Routes where a secret is guessed.
+            ("/user/login/email-code/verify".to_string(), limit((30, 30), (5, 5), "email")),
+            ("/user/login/mfa".to_string(), limit((30, 30), (5, 5), "challenge_token")),
+            ("/user/login/magic-link/verify".to_string(), limit((10, 10), (5, 5), "token")),
+            ("/user/password/reset".to_string(), limit((10, 10), (5, 5), "token")),
+        ]);
+        Self { routes }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct FederationConfig {
+    /// Frontend page identity providers send the browser back to, registered
+    /// with each of them as the redirect URI. It posts the `code` and `state`
+    /// it receives to `/user/login/federated/callback`.
+    pub redirect_url: String,
+    #[serde(default)]
+    pub providers: Vec<IdentityProviderConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdentityProviderConfig {
+    /// Used in login URLs and stored with linked identities, e.g. `google`.
+    pub name: String,
+    /// OpenID Connect issuer; endpoints left unset are read from its discovery document.
+    pub issuer: Option<String>,
+    pub authorization_endpoint: Option<String>,
+    pub token_endpoint: Option<String>,
+    pub userinfo_endpoint: Option<String>,
+    pub client_id: String,
+    pub client_secret: String,
+    #[serde(default = "default_federation_scopes")]
+    pub scopes: Vec<String>,
+    /// Userinfo member holding the account id, `id` for GitHub.
+    #[serde(default = "default_subject_claim")]
+    pub subject_claim: String,
+    /// Treat the returned email as verified when the provider omits
+    /// `email_verified`, for providers that only expose verified addresses.
+    #[serde(default)]
+    pub trust_email: bool,
+}
 
 #[derive(Debug, Deserialize)]
 pub struct SmtpConfig {
@@ -57,7 +335,27 @@ pub struct SmtpConfig {
 }
 
 fn default_jwt_expiration() -> i64 {
-    86400 // 24 hours in seconds
+    900 // 15 minutes in seconds
+}
+
+fn default_refresh_expiration() -> i64 {
+    1_209_600 // 14 days in seconds
+}
+
+fn default_jwt_algorithm() -> Algorithm {
+    Algorithm::HS256
+}
+
+fn default_mfa_issuer() -> String {
+    "Auth Service".to_string()
+}
+
+fn default_federation_scopes() -> Vec<String> {
+    vec!["openid".to_string(), "email".to_string(), "profile".to_string()]
+}
+
+fn default_subject_claim() -> String {
+    "sub".to_string()
 }
 
 pub fn load_config() -> Result<Config, ConfigError> {
@@ -72,3 +370,65 @@ pub fn load_config() -> Result<Config, ConfigError> {
     // Parse environment variables into config
     config.try_deserialize()
 }
+
+#[cfg(test)]
+pub fn test_config() -> Config {
+    Config {
+        server: ServerConfig {
+            host: default_host(),
+            port: default_port(),
+            trust_forwarded_for: false,
+        },
+        database: DatabaseConfig {
+            url: "postgres://localhost/authdb".to_string(),
+        },
+        jwt: JwtConfig {
+            secret: "test-secret".to_string(),
+            expiration: default_jwt_expiration(),
+            refresh_expiration: default_refresh_expiration(),
+            algorithm: default_jwt_algorithm(),
+            key_id: None,
+            private_key_path: None,
+            public_key_path: None,
+            verification_keys: vec![],
+        },
+        smtp: SmtpConfig {
+            from_name: "noreply".to_string(),
+            from_email: "noreply@example.com".to_string(),
+            host: "localhost".to_string(),
+            port: 2525,
+            username: String::new(),
+            password: String::new(),
+            tls: false,
+        },
+        app: AppConfig {
+            verification_url: "http://localhost/verify".to_string(),
+            password_reset_url: "http://localhost/reset-password".to_string(),
+            email_change_url: "http://localhost/confirm-email".to_string(),
+            magic_link_url: "http://localhost/magic-link".to_string(),
+            invitation_url: "http://localhost/invitation".to_string(),
+        },
+        mfa: MfaConfig {
+            issuer: default_mfa_issuer(),
+            secret: "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_string(),
+        },
+        webauthn: WebauthnConfig {
+            rp_id: "localhost".to_string(),
+            rp_name: default_mfa_issuer(),
+            origin: "http://localhost:3000".to_string(),
+        },
+        oidc: OidcConfig {
+            issuer: "http://localhost:3000".to_string(),
+            login_url: "http://localhost/login".to_string(),
+            client_token_audience: "http://localhost:3000/api".to_string(),
+        },
+        federation: FederationConfig {
+            redirect_url: "http://localhost/login/callback".to_string(),
+            providers: vec![],
+        },
+        auth: AuthConfig::default(),
+        denylist: DenylistConfig::default(),
+        rate_limit: RateLimitConfig::default(),
+        tenants: vec![],
+    }
+}
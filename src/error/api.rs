@@ -1,20 +1,31 @@
 use crate::models::response::{ApiResponse, ErrorFieldDetail};
 use axum::extract::rejection::JsonRejection;
 use axum::response::{IntoResponse, Response};
-use http::StatusCode;
+use http::header::RETRY_AFTER;
+use http::{HeaderValue, StatusCode};
 #[allow(dead_code)]
 #[allow(unused_variables)]
 use std::fmt;
+use crate::error::api_key::ApiKeyError;
 use crate::error::authentication::AuthenticationError;
+use crate::error::federation::FederationError;
+use crate::error::oauth::OAuthError;
+use crate::error::organization::OrganizationError;
+use crate::error::role::RoleError;
 use crate::error::user::UserError;
+use validator::ValidationErrors;
 
 #[allow(dead_code)]
 #[allow(unused_variables)]
 pub enum ApiError {
     Conflict(String),
     Unauthorized(String),
+    Forbidden(String),
+    NotFound(String),
     BadRequest(String),
     InternalServerError(String),
+    TooManyRequests { message: String, retry_after: u64 },
+    Locked { message: String, retry_after: u64 },
     ValidationError {
         message: String,
         field_errors: Vec<(String, String)>,
@@ -25,10 +36,14 @@ pub enum ApiError {
 impl fmt::Display for ApiError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Conflict(msg) => write!(f, "{}", msg),
+            Self::Conflict(msg) => write!(f, "Conflict: {}", msg),
             Self::BadRequest(msg) => write!(f, "Bad request: {}", msg),
             Self::Unauthorized(msg) => write!(f, "Unauthorized: {}", msg),
+            Self::Forbidden(msg) => write!(f, "Forbidden: {}", msg),
+            Self::NotFound(msg) => write!(f, "Not found: {}", msg),
             Self::InternalServerError(msg) => write!(f, "Internal server error: {}", msg),
+            Self::TooManyRequests { message, .. } => write!(f, "Too many requests: {}", message),
+            Self::Locked { message, .. } => write!(f, "Locked: {}", message),
             Self::ValidationError { message, .. } => write!(f, "Validation error: {}", message),
             Self::JsonRejection(_) => write!(f, "Failed to retrieve json"),
         }
@@ -39,13 +54,18 @@ impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
         let status = self.status_code();
         let details = self.details();
+        let retry_after = self.retry_after();
         let error_response: ApiResponse<(), Vec<ErrorFieldDetail>> = ApiResponse {
             success: false,
             message: self.to_string(),
             data: None,
             error: Some(details),
         };
-        (status, error_response).into_response()
+        let mut response = (status, error_response).into_response();
+        if let Some(seconds) = retry_after {
+            response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response
     }
 }
 
@@ -54,13 +74,24 @@ impl ApiError {
         match self {
             ApiError::Conflict(_) => StatusCode::CONFLICT,
             ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
             ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
             ApiError::InternalServerError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Locked { .. } => StatusCode::LOCKED,
             ApiError::ValidationError { .. } => StatusCode::UNPROCESSABLE_ENTITY,
             ApiError::JsonRejection { .. } => StatusCode::BAD_REQUEST,
         }
     }
 
+    fn retry_after(&self) -> Option<u64> {
+        match self {
+            Self::TooManyRequests { retry_after, .. } | Self::Locked { retry_after, .. } => Some(*retry_after),
+            _ => None,
+        }
+    }
+
     fn details(&self) -> Vec<ErrorFieldDetail> {
         match self {
             Self::ValidationError { field_errors, .. } => field_errors
@@ -81,6 +112,30 @@ impl From<JsonRejection> for ApiError {
     }
 }
 
+impl From<ValidationErrors> for ApiError {
+    fn from(err: ValidationErrors) -> Self {
+        let field_errors = err
+            .field_errors()
+            .iter()
+            .map(|(field, errors)| {
+                let messages: Vec<String> = errors
+                    .iter()
+                    .map(|e| match &e.message {
+                        Some(message) => message.to_string(),
+                        None => e.code.to_string(),
+                    })
+                    .collect();
+                (field.to_string(), messages.join(", "))
+            })
+            .collect();
+
+        ApiError::ValidationError {
+            message: "Invalid input".to_string(),
+            field_errors,
+        }
+    }
+}
+
 impl From<AuthenticationError> for ApiError {
     fn from(error: AuthenticationError) -> Self {
         match error {
@@ -96,6 +151,65 @@ impl From<AuthenticationError> for ApiError {
             }
             AuthenticationError::InvalidToken => ApiError::BadRequest("Invalid token".to_string()),
             AuthenticationError::InvalidCredentials => ApiError::Unauthorized("Invalid credentials".to_string()),
+            AuthenticationError::AccountInactive => ApiError::Forbidden("Account is inactive".to_string()),
+            AuthenticationError::InvalidRefreshToken => {
+                ApiError::Unauthorized("Invalid refresh token".to_string())
+            }
+            AuthenticationError::InvalidMfaCode => {
+                ApiError::Unauthorized("Invalid MFA code".to_string())
+            }
+            AuthenticationError::MfaAlreadyEnabled => {
+                ApiError::Conflict("MFA is already enabled".to_string())
+            }
+            AuthenticationError::MfaNotEnrolled => {
+                ApiError::BadRequest("MFA enrollment has not been started".to_string())
+            }
+            AuthenticationError::PasskeyNotFound => ApiError::BadRequest("Passkey not found".to_string()),
+            AuthenticationError::SessionNotFound => ApiError::BadRequest("Session not found".to_string()),
+            AuthenticationError::LoginThrottled { retry_after, account_locked: true } => ApiError::Locked {
+                message: "Account is temporarily locked after too many failed logins".to_string(),
+                retry_after,
+            },
+            AuthenticationError::LoginThrottled { retry_after, account_locked: false } => {
+                ApiError::TooManyRequests {
+                    message: "Too many failed login attempts".to_string(),
+                    retry_after,
+                }
+            }
+        }
+    }
+}
+
+impl From<RoleError> for ApiError {
+    fn from(error: RoleError) -> Self {
+        match error {
+            RoleError::InternalServerError => ApiError::InternalServerError("Internal server error".to_string()),
+            RoleError::RoleAlreadyExists => ApiError::Conflict("Role already exists".to_string()),
+            RoleError::UserNotFound => ApiError::NotFound(error.to_string()),
+            other => ApiError::BadRequest(other.to_string()),
+        }
+    }
+}
+
+impl From<ApiKeyError> for ApiError {
+    fn from(error: ApiKeyError) -> Self {
+        match error {
+            ApiKeyError::InternalServerError => ApiError::InternalServerError("Internal server error".to_string()),
+            ApiKeyError::InvalidApiKey => ApiError::Unauthorized(error.to_string()),
+            other => ApiError::BadRequest(other.to_string()),
+        }
+    }
+}
+
+impl From<OrganizationError> for ApiError {
+    fn from(error: OrganizationError) -> Self {
+        match error {
+            OrganizationError::InternalServerError => ApiError::InternalServerError("Internal server error".to_string()),
+            OrganizationError::InsufficientRole | OrganizationError::InvitationForAnotherEmail => {
+                ApiError::Forbidden(error.to_string())
+            }
+            OrganizationError::LastOwner => ApiError::Conflict(error.to_string()),
+            other => ApiError::BadRequest(other.to_string()),
         }
     }
 }
@@ -110,6 +224,34 @@ impl From<UserError> for ApiError {
                 ApiError::InternalServerError("Internal server error".to_string())
             }
             UserError::UserNotFound(error) => ApiError::Unauthorized(error),
+            UserError::NotInTenant => ApiError::NotFound(err.to_string()),
+        }
+    }
+}
+
+impl From<OAuthError> for ApiError {
+    fn from(error: OAuthError) -> Self {
+        match error {
+            OAuthError::ServerError => ApiError::InternalServerError("Internal server error".to_string()),
+            OAuthError::InvalidClient => ApiError::Unauthorized("Invalid client".to_string()),
+            other => ApiError::BadRequest(other.description().unwrap_or_else(|| other.code().to_string())),
+        }
+    }
+}
+
+impl From<FederationError> for ApiError {
+    fn from(error: FederationError) -> Self {
+        match error {
+            FederationError::InternalServerError => {
+                ApiError::InternalServerError("Internal server error".to_string())
+            }
+            FederationError::AccountAlreadyExists => {
+                ApiError::Conflict("Account already exists".to_string())
+            }
+            FederationError::EmailNotVerified | FederationError::AccountInactive => {
+                ApiError::Unauthorized(error.to_string())
+            }
+            other => ApiError::BadRequest(other.to_string()),
         }
     }
 }
@@ -122,22 +264,6 @@ mod tests {
     use http::StatusCode;
     use serde_json::{Value, json};
 
-    // Dummy implementation of ErrorFieldDetail if needed
-    #[derive(Debug, serde::Serialize, PartialEq)]
-    struct ErrorFieldDetail {
-        field: String,
-        message: String,
-    }
-
-    // Dummy implementation of ApiResponse if needed
-    #[derive(Debug, serde::Serialize)]
-    struct ApiResponse<T, E> {
-        success: bool,
-        message: String,
-        data: Option<T>,
-        error: Option<E>,
-    }
-
     #[tokio::test]
     async fn test_conflict_error_response() {
         let err = ApiError::Conflict("Item already exists".into());
@@ -179,6 +305,22 @@ mod tests {
         assert_eq!(json["error"], expected_errors);
     }
 
+    #[test]
+    fn test_from_validation_errors_falls_back_to_code() {
+        let mut errors = ValidationErrors::new();
+        errors.add("password", validator::ValidationError::new("password_too_short"));
+
+        match ApiError::from(errors) {
+            ApiError::ValidationError { field_errors, .. } => {
+                assert_eq!(
+                    field_errors,
+                    vec![("password".to_string(), "password_too_short".to_string())]
+                );
+            }
+            _ => panic!("expected a validation error"),
+        }
+    }
+
     #[tokio::test]
     async fn test_internal_server_error_response() {
         let err = ApiError::InternalServerError("Something went wrong".into());
@@ -220,4 +362,25 @@ mod tests {
         assert_eq!(json["success"], false);
         assert_eq!(json["message"], "Unauthorized: Login required");
     }
+
+    #[tokio::test]
+    async fn test_locked_account_response_has_retry_after() {
+        let err: ApiError = AuthenticationError::LoginThrottled { retry_after: 900, account_locked: true }.into();
+        let response = err.into_response();
+
+        assert_eq!(response.status(), StatusCode::LOCKED);
+        assert_eq!(response.headers()[RETRY_AFTER], "900");
+        let body = to_bytes(response.into_body(), 200).await.unwrap();
+        let json: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["success"], false);
+    }
+
+    #[tokio::test]
+    async fn test_login_backoff_response_is_too_many_requests() {
+        let err: ApiError = AuthenticationError::LoginThrottled { retry_after: 4, account_locked: false }.into();
+        let response = err.into_response();
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[RETRY_AFTER], "4");
+    }
 }
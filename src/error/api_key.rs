@@ -0,0 +1,16 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ApiKeyError {
+    #[error("Internal server error")]
+    InternalServerError,
+
+    #[error("Invalid API key")]
+    InvalidApiKey,
+
+    #[error("API key not found")]
+    ApiKeyNotFound,
+
+    #[error("Unknown scope {0}")]
+    UnknownScope(String),
+}
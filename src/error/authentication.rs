@@ -1,7 +1,6 @@
 #[allow(dead_code)]
 #[allow(unused_variables)]
 use thiserror::Error;
-use crate::error::api::ApiError;
 
 #[allow(dead_code)]
 #[allow(unused_variables)]
@@ -21,4 +20,28 @@ pub enum AuthenticationError {
 
     #[error("Invalid Credentials")]
     InvalidCredentials,
+
+    #[error("Account is inactive")]
+    AccountInactive,
+
+    #[error("Invalid refresh token")]
+    InvalidRefreshToken,
+
+    #[error("Invalid MFA code")]
+    InvalidMfaCode,
+
+    #[error("MFA is already enabled")]
+    MfaAlreadyEnabled,
+
+    #[error("MFA enrollment has not been started")]
+    MfaNotEnrolled,
+
+    #[error("Passkey not found")]
+    PasskeyNotFound,
+
+    #[error("Session not found")]
+    SessionNotFound,
+
+    #[error("Too many failed login attempts")]
+    LoginThrottled { retry_after: u64, account_locked: bool },
 }
\ No newline at end of file
@@ -0,0 +1,25 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum FederationError {
+    #[error("Unknown identity provider")]
+    UnknownProvider,
+
+    #[error("Invalid or expired state")]
+    InvalidState,
+
+    #[error("Identity provider error: {0}")]
+    Provider(String),
+
+    #[error("The identity provider has not verified this email address")]
+    EmailNotVerified,
+
+    #[error("Account is inactive")]
+    AccountInactive,
+
+    #[error("Email or username is already registered")]
+    AccountAlreadyExists,
+
+    #[error("Internal server error")]
+    InternalServerError,
+}
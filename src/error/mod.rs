@@ -1,4 +1,9 @@
 pub mod api;
+pub mod api_key;
 pub mod authentication;
 pub mod email;
+pub mod federation;
+pub mod oauth;
+pub mod organization;
+pub mod role;
 pub mod user;
@@ -0,0 +1,98 @@
+use crate::error::authentication::AuthenticationError;
+use crate::error::user::UserError;
+use axum::Json;
+use axum::response::{IntoResponse, Response};
+use http::StatusCode;
+use http::header::WWW_AUTHENTICATE;
+use serde::Serialize;
+
+/// Errors of the OAuth 2.0 endpoints, rendered as RFC 6749 section 5.2 bodies
+/// rather than the `ApiResponse` envelope so standard clients understand them.
+#[derive(Debug)]
+pub enum OAuthError {
+    InvalidRequest(String),
+    InvalidClient,
+    InvalidGrant(String),
+    UnauthorizedClient,
+    UnsupportedGrantType,
+    UnsupportedResponseType,
+    InvalidScope(String),
+    ServerError,
+}
+
+#[derive(Serialize)]
+struct OAuthErrorBody {
+    error: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_description: Option<String>,
+}
+
+impl OAuthError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            OAuthError::InvalidRequest(_) => "invalid_request",
+            OAuthError::InvalidClient => "invalid_client",
+            OAuthError::InvalidGrant(_) => "invalid_grant",
+            OAuthError::UnauthorizedClient => "unauthorized_client",
+            OAuthError::UnsupportedGrantType => "unsupported_grant_type",
+            OAuthError::UnsupportedResponseType => "unsupported_response_type",
+            OAuthError::InvalidScope(_) => "invalid_scope",
+            OAuthError::ServerError => "server_error",
+        }
+    }
+
+    pub fn description(&self) -> Option<String> {
+        match self {
+            OAuthError::InvalidRequest(msg) | OAuthError::InvalidGrant(msg) | OAuthError::InvalidScope(msg) => {
+                Some(msg.clone())
+            }
+            _ => None,
+        }
+    }
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            OAuthError::InvalidClient => StatusCode::UNAUTHORIZED,
+            OAuthError::ServerError => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl IntoResponse for OAuthError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        let body = Json(OAuthErrorBody {
+            error: self.code(),
+            error_description: self.description(),
+        });
+
+        match self {
+            OAuthError::InvalidClient => {
+                (status, [(WWW_AUTHENTICATE, "Basic")], body).into_response()
+            }
+            _ => (status, body).into_response(),
+        }
+    }
+}
+
+impl From<AuthenticationError> for OAuthError {
+    fn from(error: AuthenticationError) -> Self {
+        match error {
+            AuthenticationError::InternalServerError => OAuthError::ServerError,
+            AuthenticationError::InvalidRefreshToken => {
+                OAuthError::InvalidGrant("Invalid refresh token".to_string())
+            }
+            other => OAuthError::InvalidGrant(other.to_string()),
+        }
+    }
+}
+
+impl From<UserError> for OAuthError {
+    fn from(error: UserError) -> Self {
+        match error {
+            UserError::UserNotFound(_) => OAuthError::InvalidGrant("User not found".to_string()),
+            _ => OAuthError::ServerError,
+        }
+    }
+}
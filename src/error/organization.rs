@@ -0,0 +1,29 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum OrganizationError {
+    #[error("Internal server error")]
+    InternalServerError,
+
+    /// Also returned to users who are not members, so as not to reveal the organization.
+    #[error("Organization not found")]
+    OrganizationNotFound,
+
+    #[error("Your role in the organization does not allow this")]
+    InsufficientRole,
+
+    #[error("Member not found")]
+    MemberNotFound,
+
+    #[error("An organization needs at least one owner")]
+    LastOwner,
+
+    #[error("Invitation not found")]
+    InvitationNotFound,
+
+    #[error("Invitation is invalid or has expired")]
+    InvalidInvitation,
+
+    #[error("Invitation was sent to another email address")]
+    InvitationForAnotherEmail,
+}
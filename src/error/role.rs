@@ -0,0 +1,22 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum RoleError {
+    #[error("Internal server error")]
+    InternalServerError,
+
+    #[error("Role already exists")]
+    RoleAlreadyExists,
+
+    #[error("Role not found")]
+    RoleNotFound,
+
+    #[error("Role is not assigned to the user")]
+    RoleNotAssigned,
+
+    #[error("Unknown permission {0}")]
+    UnknownPermission(String),
+
+    #[error("User not found")]
+    UserNotFound,
+}
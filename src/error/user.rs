@@ -1,4 +1,3 @@
-use crate::error::api::ApiError;
 use thiserror::Error;
 
 #[allow(dead_code)]
@@ -13,4 +12,7 @@ pub enum UserError {
 
     #[error("User not found: {0}")]
     UserNotFound(String),
-}
\ No newline at end of file
+
+    #[error("User not found")]
+    NotInTenant,
+}
@@ -0,0 +1,97 @@
+use crate::app_state::AppState;
+use axum::extract::{ConnectInfo, FromRequestParts};
+use http::header::USER_AGENT;
+use http::request::Parts;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// Where a request comes from, recorded with the sessions it starts.
+#[derive(Debug, Default)]
+pub struct ClientContext {
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+impl FromRequestParts<Arc<AppState>> for ClientContext {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let user_agent = parts
+            .headers
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        // The closest proxy appends the address it saw, so the last entry is the one it vouches for.
+        let forwarded_for = state
+            .config
+            .server
+            .trust_forwarded_for
+            .then(|| parts.headers.get("x-forwarded-for"))
+            .flatten()
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .map(|ip| ip.trim().to_string());
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip().to_string());
+
+        Ok(ClientContext {
+            user_agent,
+            ip_address: forwarded_for.or(peer),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state::test_state;
+    use crate::config::test_config;
+    use http::Request;
+
+    async fn extract(state: AppState, request: Request<()>) -> ClientContext {
+        let (mut parts, _) = request.into_parts();
+        ClientContext::from_request_parts(&mut parts, &Arc::new(state))
+            .await
+            .unwrap()
+    }
+
+    fn request() -> Request<()> {
+        let mut request = Request::builder()
+            .header(USER_AGENT, "curl/8.5.0")
+            .header("x-forwarded-for", "203.0.113.7, 198.51.100.2")
+            .body(())
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 54321))));
+        request
+    }
+
+    #[tokio::test]
+    async fn test_peer_address_is_used_by_default() {
+        let context = extract(test_state(), request()).await;
+
+        assert_eq!(context.user_agent.as_deref(), Some("curl/8.5.0"));
+        assert_eq!(context.ip_address.as_deref(), Some("10.0.0.1"));
+    }
+
+    #[tokio::test]
+    async fn test_forwarded_for_is_used_when_trusted() {
+        let mut config = test_config();
+        config.server.trust_forwarded_for = true;
+        let state = AppState {
+            config: Arc::new(config),
+            ..test_state()
+        };
+
+        let context = extract(state, request()).await;
+        assert_eq!(context.ip_address.as_deref(), Some("198.51.100.2"));
+    }
+}
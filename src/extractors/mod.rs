@@ -1 +1,4 @@
+pub mod auth_user;
+pub mod client_context;
 pub mod payload_json;
+pub mod tenant;
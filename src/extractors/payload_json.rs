@@ -7,6 +7,7 @@ use axum::response::{IntoResponse, Response};
 #[from_request(via(axum::Json), rejection(ApiError))]
 pub struct PayloadJson<T>(pub T);
 
+#[allow(dead_code)]
 pub enum JsonError {
     // The request body contained invalid JSON
     JsonRejection(JsonRejection),
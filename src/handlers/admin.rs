@@ -0,0 +1,245 @@
+use crate::app_state::AppState;
+use crate::error::api::ApiError;
+use crate::extractors::payload_json::PayloadJson;
+use crate::extractors::tenant::Tenant;
+use crate::models::oauth::{OAuthClient, RegisteredClient};
+use crate::models::request::{CreateOAuthClient, CreateRole, ListUsers};
+use crate::models::response::SuccessResponse;
+use crate::models::role::{Permission, Role};
+use crate::models::user::{User, UserPage};
+use crate::utils::security::generate_token;
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{Path, Query, State};
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+pub async fn create_oauth_client(
+    State(state): State<Arc<AppState>>,
+    PayloadJson(payload): PayloadJson<CreateOAuthClient>,
+) -> Result<SuccessResponse<RegisteredClient>, ApiError> {
+    payload.validate()?;
+
+    let client = state.services.oauth_service.create_client(&payload).await?;
+
+    Ok(SuccessResponse {
+        message: "Client registered, the secret is only shown once".to_string(),
+        data: Some(client),
+    })
+}
+
+pub async fn list_oauth_clients(
+    State(state): State<Arc<AppState>>,
+) -> Result<SuccessResponse<Vec<OAuthClient>>, ApiError> {
+    let clients = state.services.oauth_service.list_clients().await?;
+
+    Ok(SuccessResponse {
+        message: "Clients retrieved".to_string(),
+        data: Some(clients),
+    })
+}
+
+pub async fn revoke_oauth_client(
+    State(state): State<Arc<AppState>>,
+    Path(client_id): Path<String>,
+) -> Result<SuccessResponse<()>, ApiError> {
+    state.services.oauth_service.revoke_client(&client_id).await?;
+
+    Ok(SuccessResponse {
+        message: "Client revoked".to_string(),
+        data: None,
+    })
+}
+
+pub async fn list_roles(State(state): State<Arc<AppState>>) -> Result<SuccessResponse<Vec<Role>>, ApiError> {
+    let roles = state.services.role_service.list_roles().await?;
+
+    Ok(SuccessResponse {
+        message: "Roles retrieved".to_string(),
+        data: Some(roles),
+    })
+}
+
+pub async fn create_role(
+    State(state): State<Arc<AppState>>,
+    PayloadJson(payload): PayloadJson<CreateRole>,
+) -> Result<SuccessResponse<Role>, ApiError> {
+    payload.validate()?;
+
+    let role = state.services.role_service.create_role(&payload).await?;
+
+    Ok(SuccessResponse {
+        message: "Role created".to_string(),
+        data: Some(role),
+    })
+}
+
+pub async fn list_permissions(
+    State(state): State<Arc<AppState>>,
+) -> Result<SuccessResponse<Vec<Permission>>, ApiError> {
+    let permissions = state.services.role_service.list_permissions().await?;
+
+    Ok(SuccessResponse {
+        message: "Permissions retrieved".to_string(),
+        data: Some(permissions),
+    })
+}
+
+pub async fn list_user_roles(
+    State(state): State<Arc<AppState>>,
+    Tenant(tenant): Tenant,
+    Path(user_id): Path<Uuid>,
+) -> Result<SuccessResponse<Vec<Role>>, ApiError> {
+    let roles = state.services.role_service.user_roles(&tenant, &user_id).await?;
+
+    Ok(SuccessResponse {
+        message: "Roles retrieved".to_string(),
+        data: Some(roles),
+    })
+}
+
+/// Grants a role; tokens carry it from the user's next login or refresh.
+pub async fn assign_role(
+    State(state): State<Arc<AppState>>,
+    Tenant(tenant): Tenant,
+    Path((user_id, role)): Path<(Uuid, String)>,
+) -> Result<SuccessResponse<()>, ApiError> {
+    state.services.role_service.assign_role(&tenant, &user_id, &role).await?;
+
+    Ok(SuccessResponse {
+        message: "Role assigned".to_string(),
+        data: None,
+    })
+}
+
+/// Takes a role away; tokens already issued keep it until they expire.
+pub async fn remove_role(
+    State(state): State<Arc<AppState>>,
+    Tenant(tenant): Tenant,
+    Path((user_id, role)): Path<(Uuid, String)>,
+) -> Result<SuccessResponse<()>, ApiError> {
+    state.services.role_service.remove_role(&tenant, &user_id, &role).await?;
+
+    Ok(SuccessResponse {
+        message: "Role removed".to_string(),
+        data: None,
+    })
+}
+
+/// Lists the users of the tenant the request is served as.
+pub async fn list_users(
+    State(state): State<Arc<AppState>>,
+    Tenant(tenant): Tenant,
+    query: Result<Query<ListUsers>, QueryRejection>,
+) -> Result<SuccessResponse<UserPage>, ApiError> {
+    let Query(query) = query.map_err(|e| ApiError::BadRequest(e.body_text()))?;
+    query.validate()?;
+
+    let search = query.search.as_deref().map(str::trim).filter(|search| !search.is_empty());
+    let page = state
+        .services
+        .user_service
+        .list_users(&tenant, search, query.page, query.per_page)
+        .await?;
+
+    Ok(SuccessResponse {
+        message: "Users retrieved".to_string(),
+        data: Some(page),
+    })
+}
+
+pub async fn get_user(
+    State(state): State<Arc<AppState>>,
+    Tenant(tenant): Tenant,
+    Path(user_id): Path<Uuid>,
+) -> Result<SuccessResponse<User>, ApiError> {
+    let user = state.services.user_service.get_tenant_user(&tenant, &user_id).await?;
+
+    Ok(SuccessResponse {
+        message: "User retrieved".to_string(),
+        data: Some(user),
+    })
+}
+
+pub async fn activate_user(
+    State(state): State<Arc<AppState>>,
+    Tenant(tenant): Tenant,
+    Path(user_id): Path<Uuid>,
+) -> Result<SuccessResponse<User>, ApiError> {
+    let user = state.services.user_service.set_active(&tenant, &user_id, true).await?;
+
+    Ok(SuccessResponse {
+        message: "User activated".to_string(),
+        data: Some(user),
+    })
+}
+
+/// Blocks the account from logging in and ends its sessions.
+pub async fn deactivate_user(
+    State(state): State<Arc<AppState>>,
+    Tenant(tenant): Tenant,
+    Path(user_id): Path<Uuid>,
+) -> Result<SuccessResponse<User>, ApiError> {
+    let user = state.services.user_service.set_active(&tenant, &user_id, false).await?;
+    state.services.auth_service.revoke_user_sessions(&user_id).await?;
+
+    Ok(SuccessResponse {
+        message: "User deactivated".to_string(),
+        data: Some(user),
+    })
+}
+
+pub async fn verify_user(
+    State(state): State<Arc<AppState>>,
+    Tenant(tenant): Tenant,
+    Path(user_id): Path<Uuid>,
+) -> Result<SuccessResponse<User>, ApiError> {
+    let user = state.services.user_service.mark_verified(&tenant, &user_id).await?;
+
+    Ok(SuccessResponse {
+        message: "User verified".to_string(),
+        data: Some(user),
+    })
+}
+
+/// Replaces the password with a random one, ends every session and emails
+/// the user a link to choose a new password.
+pub async fn reset_user_password(
+    State(state): State<Arc<AppState>>,
+    Tenant(tenant): Tenant,
+    Path(user_id): Path<Uuid>,
+) -> Result<SuccessResponse<()>, ApiError> {
+    let user = state.services.user_service.get_tenant_user(&tenant, &user_id).await?;
+    state
+        .services
+        .user_service
+        .update_password(&user_id, &generate_token())
+        .await?;
+    state.services.auth_service.revoke_user_sessions(&user_id).await?;
+    state
+        .services
+        .auth_service
+        .send_password_reset_token(&state.services.email_service, &user)
+        .await?;
+
+    Ok(SuccessResponse {
+        message: "Password reset, a reset link has been sent to the user".to_string(),
+        data: None,
+    })
+}
+
+pub async fn delete_user(
+    State(state): State<Arc<AppState>>,
+    Tenant(tenant): Tenant,
+    Path(user_id): Path<Uuid>,
+) -> Result<SuccessResponse<()>, ApiError> {
+    let user = state.services.user_service.get_tenant_user(&tenant, &user_id).await?;
+    // Deny the user's access tokens before the sessions go with the account.
+    state.services.auth_service.revoke_user_sessions(&user.id).await?;
+    state.services.user_service.delete_user(&tenant, &user_id).await?;
+
+    Ok(SuccessResponse {
+        message: "User deleted".to_string(),
+        data: None,
+    })
+}
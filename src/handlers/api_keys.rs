@@ -0,0 +1,50 @@
+use crate::app_state::AppState;
+use crate::error::api::ApiError;
+use crate::extractors::auth_user::AuthSession;
+use crate::extractors::payload_json::PayloadJson;
+use crate::models::api_key::{ApiKey, CreatedApiKey};
+use crate::models::request::CreateApiKey;
+use crate::models::response::SuccessResponse;
+use axum::extract::{Path, State};
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+pub async fn list_api_keys(
+    State(state): State<Arc<AppState>>,
+    AuthSession { user, .. }: AuthSession,
+) -> Result<SuccessResponse<Vec<ApiKey>>, ApiError> {
+    let api_keys = state.services.api_key_service.list_api_keys(&user.id).await?;
+
+    Ok(SuccessResponse {
+        message: "API keys retrieved".to_string(),
+        data: Some(api_keys),
+    })
+}
+
+pub async fn create_api_key(
+    State(state): State<Arc<AppState>>,
+    AuthSession { user, .. }: AuthSession,
+    PayloadJson(payload): PayloadJson<CreateApiKey>,
+) -> Result<SuccessResponse<CreatedApiKey>, ApiError> {
+    payload.validate()?;
+    let api_key = state.services.api_key_service.create_api_key(&user.id, &payload).await?;
+
+    Ok(SuccessResponse {
+        message: "API key created, store it now as it will not be shown again".to_string(),
+        data: Some(api_key),
+    })
+}
+
+pub async fn revoke_api_key(
+    State(state): State<Arc<AppState>>,
+    AuthSession { user, .. }: AuthSession,
+    Path(api_key_id): Path<Uuid>,
+) -> Result<SuccessResponse<()>, ApiError> {
+    state.services.api_key_service.revoke_api_key(&user.id, &api_key_id).await?;
+
+    Ok(SuccessResponse {
+        message: "API key revoked".to_string(),
+        data: None,
+    })
+}
@@ -1,44 +1,36 @@
 use crate::app_state::AppState;
 use crate::error::api::ApiError;
+use crate::extractors::auth_user::AuthSession;
+use crate::extractors::client_context::ClientContext;
 use crate::extractors::payload_json::PayloadJson;
-use crate::models::request::{Login, RegisterUser, ResendToken, Token};
+use crate::extractors::tenant::Tenant;
+use crate::error::authentication::AuthenticationError;
+use crate::error::user::UserError;
+use crate::models::request::{
+    EmailCodeLogin, EmailLoginRequest, ForgotPassword, Login, MfaLogin, Refresh, RegisterUser,
+    ResendToken, ResetPassword, Token,
+};
 use crate::models::response::SuccessResponse;
+use crate::models::user::User;
 use axum::extract::State;
-use axum::response::IntoResponse;
-use axum::Json;
-use serde_json::json;
+use std::future::Future;
 use std::sync::Arc;
+use tracing::{error, warn};
 use validator::Validate;
-use crate::models::authenticate::JwtToken;
+use crate::models::authenticate::{JwtToken, LoginResponse, TokenPurpose};
 
 pub async fn register_user(
     State(state): State<Arc<AppState>>,
+    Tenant(tenant): Tenant,
     PayloadJson(payload): PayloadJson<RegisterUser>,
 ) -> Result<SuccessResponse<()>, ApiError> {
-    if let Err(err) = payload.validate() {
-        let mut errors_map = vec![];
+    payload.validate()?;
+    let user = state.services.user_service.create_user(&tenant, payload).await?;
 
-        for (field, errors) in err.field_errors() {
-            let messages: Vec<String> = errors
-                .iter()
-                .filter_map(|e| e.message.as_ref())
-                .map(|m| m.to_string())
-                .collect();
-
-            errors_map.push((field.to_string(), messages.join(", ")));
-        }
-
-        return Err(ApiError::ValidationError {
-            message: "Invalid input".to_string(),
-            field_errors: errors_map,
-        });
-    }
-    let user = state.services.user_service.create_user(payload).await?;
-
-    let _ = state
+    state
         .services
         .auth_service
-        .send_activation_token(&state.services.email_service, user.id)
+        .send_activation_token(&state.services.email_service, &user)
         .await?;
     Ok(SuccessResponse{
         data: None,
@@ -64,12 +56,12 @@ pub async fn resend_token(
     State(state): State<Arc<AppState>>,
     PayloadJson(payload): PayloadJson<ResendToken>,
 ) -> Result<SuccessResponse<()>, ApiError> {
-    let user_id = payload.user_id;
+    let user = state.services.user_service.get_user_by_id(&payload.user_id).await?;
 
     state
         .services
         .auth_service
-        .resend_activation_token(&state.services.email_service, &user_id)
+        .resend_activation_token(&state.services.email_service, &user)
         .await?;
     Ok(SuccessResponse {
         data: None,
@@ -80,17 +72,297 @@ pub async fn resend_token(
 
 pub async fn login(
     State(state): State<Arc<AppState>>,
+    Tenant(tenant): Tenant,
+    client: ClientContext,
     PayloadJson(payload): PayloadJson<Login>,
-) -> Result<SuccessResponse<JwtToken>, ApiError> {
+) -> Result<SuccessResponse<LoginResponse>, ApiError> {
+    let auth_service = &state.services.auth_service;
     let identity = payload.identity;
-    let user = state.services.user_service.get_user_by_email_or_username(&identity).await?;
+    let user = match state.services.user_service.get_user_by_email_or_username(&tenant, &identity).await {
+        Ok(user) => user,
+        Err(e) => {
+            auth_service.check_login_throttle(None, &client).await?;
+            auth_service.record_failed_login(None, &client).await?;
+            return Err(e.into());
+        }
+    };
+
+    let response = auth_service.login(user, payload.password, &client).await?;
+    let message = match response {
+        LoginResponse::Token(_) => "Login success",
+        LoginResponse::MfaRequired(_) => "MFA required",
+    };
+
+    Ok(SuccessResponse {
+        message: message.to_string(),
+        data: Some(response),
+    })
+}
+
+pub async fn request_email_code(
+    State(state): State<Arc<AppState>>,
+    Tenant(tenant): Tenant,
+    PayloadJson(payload): PayloadJson<EmailLoginRequest>,
+) -> Result<SuccessResponse<()>, ApiError> {
+    payload.validate()?;
+
+    email_account_in_background(state, tenant, payload.email, |state, user| async move {
+        state
+            .services
+            .auth_service
+            .send_email_login_code(&state.services.email_service, &user)
+            .await
+    });
 
-    let token = state.services.auth_service.login(user, identity).await?;
+    Ok(SuccessResponse {
+        data: None,
+        message: "If the account exists, a sign-in code has been sent".to_string(),
+    })
+}
+
+pub async fn login_email_code(
+    State(state): State<Arc<AppState>>,
+    Tenant(tenant): Tenant,
+    client: ClientContext,
+    PayloadJson(payload): PayloadJson<EmailCodeLogin>,
+) -> Result<SuccessResponse<LoginResponse>, ApiError> {
+    payload.validate()?;
+
+    let auth_service = &state.services.auth_service;
+    let user = match state.services.user_service.get_user_by_email(&tenant, &payload.email).await {
+        Ok(user) => user,
+        Err(UserError::UserNotFound(_)) => {
+            auth_service.check_login_throttle(None, &client).await?;
+            auth_service.record_failed_login(None, &client).await?;
+            return Err(AuthenticationError::InvalidCredentials.into());
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let response = auth_service.login_with_email_code(&user, &payload.code, &client).await?;
+    let message = match response {
+        LoginResponse::Token(_) => "Login success",
+        LoginResponse::MfaRequired(_) => "MFA required",
+    };
+
+    Ok(SuccessResponse {
+        message: message.to_string(),
+        data: Some(response),
+    })
+}
+
+pub async fn request_magic_link(
+    State(state): State<Arc<AppState>>,
+    Tenant(tenant): Tenant,
+    PayloadJson(payload): PayloadJson<EmailLoginRequest>,
+) -> Result<SuccessResponse<()>, ApiError> {
+    payload.validate()?;
+
+    email_account_in_background(state, tenant, payload.email, |state, user| async move {
+        state
+            .services
+            .auth_service
+            .send_magic_link(&state.services.email_service, &user)
+            .await
+    });
+
+    Ok(SuccessResponse {
+        data: None,
+        message: "If the account exists, a sign-in link has been sent".to_string(),
+    })
+}
+
+pub async fn login_magic_link(
+    State(state): State<Arc<AppState>>,
+    client: ClientContext,
+    PayloadJson(payload): PayloadJson<Token>,
+) -> Result<SuccessResponse<LoginResponse>, ApiError> {
+    let user_id = state
+        .services
+        .auth_service
+        .consume_verification_token(&payload.token, TokenPurpose::MagicLink)
+        .await?;
+    let user = state.services.user_service.get_user_by_id(&user_id).await?;
+
+    let response = state.services.auth_service.complete_login(&user, &client).await?;
+    let message = match response {
+        LoginResponse::Token(_) => "Login success",
+        LoginResponse::MfaRequired(_) => "MFA required",
+    };
+
+    Ok(SuccessResponse {
+        message: message.to_string(),
+        data: Some(response),
+    })
+}
+
+pub async fn login_mfa(
+    State(state): State<Arc<AppState>>,
+    client: ClientContext,
+    PayloadJson(payload): PayloadJson<MfaLogin>,
+) -> Result<SuccessResponse<JwtToken>, ApiError> {
+    let challenge = state
+        .services
+        .auth_service
+        .find_mfa_challenge(&payload.challenge_token)
+        .await?;
+    let user = state.services.user_service.get_user_by_id(&challenge.user_id).await?;
+
+    let verified = match (&payload.code, &payload.recovery_code) {
+        (Some(code), _) => state.services.mfa_service.verify_totp(&user, code).await,
+        (None, Some(code)) => state.services.mfa_service.verify_recovery_code(&user, code).await,
+        (None, None) => {
+            return Err(ApiError::ValidationError {
+                message: "Invalid input".to_string(),
+                field_errors: vec![("code".to_string(), "code or recovery_code is required".to_string())],
+            });
+        }
+    };
+    if let Err(err) = verified {
+        state
+            .services
+            .auth_service
+            .record_failed_mfa_attempt(&challenge.id)
+            .await?;
+        return Err(err.into());
+    }
+
+    state
+        .services
+        .auth_service
+        .complete_mfa_challenge(&challenge.id)
+        .await?;
+    let token = state
+        .services
+        .auth_service
+        .start_session(&user, &client, None)
+        .await?;
 
     Ok(SuccessResponse {
         message: "Login success".to_string(),
-        data: Some(JwtToken {
-            token
-        })
+        data: Some(token),
     })
 }
+
+pub async fn refresh_token(
+    State(state): State<Arc<AppState>>,
+    PayloadJson(payload): PayloadJson<Refresh>,
+) -> Result<SuccessResponse<JwtToken>, ApiError> {
+    let refresh_token = state
+        .services
+        .auth_service
+        .consume_refresh_token(&payload.refresh_token, None)
+        .await?;
+    let user = state.services.user_service.get_user_by_id(&refresh_token.user_id).await?;
+
+    let token = state
+        .services
+        .auth_service
+        .refresh_session(&user, refresh_token.family_id)
+        .await?;
+
+    Ok(SuccessResponse {
+        message: "Token refreshed".to_string(),
+        data: Some(token),
+    })
+}
+
+/// Ends the session of the access token used, so that neither it nor the
+/// session's refresh token is accepted anymore.
+pub async fn logout(
+    State(state): State<Arc<AppState>>,
+    AuthSession { user, session_id, .. }: AuthSession,
+) -> Result<SuccessResponse<()>, ApiError> {
+    state
+        .services
+        .auth_service
+        .revoke_session(&user.id, &session_id)
+        .await?;
+
+    Ok(SuccessResponse {
+        message: "Logged out".to_string(),
+        data: None,
+    })
+}
+
+pub async fn forgot_password(
+    State(state): State<Arc<AppState>>,
+    Tenant(tenant): Tenant,
+    PayloadJson(payload): PayloadJson<ForgotPassword>,
+) -> Result<SuccessResponse<()>, ApiError> {
+    payload.validate()?;
+
+    email_account_in_background(state, tenant, payload.email, |state, user| async move {
+        state
+            .services
+            .auth_service
+            .send_password_reset_token(&state.services.email_service, &user)
+            .await
+    });
+
+    Ok(SuccessResponse {
+        data: None,
+        message: "If the account exists, a password reset link has been sent".to_string(),
+    })
+}
+
+pub async fn reset_password(
+    State(state): State<Arc<AppState>>,
+    PayloadJson(payload): PayloadJson<ResetPassword>,
+) -> Result<SuccessResponse<()>, ApiError> {
+    payload.validate()?;
+
+    state
+        .services
+        .auth_service
+        .reset_password(&payload.token, &payload.password)
+        .await?;
+
+    Ok(SuccessResponse {
+        data: None,
+        message: "Password has been reset".to_string(),
+    })
+}
+
+pub async fn confirm_email_change(
+    State(state): State<Arc<AppState>>,
+    PayloadJson(payload): PayloadJson<Token>,
+) -> Result<SuccessResponse<()>, ApiError> {
+    let user_id = state
+        .services
+        .auth_service
+        .consume_verification_token(&payload.token, TokenPurpose::EmailChange)
+        .await?;
+    state.services.user_service.confirm_pending_email(&user_id).await?;
+
+    Ok(SuccessResponse {
+        data: None,
+        message: "Email changed".to_string(),
+    })
+}
+
+/// Looks up the account of `email` and sends it an email off the request path,
+/// so that the response takes as long whether or not the account exists.
+fn email_account_in_background<F, Fut>(state: Arc<AppState>, tenant: String, email: String, send: F)
+where
+    F: FnOnce(Arc<AppState>, User) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<(), AuthenticationError>> + Send + 'static,
+{
+    // Dropped rather than queued when too many are pending, whether or not the account exists.
+    let Some(permit) = state.services.email_service.try_reserve_background() else {
+        warn!("Too many pending emails, dropping one requested for {}", email);
+        return;
+    };
+    tokio::spawn(async move {
+        let _permit = permit;
+        let user = match state.services.user_service.get_user_by_email(&tenant, &email).await {
+            Ok(user) => user,
+            // Lookup failures other than an unknown address are logged by the service.
+            Err(_) => return,
+        };
+        let user_id = user.id;
+        if let Err(e) = send(state, user).await {
+            error!("Failed to email user {}: {}", user_id, e);
+        }
+    });
+}
@@ -0,0 +1,61 @@
+use crate::app_state::AppState;
+use crate::error::api::ApiError;
+use crate::extractors::client_context::ClientContext;
+use crate::extractors::payload_json::PayloadJson;
+use crate::extractors::tenant::Tenant;
+use crate::models::authenticate::LoginResponse;
+use crate::models::request::FederatedCallback;
+use crate::models::response::SuccessResponse;
+use axum::extract::{Path, State};
+use axum::response::Redirect;
+use std::sync::Arc;
+use validator::Validate;
+
+pub async fn list_providers(
+    State(state): State<Arc<AppState>>,
+) -> Result<SuccessResponse<Vec<String>>, ApiError> {
+    Ok(SuccessResponse {
+        message: "Identity providers retrieved".to_string(),
+        data: Some(state.services.federation_service.provider_names()),
+    })
+}
+
+/// Sends the browser to the identity provider, which returns it to the
+/// configured redirect URL.
+pub async fn begin_login(
+    State(state): State<Arc<AppState>>,
+    Tenant(tenant): Tenant,
+    Path(provider): Path<String>,
+) -> Result<Redirect, ApiError> {
+    let authorization_url = state
+        .services
+        .federation_service
+        .begin_login(&tenant, &provider)
+        .await?;
+    Ok(Redirect::to(&authorization_url))
+}
+
+pub async fn login_callback(
+    State(state): State<Arc<AppState>>,
+    client: ClientContext,
+    PayloadJson(payload): PayloadJson<FederatedCallback>,
+) -> Result<SuccessResponse<LoginResponse>, ApiError> {
+    payload.validate()?;
+
+    let user = state
+        .services
+        .federation_service
+        .finish_login(&payload.state, &payload.code)
+        .await?;
+
+    let response = state.services.auth_service.complete_login(&user, &client).await?;
+    let message = match response {
+        LoginResponse::Token(_) => "Login success",
+        LoginResponse::MfaRequired(_) => "MFA required",
+    };
+
+    Ok(SuccessResponse {
+        message: message.to_string(),
+        data: Some(response),
+    })
+}
@@ -0,0 +1,54 @@
+use crate::app_state::AppState;
+use crate::error::api::ApiError;
+use crate::extractors::auth_user::AuthSession;
+use crate::extractors::payload_json::PayloadJson;
+use crate::models::authenticate::{RecoveryCodes, TotpEnrollment};
+use crate::models::request::TotpCode;
+use crate::models::response::SuccessResponse;
+use axum::extract::State;
+use std::sync::Arc;
+
+pub async fn enroll_totp(
+    State(state): State<Arc<AppState>>,
+    AuthSession { user, .. }: AuthSession,
+) -> Result<SuccessResponse<TotpEnrollment>, ApiError> {
+    let enrollment = state.services.mfa_service.begin_totp_enrollment(&user).await?;
+
+    Ok(SuccessResponse {
+        message: "Scan the code and confirm it with a first code".to_string(),
+        data: Some(enrollment),
+    })
+}
+
+pub async fn confirm_totp(
+    State(state): State<Arc<AppState>>,
+    AuthSession { user, .. }: AuthSession,
+    PayloadJson(payload): PayloadJson<TotpCode>,
+) -> Result<SuccessResponse<RecoveryCodes>, ApiError> {
+    let recovery_codes = state
+        .services
+        .mfa_service
+        .confirm_totp_enrollment(&user, &payload.code)
+        .await?;
+
+    Ok(SuccessResponse {
+        message: "MFA enabled, store the recovery codes somewhere safe".to_string(),
+        data: Some(recovery_codes),
+    })
+}
+
+pub async fn regenerate_recovery_codes(
+    State(state): State<Arc<AppState>>,
+    AuthSession { user, .. }: AuthSession,
+) -> Result<SuccessResponse<RecoveryCodes>, ApiError> {
+    let recovery_codes = state
+        .services
+        .mfa_service
+        .regenerate_recovery_codes(&user)
+        .await?;
+
+    Ok(SuccessResponse {
+        message: "Recovery codes regenerated, previous codes no longer work".to_string(),
+        data: Some(recovery_codes),
+    })
+}
@@ -1,2 +1,12 @@
+pub mod admin;
+pub mod api_keys;
 pub mod authentication;
+pub mod federation;
 pub mod health;
+pub mod mfa;
+pub mod oauth;
+pub mod organizations;
+pub mod passkeys;
+pub mod sessions;
+pub mod user;
+pub mod well_known;
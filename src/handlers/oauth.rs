@@ -0,0 +1,404 @@
+use crate::app_state::AppState;
+use crate::error::oauth::OAuthError;
+use crate::error::user::UserError;
+use crate::extractors::auth_user::{AuthSession, AuthUser};
+use crate::extractors::client_context::ClientContext;
+use crate::extractors::payload_json::PayloadJson;
+use crate::models::oauth::{
+    AuthorizationRedirect, AuthorizationRequest, IntrospectionResponse, OAuthClient, OAuthTokenResponse,
+    TokenReference, TokenRequest, UserInfo,
+};
+use crate::models::response::SuccessResponse;
+use crate::models::user::User;
+use crate::services::oauth::{
+    check_authorization_request, grant_client_scope, parse_basic_auth, redirect_uri_is_registered,
+};
+use axum::extract::rejection::{FormRejection, QueryRejection};
+use axum::extract::{Query, State};
+use axum::response::{IntoResponse, Redirect};
+use axum::{Form, Json};
+use http::{HeaderMap, StatusCode};
+use http::header::{AUTHORIZATION, CACHE_CONTROL};
+use std::sync::Arc;
+use url::Url;
+use uuid::Uuid;
+
+/// Browser entry point of the authorization code flow.
+///
+/// After validating the request it hands over to the configured login page,
+/// which signs the user in and posts the same parameters to [`approve`].
+pub async fn authorize(
+    State(state): State<Arc<AppState>>,
+    query: Result<Query<AuthorizationRequest>, QueryRejection>,
+) -> Result<Redirect, OAuthError> {
+    let Query(request) = query.map_err(|e| OAuthError::InvalidRequest(e.body_text()))?;
+    if let Err(err) = validate_request(&state, &request).await? {
+        return Ok(Redirect::to(&error_redirect(&request, &err)));
+    }
+
+    let login_url = state.services.oauth_service.login_redirect(&request)?;
+    Ok(Redirect::to(&login_url))
+}
+
+/// Issues an authorization code to the signed-in user; the login page sends
+/// the browser on to the returned `redirect_to`. Clients are first-party, so
+/// there is no separate consent step.
+pub async fn approve(
+    State(state): State<Arc<AppState>>,
+    AuthSession { user, .. }: AuthSession,
+    PayloadJson(request): PayloadJson<AuthorizationRequest>,
+) -> Result<SuccessResponse<AuthorizationRedirect>, OAuthError> {
+    if let Err(err) = validate_request(&state, &request).await? {
+        return Ok(SuccessResponse {
+            message: "Authorization request rejected".to_string(),
+            data: Some(AuthorizationRedirect {
+                redirect_to: error_redirect(&request, &err),
+            }),
+        });
+    }
+
+    let code = state
+        .services
+        .oauth_service
+        .create_authorization_code(&user, &request)
+        .await?;
+    let mut redirect_to = Url::parse(&request.redirect_uri)
+        .map_err(|_| OAuthError::InvalidRequest("Malformed redirect_uri".to_string()))?;
+    redirect_to.query_pairs_mut().append_pair("code", &code);
+    if let Some(request_state) = &request.state {
+        redirect_to.query_pairs_mut().append_pair("state", request_state);
+    }
+
+    Ok(SuccessResponse {
+        message: "Authorization granted".to_string(),
+        data: Some(AuthorizationRedirect {
+            redirect_to: redirect_to.to_string(),
+        }),
+    })
+}
+
+pub async fn token(
+    State(state): State<Arc<AppState>>,
+    client_context: ClientContext,
+    headers: HeaderMap,
+    form: Result<Form<TokenRequest>, FormRejection>,
+) -> Result<impl IntoResponse, OAuthError> {
+    let Form(request) = form.map_err(|e| OAuthError::InvalidRequest(e.body_text()))?;
+    let client = authenticate_client(
+        &state,
+        &headers,
+        request.client_id.as_deref(),
+        request.client_secret.as_deref(),
+    )
+    .await?;
+
+    let response = match request.grant_type.as_str() {
+        "authorization_code" => {
+            let code = request
+                .code
+                .as_deref()
+                .ok_or_else(|| OAuthError::InvalidRequest("code is required".to_string()))?;
+            let authorization_code = state
+                .services
+                .oauth_service
+                .exchange_authorization_code(
+                    &client,
+                    code,
+                    request.redirect_uri.as_deref(),
+                    request.code_verifier.as_deref(),
+                )
+                .await?;
+            let user = state
+                .services
+                .user_service
+                .get_user_by_id(&authorization_code.user_id)
+                .await?;
+            if !user.is_active {
+                return Err(OAuthError::InvalidGrant("Account is inactive".to_string()));
+            }
+
+            let tokens = state
+                .services
+                .auth_service
+                .start_session(&user, &client_context, Some(&client.client_id))
+                .await?;
+            let id_token = if authorization_code.scope.split_whitespace().any(|scope| scope == "openid") {
+                Some(state.services.auth_service.create_id_token(
+                    &user,
+                    &client.client_id,
+                    authorization_code.nonce,
+                    &authorization_code.scope,
+                )?)
+            } else {
+                None
+            };
+
+            OAuthTokenResponse {
+                access_token: tokens.access_token,
+                token_type: tokens.token_type,
+                expires_in: tokens.expires_in,
+                refresh_token: Some(tokens.refresh_token),
+                id_token,
+                scope: Some(authorization_code.scope),
+            }
+        }
+        "client_credentials" => {
+            let scope = grant_client_scope(&client, request.scope.as_deref())?;
+            let access_token = state
+                .services
+                .auth_service
+                .create_client_token(&client.client_id, &scope)?;
+
+            OAuthTokenResponse {
+                access_token,
+                token_type: "Bearer".to_string(),
+                expires_in: state.services.auth_service.access_token_expiration(),
+                refresh_token: None,
+                id_token: None,
+                scope: Some(scope),
+            }
+        }
+        "refresh_token" => {
+            let refresh_token = request
+                .refresh_token
+                .as_deref()
+                .ok_or_else(|| OAuthError::InvalidRequest("refresh_token is required".to_string()))?;
+            let refresh_token = state
+                .services
+                .auth_service
+                .consume_refresh_token(refresh_token, Some(&client.client_id))
+                .await?;
+            let user = state
+                .services
+                .user_service
+                .get_user_by_id(&refresh_token.user_id)
+                .await?;
+            if !user.is_active {
+                return Err(OAuthError::InvalidGrant("Account is inactive".to_string()));
+            }
+            let tokens = state
+                .services
+                .auth_service
+                .refresh_session(&user, refresh_token.family_id)
+                .await?;
+
+            OAuthTokenResponse {
+                access_token: tokens.access_token,
+                token_type: tokens.token_type,
+                expires_in: tokens.expires_in,
+                refresh_token: Some(tokens.refresh_token),
+                id_token: None,
+                scope: None,
+            }
+        }
+        _ => return Err(OAuthError::UnsupportedGrantType),
+    };
+
+    Ok(([(CACHE_CONTROL, "no-store")], Json(response)))
+}
+
+/// Token introspection, RFC 7662, for resource servers that cannot verify
+/// our tokens themselves. Only confidential clients may ask.
+pub async fn introspect(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    form: Result<Form<TokenReference>, FormRejection>,
+) -> Result<impl IntoResponse, OAuthError> {
+    let Form(request) = form.map_err(|e| OAuthError::InvalidRequest(e.body_text()))?;
+    let client = authenticate_client(
+        &state,
+        &headers,
+        request.client_id.as_deref(),
+        request.client_secret.as_deref(),
+    )
+    .await?;
+    if client.client_secret_hash.is_none() {
+        return Err(OAuthError::UnauthorizedClient);
+    }
+
+    let response = introspect_token(&state, &request.token).await?;
+    Ok(([(CACHE_CONTROL, "no-store")], Json(response)))
+}
+
+/// Token revocation, RFC 7009. Clients may only revoke tokens issued to them,
+/// that is their own client_credentials tokens and the tokens of sessions they
+/// started through `/oauth/token`: revoking such a refresh token ends its whole
+/// family, revoking such an access token ends its session. Tokens issued to
+/// anyone else are refused with `unauthorized_client`, unknown tokens are not
+/// an error.
+pub async fn revoke(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    form: Result<Form<TokenReference>, FormRejection>,
+) -> Result<StatusCode, OAuthError> {
+    let Form(request) = form.map_err(|e| OAuthError::InvalidRequest(e.body_text()))?;
+    let client = authenticate_client(
+        &state,
+        &headers,
+        request.client_id.as_deref(),
+        request.client_secret.as_deref(),
+    )
+    .await?;
+
+    let auth_service = &state.services.auth_service;
+    let issued_to_client = if let Ok(claims) = auth_service.decode_token(&request.token) {
+        let issued = auth_service.is_issued_to_client(&claims, &client.client_id).await?;
+        if issued {
+            auth_service.revoke_access_token(&request.token, &claims).await?;
+        }
+        issued
+    } else if let Ok(claims) = auth_service.decode_client_token(&request.token) {
+        let issued = claims.sub == client.client_id;
+        if issued {
+            auth_service.revoke_access_token(&request.token, &claims).await?;
+        }
+        issued
+    } else {
+        auth_service
+            .revoke_refresh_token(&request.token, &client.client_id)
+            .await?
+            .unwrap_or(true)
+    };
+
+    if !issued_to_client {
+        return Err(OAuthError::UnauthorizedClient);
+    }
+    Ok(StatusCode::OK)
+}
+
+pub async fn userinfo(AuthUser(user): AuthUser) -> Json<UserInfo> {
+    Json(UserInfo {
+        sub: user.id.to_string(),
+        email: user.email,
+        email_verified: user.is_verified,
+        preferred_username: user.username,
+    })
+}
+
+/// Authenticates the calling client with HTTP Basic auth, falling back to
+/// credentials in the form body.
+async fn authenticate_client(
+    state: &AppState,
+    headers: &HeaderMap,
+    client_id: Option<&str>,
+    client_secret: Option<&str>,
+) -> Result<OAuthClient, OAuthError> {
+    let basic = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_basic_auth);
+    let (client_id, client_secret) = match &basic {
+        Some((client_id, client_secret)) => (client_id.as_str(), Some(client_secret.as_str())),
+        None => (client_id.ok_or(OAuthError::InvalidClient)?, client_secret),
+    };
+    state
+        .services
+        .oauth_service
+        .authenticate_client(client_id, client_secret)
+        .await
+}
+
+async fn introspect_token(state: &AppState, token: &str) -> Result<IntrospectionResponse, OAuthError> {
+    let auth_service = &state.services.auth_service;
+
+    if let Ok(claims) = auth_service.decode_token(token) {
+        let user = match Uuid::parse_str(&claims.sub) {
+            Ok(user_id) => active_user(state, &user_id).await?,
+            Err(_) => None,
+        };
+        let Some(user) = user else {
+            return Ok(IntrospectionResponse::default());
+        };
+        if auth_service.is_access_token_revoked(token, &claims).await? {
+            return Ok(IntrospectionResponse::default());
+        }
+        return Ok(IntrospectionResponse {
+            active: true,
+            username: Some(user.username),
+            token_type: Some("Bearer".to_string()),
+            exp: Some(claims.exp as i64),
+            iat: Some(claims.iat as i64),
+            sub: Some(claims.sub),
+            ..Default::default()
+        });
+    }
+
+    if let Ok(claims) = auth_service.decode_client_token(token) {
+        let registered = state.services.oauth_service.find_client(&claims.sub).await?.is_some();
+        if !registered || auth_service.is_access_token_revoked(token, &claims).await? {
+            return Ok(IntrospectionResponse::default());
+        }
+        return Ok(IntrospectionResponse {
+            active: true,
+            scope: claims.scope,
+            client_id: Some(claims.sub.clone()),
+            token_type: Some("Bearer".to_string()),
+            exp: Some(claims.exp as i64),
+            iat: Some(claims.iat as i64),
+            sub: Some(claims.sub),
+            aud: claims.aud,
+            ..Default::default()
+        });
+    }
+
+    if let Some(refresh_token) = auth_service.find_active_refresh_token(token).await?
+        && let Some(user) = active_user(state, &refresh_token.user_id).await?
+    {
+        return Ok(IntrospectionResponse {
+            active: true,
+            username: Some(user.username),
+            token_type: Some("refresh_token".to_string()),
+            exp: Some(refresh_token.expires_at.timestamp()),
+            iat: Some(refresh_token.created_at.timestamp()),
+            sub: Some(refresh_token.user_id.to_string()),
+            ..Default::default()
+        });
+    }
+
+    Ok(IntrospectionResponse::default())
+}
+
+async fn active_user(state: &AppState, user_id: &Uuid) -> Result<Option<User>, OAuthError> {
+    match state.services.user_service.get_user_by_id(user_id).await {
+        Ok(user) if user.is_active => Ok(Some(user)),
+        Ok(_) | Err(UserError::UserNotFound(_)) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Errors about the client or redirect URI are returned directly (`Err`), the
+/// rest (`Ok(Err)`) can safely be reported to the client's redirect URI.
+async fn validate_request(
+    state: &AppState,
+    request: &AuthorizationRequest,
+) -> Result<Result<(), OAuthError>, OAuthError> {
+    let client = state
+        .services
+        .oauth_service
+        .find_client(&request.client_id)
+        .await?
+        .ok_or_else(|| OAuthError::InvalidRequest("Unknown client_id".to_string()))?;
+    if !redirect_uri_is_registered(&client, &request.redirect_uri) {
+        return Err(OAuthError::InvalidRequest("redirect_uri is not registered".to_string()));
+    }
+
+    Ok(check_authorization_request(request))
+}
+
+fn error_redirect(request: &AuthorizationRequest, error: &OAuthError) -> String {
+    let mut redirect_to = match Url::parse(&request.redirect_uri) {
+        Ok(url) => url,
+        Err(_) => return request.redirect_uri.clone(),
+    };
+    {
+        let mut query = redirect_to.query_pairs_mut();
+        query.append_pair("error", error.code());
+        if let Some(description) = error.description() {
+            query.append_pair("error_description", &description);
+        }
+        if let Some(request_state) = &request.state {
+            query.append_pair("state", request_state);
+        }
+    }
+    redirect_to.to_string()
+}
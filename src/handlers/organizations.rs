@@ -0,0 +1,232 @@
+use crate::app_state::AppState;
+use crate::error::api::ApiError;
+use crate::extractors::auth_user::{AuthSession, AuthUser};
+use crate::extractors::payload_json::PayloadJson;
+use crate::models::authenticate::JwtToken;
+use crate::models::organization::{Organization, OrganizationInvitation, OrganizationMember, OrganizationRole};
+use crate::models::request::{CreateOrganization, InviteMember, SwitchOrganization, Token, UpdateMemberRole};
+use crate::models::response::SuccessResponse;
+use axum::extract::{Path, State};
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+pub async fn list_organizations(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<SuccessResponse<Vec<Organization>>, ApiError> {
+    let organizations = state.services.organization_service.list_organizations(&user.id).await?;
+
+    Ok(SuccessResponse {
+        message: "Organizations retrieved".to_string(),
+        data: Some(organizations),
+    })
+}
+
+pub async fn create_organization(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    PayloadJson(payload): PayloadJson<CreateOrganization>,
+) -> Result<SuccessResponse<Organization>, ApiError> {
+    payload.validate()?;
+    let organization = state
+        .services
+        .organization_service
+        .create_organization(&user, payload.name.trim())
+        .await?;
+
+    Ok(SuccessResponse {
+        message: "Organization created".to_string(),
+        data: Some(organization),
+    })
+}
+
+pub async fn get_organization(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Path(organization_id): Path<Uuid>,
+) -> Result<SuccessResponse<Organization>, ApiError> {
+    let organization = state
+        .services
+        .organization_service
+        .get_organization(&organization_id, &user.id)
+        .await?;
+
+    Ok(SuccessResponse {
+        message: "Organization retrieved".to_string(),
+        data: Some(organization),
+    })
+}
+
+pub async fn delete_organization(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Path(organization_id): Path<Uuid>,
+) -> Result<SuccessResponse<()>, ApiError> {
+    state
+        .services
+        .organization_service
+        .delete_organization(&organization_id, &user.id)
+        .await?;
+
+    Ok(SuccessResponse {
+        message: "Organization deleted".to_string(),
+        data: None,
+    })
+}
+
+/// Makes the current session act in an organization the user belongs to, and
+/// returns tokens carrying it. Refreshed tokens keep it.
+pub async fn switch_organization(
+    State(state): State<Arc<AppState>>,
+    AuthSession { user, session_id, .. }: AuthSession,
+    PayloadJson(payload): PayloadJson<SwitchOrganization>,
+) -> Result<SuccessResponse<JwtToken>, ApiError> {
+    if let Some(organization_id) = &payload.organization_id {
+        state
+            .services
+            .organization_service
+            .get_organization(organization_id, &user.id)
+            .await?;
+    }
+    let token = state
+        .services
+        .auth_service
+        .switch_organization(&user, session_id, payload.organization_id)
+        .await?;
+
+    Ok(SuccessResponse {
+        message: "Active organization changed".to_string(),
+        data: Some(token),
+    })
+}
+
+pub async fn list_members(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Path(organization_id): Path<Uuid>,
+) -> Result<SuccessResponse<Vec<OrganizationMember>>, ApiError> {
+    let organizations = &state.services.organization_service;
+    organizations
+        .require_role(&organization_id, &user.id, OrganizationRole::Member)
+        .await?;
+    let members = organizations.list_members(&organization_id).await?;
+
+    Ok(SuccessResponse {
+        message: "Members retrieved".to_string(),
+        data: Some(members),
+    })
+}
+
+/// Changes a member's role; tokens carry it from the member's next refresh.
+pub async fn update_member_role(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Path((organization_id, member_id)): Path<(Uuid, Uuid)>,
+    PayloadJson(payload): PayloadJson<UpdateMemberRole>,
+) -> Result<SuccessResponse<()>, ApiError> {
+    state
+        .services
+        .organization_service
+        .update_member_role(&organization_id, &user.id, &member_id, payload.role)
+        .await?;
+
+    Ok(SuccessResponse {
+        message: "Member role updated".to_string(),
+        data: None,
+    })
+}
+
+/// Removes a member, or leaves the organization when the member is the caller.
+pub async fn remove_member(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Path((organization_id, member_id)): Path<(Uuid, Uuid)>,
+) -> Result<SuccessResponse<()>, ApiError> {
+    state
+        .services
+        .organization_service
+        .remove_member(&organization_id, &user.id, &member_id)
+        .await?;
+
+    Ok(SuccessResponse {
+        message: "Member removed".to_string(),
+        data: None,
+    })
+}
+
+pub async fn list_invitations(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Path(organization_id): Path<Uuid>,
+) -> Result<SuccessResponse<Vec<OrganizationInvitation>>, ApiError> {
+    let organizations = &state.services.organization_service;
+    organizations
+        .require_role(&organization_id, &user.id, OrganizationRole::Admin)
+        .await?;
+    let invitations = organizations.list_invitations(&organization_id).await?;
+
+    Ok(SuccessResponse {
+        message: "Invitations retrieved".to_string(),
+        data: Some(invitations),
+    })
+}
+
+pub async fn invite_member(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Path(organization_id): Path<Uuid>,
+    PayloadJson(payload): PayloadJson<InviteMember>,
+) -> Result<SuccessResponse<OrganizationInvitation>, ApiError> {
+    payload.validate()?;
+    let invitation = state
+        .services
+        .organization_service
+        .invite(
+            &state.services.email_service,
+            &organization_id,
+            &user,
+            payload.email.trim(),
+            payload.role,
+        )
+        .await?;
+
+    Ok(SuccessResponse {
+        message: "Invitation sent".to_string(),
+        data: Some(invitation),
+    })
+}
+
+pub async fn revoke_invitation(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    Path((organization_id, invitation_id)): Path<(Uuid, Uuid)>,
+) -> Result<SuccessResponse<()>, ApiError> {
+    state
+        .services
+        .organization_service
+        .revoke_invitation(&organization_id, &user.id, &invitation_id)
+        .await?;
+
+    Ok(SuccessResponse {
+        message: "Invitation revoked".to_string(),
+        data: None,
+    })
+}
+
+pub async fn accept_invitation(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+    PayloadJson(payload): PayloadJson<Token>,
+) -> Result<SuccessResponse<Organization>, ApiError> {
+    let organization = state
+        .services
+        .organization_service
+        .accept_invitation(&user, &payload.token)
+        .await?;
+
+    Ok(SuccessResponse {
+        message: "Invitation accepted".to_string(),
+        data: Some(organization),
+    })
+}
@@ -0,0 +1,120 @@
+use crate::app_state::AppState;
+use crate::error::api::ApiError;
+use crate::extractors::auth_user::{AuthSession, AuthUser};
+use crate::extractors::client_context::ClientContext;
+use crate::extractors::payload_json::PayloadJson;
+use crate::extractors::tenant::Tenant;
+use crate::models::authenticate::LoginResponse;
+use crate::models::passkey::{AuthenticationCredential, CreationOptions, Passkey, RequestOptions};
+use crate::models::request::PasskeyRegistration;
+use crate::models::response::SuccessResponse;
+use axum::extract::{Path, State};
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+pub async fn begin_registration(
+    State(state): State<Arc<AppState>>,
+    AuthSession { user, .. }: AuthSession,
+) -> Result<SuccessResponse<CreationOptions>, ApiError> {
+    let options = state.services.passkey_service.begin_registration(&user).await?;
+
+    Ok(SuccessResponse {
+        message: "Registration challenge created".to_string(),
+        data: Some(options),
+    })
+}
+
+pub async fn finish_registration(
+    State(state): State<Arc<AppState>>,
+    AuthSession { user, .. }: AuthSession,
+    PayloadJson(payload): PayloadJson<PasskeyRegistration>,
+) -> Result<SuccessResponse<Passkey>, ApiError> {
+    payload.validate()?;
+
+    let passkey = state
+        .services
+        .passkey_service
+        .finish_registration(&user, payload.name, &payload.credential)
+        .await?;
+
+    Ok(SuccessResponse {
+        message: "Passkey registered".to_string(),
+        data: Some(passkey),
+    })
+}
+
+pub async fn begin_authentication(
+    State(state): State<Arc<AppState>>,
+) -> Result<SuccessResponse<RequestOptions>, ApiError> {
+    let options = state.services.passkey_service.begin_authentication().await?;
+
+    Ok(SuccessResponse {
+        message: "Authentication challenge created".to_string(),
+        data: Some(options),
+    })
+}
+
+pub async fn finish_authentication(
+    State(state): State<Arc<AppState>>,
+    Tenant(tenant): Tenant,
+    client: ClientContext,
+    PayloadJson(payload): PayloadJson<AuthenticationCredential>,
+) -> Result<SuccessResponse<LoginResponse>, ApiError> {
+    let (user_id, assertion) = state
+        .services
+        .passkey_service
+        .finish_authentication(&tenant, &payload)
+        .await?;
+    let user = state.services.user_service.get_user_by_id(&user_id).await?;
+
+    // A user-verified passkey is already two factors; otherwise fall back to the usual MFA step.
+    let response = if assertion.user_verified {
+        let token = state
+            .services
+            .auth_service
+            .start_session(&user, &client, None)
+            .await?;
+        LoginResponse::Token(token)
+    } else {
+        state.services.auth_service.complete_login(&user, &client).await?
+    };
+    let message = match response {
+        LoginResponse::Token(_) => "Login success",
+        LoginResponse::MfaRequired(_) => "MFA required",
+    };
+
+    Ok(SuccessResponse {
+        message: message.to_string(),
+        data: Some(response),
+    })
+}
+
+pub async fn list_passkeys(
+    State(state): State<Arc<AppState>>,
+    AuthUser(user): AuthUser,
+) -> Result<SuccessResponse<Vec<Passkey>>, ApiError> {
+    let passkeys = state.services.passkey_service.list_passkeys(&user.id).await?;
+
+    Ok(SuccessResponse {
+        message: "Passkeys retrieved".to_string(),
+        data: Some(passkeys),
+    })
+}
+
+pub async fn delete_passkey(
+    State(state): State<Arc<AppState>>,
+    AuthSession { user, .. }: AuthSession,
+    Path(passkey_id): Path<Uuid>,
+) -> Result<SuccessResponse<()>, ApiError> {
+    state
+        .services
+        .passkey_service
+        .delete_passkey(&user.id, &passkey_id)
+        .await?;
+
+    Ok(SuccessResponse {
+        message: "Passkey removed".to_string(),
+        data: None,
+    })
+}
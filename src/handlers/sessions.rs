@@ -0,0 +1,57 @@
+use crate::app_state::AppState;
+use crate::error::api::ApiError;
+use crate::extractors::auth_user::AuthSession;
+use crate::models::response::SuccessResponse;
+use crate::models::session::Session;
+use axum::extract::{Path, State};
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub async fn list_sessions(
+    State(state): State<Arc<AppState>>,
+    AuthSession { user, session_id, .. }: AuthSession,
+) -> Result<SuccessResponse<Vec<Session>>, ApiError> {
+    let mut sessions = state.services.auth_service.list_sessions(&user.id).await?;
+    for session in &mut sessions {
+        session.current = session.id == session_id;
+    }
+
+    Ok(SuccessResponse {
+        message: "Sessions retrieved".to_string(),
+        data: Some(sessions),
+    })
+}
+
+pub async fn revoke_session(
+    State(state): State<Arc<AppState>>,
+    AuthSession { user, .. }: AuthSession,
+    Path(session_id): Path<Uuid>,
+) -> Result<SuccessResponse<()>, ApiError> {
+    state
+        .services
+        .auth_service
+        .revoke_session(&user.id, &session_id)
+        .await?;
+
+    Ok(SuccessResponse {
+        message: "Session revoked".to_string(),
+        data: None,
+    })
+}
+
+/// Logs out everywhere except the session making the request.
+pub async fn revoke_other_sessions(
+    State(state): State<Arc<AppState>>,
+    AuthSession { user, session_id, .. }: AuthSession,
+) -> Result<SuccessResponse<()>, ApiError> {
+    let revoked = state
+        .services
+        .auth_service
+        .revoke_other_sessions(&user.id, &session_id)
+        .await?;
+
+    Ok(SuccessResponse {
+        message: format!("{} other sessions revoked", revoked),
+        data: None,
+    })
+}
@@ -0,0 +1,78 @@
+use crate::app_state::AppState;
+use crate::error::api::ApiError;
+use crate::extractors::auth_user::{AuthSession, AuthUser};
+use crate::extractors::client_context::ClientContext;
+use crate::extractors::payload_json::PayloadJson;
+use crate::models::request::{ChangeEmail, ChangePassword};
+use crate::models::response::SuccessResponse;
+use crate::models::user::User;
+use axum::extract::State;
+use std::sync::Arc;
+use validator::Validate;
+
+pub async fn me(AuthUser(user): AuthUser) -> SuccessResponse<User> {
+    SuccessResponse {
+        message: "User retrieved".to_string(),
+        data: Some(user),
+    }
+}
+
+pub async fn change_password(
+    State(state): State<Arc<AppState>>,
+    AuthSession { user, session_id, .. }: AuthSession,
+    client: ClientContext,
+    PayloadJson(payload): PayloadJson<ChangePassword>,
+) -> Result<SuccessResponse<()>, ApiError> {
+    payload.validate()?;
+
+    state
+        .services
+        .auth_service
+        .check_password_change(&user, &payload.current_password, &payload.new_password, &client)
+        .await?;
+    state
+        .services
+        .user_service
+        .update_password(&user.id, &payload.new_password)
+        .await?;
+    // Whoever knew the old password may still hold a session; only this one survives.
+    state
+        .services
+        .auth_service
+        .revoke_other_sessions(&user.id, &session_id)
+        .await?;
+    state
+        .services
+        .auth_service
+        .send_password_changed_notification(&state.services.email_service, &user)
+        .await;
+
+    Ok(SuccessResponse {
+        data: None,
+        message: "Password changed".to_string(),
+    })
+}
+
+pub async fn change_email(
+    State(state): State<Arc<AppState>>,
+    AuthSession { user, .. }: AuthSession,
+    PayloadJson(payload): PayloadJson<ChangeEmail>,
+) -> Result<SuccessResponse<()>, ApiError> {
+    payload.validate()?;
+
+    state
+        .services
+        .user_service
+        .set_pending_email(&user.id, &payload.new_email)
+        .await?;
+    state
+        .services
+        .auth_service
+        .send_email_change_token(&state.services.email_service, &user, &payload.new_email)
+        .await?;
+
+    Ok(SuccessResponse {
+        data: None,
+        message: "Confirmation sent to the new email address".to_string(),
+    })
+}
@@ -0,0 +1,15 @@
+use crate::app_state::AppState;
+use axum::Json;
+use axum::extract::State;
+use crate::models::oauth::OpenIdConfiguration;
+use jsonwebtoken::jwk::JwkSet;
+use std::sync::Arc;
+
+pub async fn jwks(State(state): State<Arc<AppState>>) -> Json<JwkSet> {
+    Json(state.services.auth_service.jwks())
+}
+
+pub async fn openid_configuration(State(state): State<Arc<AppState>>) -> Json<OpenIdConfiguration> {
+    let algorithm = state.services.auth_service.signing_algorithm();
+    Json(state.services.oauth_service.openid_configuration(algorithm))
+}
@@ -1,23 +1,38 @@
 use crate::app_state::{AppState, Services};
 use crate::config::load_config;
+use crate::middleware::tenant::TenantLayer;
 use crate::routes::error::not_found_handler;
+use crate::services::api_keys::ApiKeys;
 use crate::services::authentication::Authentication;
+use crate::services::denylist::{CachedDenylist, PostgresDenylist, spawn_sweeper};
 use crate::services::email::EmailService;
+use crate::services::federation::Federation;
+use crate::services::jwt_keys::JwtKeyring;
+use crate::services::mfa::Mfa;
+use crate::services::oauth::OAuth;
+use crate::services::organizations::Organizations;
+use crate::services::passkeys::Passkeys;
+use crate::services::roles::Roles;
 use crate::services::users::Users;
-use axum::Router;
-use sqlx::PgPool;
-use sqlx::any::{AnyPoolOptions, install_default_drivers};
+use crate::utils::clock::SystemClock;
+use axum::extract::Request;
+use axum::{Router, ServiceExt};
+use sqlx::any::install_default_drivers;
 use sqlx::postgres::PgPoolOptions;
 use std::net::{IpAddr, SocketAddr};
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
+use tower::Layer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 mod app_state;
+mod cli;
 mod config;
 mod error;
 mod extractors;
 mod handlers;
+mod middleware;
 mod models;
 mod routes;
 mod services;
@@ -46,21 +61,56 @@ async fn main() -> anyhow::Result<()> {
         .connect(config.database.url.as_str())
         .await?;
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        return cli::run(&args, pool, config).await;
+    }
+
     let email_service = EmailService::new(config.clone());
-    let auth_service = Authentication::new(pool.clone(), config.clone());
+    let keyring = JwtKeyring::from_config(&config.jwt)?;
+    let denylist = Arc::new(CachedDenylist::new(
+        Arc::new(PostgresDenylist::new(pool.clone())),
+        Duration::from_secs(config.denylist.cache_ttl_seconds),
+    ));
+    spawn_sweeper(denylist.clone(), Duration::from_secs(config.denylist.sweep_interval_seconds));
+    let api_key_service = ApiKeys::new(pool.clone());
+    let auth_service = Authentication::new(pool.clone(), config.clone(), keyring, denylist);
+    let federation_service = Federation::new(pool.clone(), config.clone())?;
+    let mfa_service = Mfa::new(pool.clone(), config.clone(), Arc::new(SystemClock))?;
+    let oauth_service = OAuth::new(pool.clone(), config.clone());
+    let organization_service = Organizations::new(pool.clone(), config.clone());
+    let passkey_service = Passkeys::new(pool.clone(), config.clone());
+    let role_service = Roles::new(pool.clone());
     let user_service = Users::new(pool, config.clone());
     let state = Arc::new(AppState {
+        config: config.clone(),
         services: Services {
+            api_key_service,
             email_service,
+            federation_service,
+            mfa_service,
+            oauth_service,
+            organization_service,
+            passkey_service,
+            role_service,
             auth_service,
             user_service,
         },
     });
     let app = Router::new()
         .nest("/health", routes::health::router())
+        .nest("/.well-known", routes::well_known::router(state.clone()))
+        .nest("/admin", routes::admin::router(state.clone()))
+        .nest("/oauth", routes::oauth::router(state.clone()))
+        .nest("/user/passkeys", routes::passkeys::router(state.clone()))
+        .nest("/user/sessions", routes::sessions::router(state.clone()))
+        .nest("/user/api-keys", routes::api_keys::router(state.clone()))
+        .nest("/user/organizations", routes::organizations::router(state.clone()))
+        .nest("/user/login/federated", routes::federation::router(state.clone()))
         .nest("/user", routes::authentication::router(state))
         .layer(tower_http::trace::TraceLayer::new_for_http())
         .fallback(not_found_handler);
+    let app = TenantLayer::new(config.clone()).layer(app);
 
     // print!("hos : {}", config.server.host);
     let ip = IpAddr::from_str(&config.server.host)?;
@@ -69,6 +119,10 @@ async fn main() -> anyhow::Result<()> {
     let listener = tokio::net::TcpListener::bind(addr).await?;
     tracing::info!("listening on {}", addr);
 
-    axum::serve(listener, app).await?;
+    axum::serve(
+        listener,
+        ServiceExt::<Request>::into_make_service_with_connect_info::<SocketAddr>(app),
+    )
+    .await?;
     Ok(())
 }
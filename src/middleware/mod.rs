@@ -0,0 +1,3 @@
+pub mod permission;
+pub mod rate_limit;
+pub mod tenant;
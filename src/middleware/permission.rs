@@ -0,0 +1,125 @@
+use crate::app_state::AppState;
+use crate::error::api::ApiError;
+use crate::extractors::auth_user::AuthGrant;
+use axum::extract::{FromRequestParts, Request};
+use axum::response::{IntoResponse, Response};
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+/// Requires a user access token or API key granting `permission`. The first
+/// administrator is bootstrapped with `auth-service grant-role <email> admin`.
+#[derive(Clone)]
+pub struct RequirePermission {
+    state: Arc<AppState>,
+    permission: &'static str,
+}
+
+impl RequirePermission {
+    pub fn new(state: Arc<AppState>, permission: &'static str) -> Self {
+        Self { state, permission }
+    }
+}
+
+impl<S> Layer<S> for RequirePermission {
+    type Service = RequirePermissionService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequirePermissionService {
+            inner,
+            state: self.state.clone(),
+            permission: self.permission,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RequirePermissionService<S> {
+    inner: S,
+    state: Arc<AppState>,
+    permission: &'static str,
+}
+
+impl<S> Service<Request> for RequirePermissionService<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // The clone may not be ready; keep the service that was polled.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let state = self.state.clone();
+        let permission = self.permission;
+
+        Box::pin(async move {
+            let (mut parts, body) = request.into_parts();
+            if let Err(e) = authorize(&state, &mut parts, permission).await {
+                return Ok(e.into_response());
+            }
+            inner.call(Request::from_parts(parts, body)).await
+        })
+    }
+}
+
+async fn authorize(state: &Arc<AppState>, parts: &mut http::request::Parts, permission: &str) -> Result<(), ApiError> {
+    let grant = AuthGrant::from_request_parts(parts, state).await?;
+    if !grant.permissions.iter().any(|granted| granted == permission) {
+        return Err(ApiError::Forbidden(format!("Missing permission {}", permission)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state::{test_access_token, test_state_with_pool};
+    use axum::Router;
+    use axum::body::Body;
+    use axum::routing::get;
+    use http::StatusCode;
+    use http::header::AUTHORIZATION;
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+
+    async fn status(state: Arc<AppState>, authorization: &str) -> StatusCode {
+        let app = Router::new()
+            .route("/admin/roles", get(|| async { "roles" }))
+            .route_layer(RequirePermission::new(state, "roles:manage"));
+        let request = Request::get("/admin/roles")
+            .header(AUTHORIZATION, authorization)
+            .body(Body::empty())
+            .unwrap();
+        app.oneshot(request).await.unwrap().status()
+    }
+
+    #[sqlx::test]
+    async fn test_token_is_checked_for_the_permission(pool: PgPool) {
+        let state = Arc::new(test_state_with_pool(pool.clone()));
+        let admin = test_access_token(&state, &pool, "default", Some("admin")).await;
+        let user = test_access_token(&state, &pool, "default", None).await;
+
+        assert_eq!(status(state.clone(), &format!("Bearer {}", admin)).await, StatusCode::OK);
+        assert_eq!(status(state, &format!("Bearer {}", user)).await, StatusCode::FORBIDDEN);
+    }
+
+    #[sqlx::test]
+    async fn test_missing_or_invalid_token_is_unauthorized(pool: PgPool) {
+        let state = Arc::new(test_state_with_pool(pool));
+        assert_eq!(status(state.clone(), "Bearer wrong").await, StatusCode::UNAUTHORIZED);
+        // The static admin token is gone; it is an invalid token like any other.
+        assert_eq!(status(state.clone(), "Bearer test-admin-token").await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(state, "Basic test-admin-token").await, StatusCode::UNAUTHORIZED);
+    }
+}
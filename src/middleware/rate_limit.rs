@@ -0,0 +1,323 @@
+use crate::app_state::AppState;
+use crate::config::{RouteRateLimit, TokenBucketConfig};
+use crate::error::api::ApiError;
+use crate::extractors::client_context::ClientContext;
+use axum::body::{Body, to_bytes};
+use axum::extract::{FromRequestParts, MatchedPath, Request};
+use axum::response::{IntoResponse, Response};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tower::{Layer, Service};
+
+/// Bodies read to find the identity are small JSON objects; anything larger is refused.
+const MAX_BODY_BYTES: usize = 64 * 1024;
+/// Buckets tracked per route and key kind before full ones are dropped.
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+/// Limits requests to the routes configured in `rate_limit.routes`, by client
+/// address and by the identity a request targets. Add it with `route_layer`
+/// so the matched route is known. Buckets are in memory, per process.
+#[derive(Clone)]
+pub struct RateLimitLayer {
+    state: Arc<AppState>,
+    limiters: Arc<HashMap<String, RouteLimiter>>,
+}
+
+impl RateLimitLayer {
+    pub fn new(state: Arc<AppState>) -> Self {
+        let limiters = state
+            .config
+            .rate_limit
+            .routes
+            .iter()
+            .map(|(path, limit)| (path.clone(), RouteLimiter::new(limit)))
+            .collect();
+        Self {
+            state,
+            limiters: Arc::new(limiters),
+        }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit {
+            inner,
+            state: self.state.clone(),
+            limiters: self.limiters.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimit<S> {
+    inner: S,
+    state: Arc<AppState>,
+    limiters: Arc<HashMap<String, RouteLimiter>>,
+}
+
+impl<S> Service<Request> for RateLimit<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // The clone may not be ready; keep the service that was polled.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let state = self.state.clone();
+        let limiters = self.limiters.clone();
+
+        Box::pin(async move {
+            let path = request
+                .extensions()
+                .get::<MatchedPath>()
+                .map(|path| path.as_str().to_string())
+                .unwrap_or_else(|| request.uri().path().to_string());
+            let Some(limiter) = limiters.get(&path) else {
+                return inner.call(request).await;
+            };
+
+            let (mut parts, body) = request.into_parts();
+            let Ok(client) = ClientContext::from_request_parts(&mut parts, &state).await;
+            let (body, identity) = match &limiter.identity_field {
+                Some(field) => match to_bytes(body, MAX_BODY_BYTES).await {
+                    Ok(bytes) => {
+                        let identity = identity_from_body(&bytes, field);
+                        (Body::from(bytes), identity)
+                    }
+                    Err(_) => {
+                        return Ok(ApiError::BadRequest("Request body is too large".to_string()).into_response());
+                    }
+                },
+                None => (body, None),
+            };
+
+            if let Err(retry_after) = limiter.check(client.ip_address.as_deref(), identity.as_deref(), Instant::now()) {
+                return Ok(ApiError::TooManyRequests {
+                    message: "Rate limit exceeded".to_string(),
+                    retry_after,
+                }
+                .into_response());
+            }
+
+            inner.call(Request::from_parts(parts, body)).await
+        })
+    }
+}
+
+/// The value of `field` in a JSON object body, normalised so that case and
+/// surrounding spaces do not make a new identity.
+fn identity_from_body(body: &[u8], field: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_slice(body).ok()?;
+    let identity = match value.get(field)? {
+        serde_json::Value::String(s) => s.trim().to_lowercase(),
+        other => other.to_string(),
+    };
+    (!identity.is_empty()).then_some(identity)
+}
+
+struct RouteLimiter {
+    per_ip: Option<TokenBuckets>,
+    per_identity: Option<TokenBuckets>,
+    identity_field: Option<String>,
+}
+
+impl RouteLimiter {
+    fn new(limit: &RouteRateLimit) -> Self {
+        // A bucket per identity needs a field to read it from, and the body is only read for one.
+        let identity_field = limit.per_identity.and(limit.identity_field.clone());
+        Self {
+            per_ip: limit.per_ip.map(TokenBuckets::new),
+            per_identity: identity_field.as_ref().and(limit.per_identity).map(TokenBuckets::new),
+            identity_field,
+        }
+    }
+
+    /// Takes a token from each applicable bucket, or returns the seconds until
+    /// the request would be allowed.
+    fn check(&self, ip_address: Option<&str>, identity: Option<&str>, now: Instant) -> Result<(), u64> {
+        if let (Some(buckets), Some(ip_address)) = (&self.per_ip, ip_address) {
+            buckets.take(ip_address, now)?;
+        }
+        if let (Some(buckets), Some(identity)) = (&self.per_identity, identity) {
+            buckets.take(identity, now)?;
+        }
+        Ok(())
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+struct TokenBuckets {
+    burst: f64,
+    per_second: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl TokenBuckets {
+    fn new(config: TokenBucketConfig) -> Self {
+        Self {
+            burst: config.burst as f64,
+            per_second: config.per_minute as f64 / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated_at).as_secs_f64();
+        (bucket.tokens + elapsed * self.per_second).min(self.burst)
+    }
+
+    fn take(&self, key: &str, now: Instant) -> Result<(), u64> {
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_KEYS && !buckets.contains_key(key) {
+            buckets.retain(|_, bucket| self.refilled(bucket, now) < self.burst);
+        }
+
+        let tokens = match buckets.get(key) {
+            Some(bucket) => self.refilled(bucket, now),
+            None => self.burst,
+        };
+        if tokens < 1.0 {
+            return Err(((1.0 - tokens) / self.per_second).ceil() as u64);
+        }
+
+        buckets.insert(
+            key.to_string(),
+            Bucket {
+                tokens: tokens - 1.0,
+                updated_at: now,
+            },
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state::test_state;
+    use crate::config::{RateLimitConfig, test_config};
+    use axum::Router;
+    use axum::routing::post;
+    use http::StatusCode;
+    use http::header::RETRY_AFTER;
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    fn buckets(burst: u32, per_minute: u32) -> TokenBuckets {
+        TokenBuckets::new(TokenBucketConfig { burst, per_minute })
+    }
+
+    #[test]
+    fn test_bucket_allows_burst_then_limits() {
+        let buckets = buckets(3, 60);
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(buckets.take("203.0.113.7", now).is_ok());
+        }
+        assert_eq!(buckets.take("203.0.113.7", now), Err(1));
+        assert!(buckets.take("198.51.100.2", now).is_ok());
+    }
+
+    #[test]
+    fn test_bucket_refills_over_time() {
+        let buckets = buckets(1, 15);
+        let now = Instant::now();
+
+        assert!(buckets.take("a@example.com", now).is_ok());
+        assert_eq!(buckets.take("a@example.com", now + Duration::from_secs(2)), Err(2));
+        assert!(buckets.take("a@example.com", now + Duration::from_secs(4)).is_ok());
+    }
+
+    #[test]
+    fn test_guessing_routes_are_limited_by_default() {
+        let routes = RateLimitConfig::default().routes;
+        for path in [
+            "/user/login/email-code/verify",
+            "/user/login/mfa",
+            "/user/login/magic-link/verify",
+            "/user/password/reset",
+        ] {
+            let limit = &routes[path];
+            assert!(limit.per_ip.is_some() && limit.per_identity.is_some(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_identity_is_normalised() {
+        let body = br#"{"email": " Alice@Example.com ", "user_id": 7}"#;
+
+        assert_eq!(identity_from_body(body, "email").as_deref(), Some("alice@example.com"));
+        assert_eq!(identity_from_body(body, "user_id").as_deref(), Some("7"));
+        assert_eq!(identity_from_body(body, "missing"), None);
+        assert_eq!(identity_from_body(b"not json", "email"), None);
+    }
+
+    fn app() -> Router {
+        let mut config = test_config();
+        config.rate_limit = RateLimitConfig {
+            routes: HashMap::from([(
+                "/user/register".to_string(),
+                RouteRateLimit {
+                    per_ip: None,
+                    per_identity: Some(TokenBucketConfig { burst: 1, per_minute: 1 }),
+                    identity_field: Some("email".to_string()),
+                },
+            )]),
+        };
+        let state = Arc::new(AppState {
+            config: Arc::new(config),
+            ..test_state()
+        });
+        Router::new()
+            .route("/user/register", post(|body: String| async move { body }))
+            .route_layer(RateLimitLayer::new(state))
+    }
+
+    fn register(email: &str) -> Request {
+        Request::post("/user/register")
+            .body(Body::from(format!(r#"{{"email":"{}"}}"#, email)))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_limited_request_gets_retry_after_and_envelope() {
+        let app = app();
+
+        let response = app.clone().oneshot(register("a@example.com")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), 100).await.unwrap();
+        assert_eq!(&body[..], br#"{"email":"a@example.com"}"#);
+
+        let response = app.clone().oneshot(register("A@example.com")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[RETRY_AFTER], "60");
+        let body = to_bytes(response.into_body(), 200).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(json["success"], false);
+
+        let response = app.oneshot(register("b@example.com")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::config::{Config, DEFAULT_TENANT};
+use crate::extractors::tenant::Tenant;
+use axum::extract::Request;
+use http::Uri;
+use http::header::HOST;
+use http::uri::PathAndQuery;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+/// Assigns each request to a tenant for the `Tenant` extractor: the one named
+/// by a `/t/{id}` path prefix, which is stripped before routing, else the one
+/// listing the `Host` header, else the default tenant. Wrap the whole router
+/// with it, as a prefix must be removed before routes are matched.
+#[derive(Clone)]
+pub struct TenantLayer {
+    config: Arc<Config>,
+}
+
+impl TenantLayer {
+    pub fn new(config: Arc<Config>) -> Self {
+        Self { config }
+    }
+}
+
+impl<S> Layer<S> for TenantLayer {
+    type Service = ResolveTenant<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ResolveTenant {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ResolveTenant<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+impl<S> Service<Request> for ResolveTenant<S>
+where
+    S: Service<Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request) -> Self::Future {
+        let tenant = resolve(&self.config, &mut request);
+        request.extensions_mut().insert(tenant);
+        self.inner.call(request)
+    }
+}
+
+fn resolve(config: &Config, request: &mut Request) -> Tenant {
+    if let Some((tenant, uri)) = strip_tenant_prefix(config, request.uri()) {
+        *request.uri_mut() = uri;
+        return tenant;
+    }
+
+    let host = request
+        .headers()
+        .get(HOST)
+        .and_then(|value| value.to_str().ok())
+        .or_else(|| request.uri().host())
+        .map(|host| match host.rsplit_once(':') {
+            Some((name, port)) if port.bytes().all(|b| b.is_ascii_digit()) => name,
+            _ => host,
+        });
+    let tenant = host.and_then(|host| {
+        config
+            .tenants
+            .iter()
+            .find(|tenant| tenant.hosts.iter().any(|known| known.eq_ignore_ascii_case(host)))
+    });
+    Tenant(tenant.map_or(DEFAULT_TENANT, |tenant| tenant.id.as_str()).to_string())
+}
+
+/// The configured tenant named by `/t/{id}` and the URI without that prefix.
+fn strip_tenant_prefix(config: &Config, uri: &Uri) -> Option<(Tenant, Uri)> {
+    let rest = uri.path().strip_prefix("/t/")?;
+    let (tenant_id, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+    let tenant = config.tenant(tenant_id)?;
+
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_string(),
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(PathAndQuery::try_from(path_and_query).ok()?);
+    let uri = Uri::from_parts(parts).ok()?;
+    Some((Tenant(tenant.id.clone()), uri))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{TenantConfig, test_config};
+    use axum::Router;
+    use axum::body::{Body, to_bytes};
+    use axum::routing::get;
+    use http::StatusCode;
+    use tower::ServiceExt;
+
+    fn app() -> Router {
+        let mut config = test_config();
+        config.tenants = vec![TenantConfig {
+            id: "acme".to_string(),
+            hosts: vec!["auth.acme.test".to_string()],
+            app: None,
+            from_name: None,
+            from_email: None,
+        }];
+        let router = Router::new().route("/user/me", get(|Tenant(tenant): Tenant| async move { tenant }));
+        Router::new().fallback_service(TenantLayer::new(Arc::new(config)).layer(router))
+    }
+
+    async fn tenant_of(request: Request) -> (StatusCode, String) {
+        let response = app().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), 100).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_tenant_is_resolved_from_host() {
+        let request = Request::get("/user/me")
+            .header(HOST, "Auth.Acme.test:443")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(tenant_of(request).await, (StatusCode::OK, "acme".to_string()));
+
+        let request = Request::get("/user/me")
+            .header(HOST, "auth.example.com")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(tenant_of(request).await, (StatusCode::OK, "default".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_tenant_prefix_is_resolved_and_stripped() {
+        let request = Request::get("/t/acme/user/me?x=1").body(Body::empty()).unwrap();
+        assert_eq!(tenant_of(request).await, (StatusCode::OK, "acme".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_tenant_prefix_is_not_stripped() {
+        let request = Request::get("/t/other/user/me").body(Body::empty()).unwrap();
+        assert_eq!(tenant_of(request).await.0, StatusCode::NOT_FOUND);
+    }
+}
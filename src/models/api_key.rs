@@ -0,0 +1,42 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// A personal API key, without its secret.
+#[derive(Serialize, FromRow)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub name: String,
+    /// Identifies the key in listings; keys start with `ak_<prefix>_`.
+    pub prefix: String,
+    /// Permissions the key may use, out of those the user has.
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A key as returned on creation, the only time its full value is shown.
+#[derive(Serialize)]
+pub struct CreatedApiKey {
+    pub key: String,
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+}
+
+/// A usable key looked up by its prefix.
+#[derive(FromRow)]
+pub struct ApiKeyRecord {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub secret_hash: String,
+    pub scopes: Vec<String>,
+}
+
+/// What a request made with an API key is allowed: the scopes of the key the
+/// user still holds a permission for.
+pub struct ApiKeyGrant {
+    pub user_id: Uuid,
+    pub permissions: Vec<String>,
+}
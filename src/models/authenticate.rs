@@ -1,5 +1,6 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
 use uuid::Uuid;
 
 #[derive(Serialize, Deserialize)]
@@ -9,7 +10,90 @@ pub struct ActivationToken {
     pub expires_at: DateTime<Utc>,
 }
 
+/// What a row in `verification_tokens` can be redeemed for.
+#[derive(Clone, Copy)]
+pub enum TokenPurpose {
+    Activation,
+    PasswordReset,
+    EmailChange,
+    MagicLink,
+}
+
+impl TokenPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenPurpose::Activation => "activation",
+            TokenPurpose::PasswordReset => "password_reset",
+            TokenPurpose::EmailChange => "email_change",
+            TokenPurpose::MagicLink => "magic_link",
+        }
+    }
+}
+
+#[derive(FromRow)]
+pub struct RefreshToken {
+    pub user_id: Uuid,
+    pub family_id: Uuid,
+}
+
+/// A refresh token that can still be redeemed, as reported by introspection.
+#[derive(FromRow)]
+pub struct ActiveRefreshToken {
+    pub user_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Outcome of a password login: tokens, or a challenge when MFA is enabled.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum LoginResponse {
+    Token(JwtToken),
+    MfaRequired(MfaChallenge),
+}
+
+#[derive(Serialize)]
+pub struct MfaChallenge {
+    pub mfa_required: bool,
+    pub challenge_token: String,
+    pub expires_in: i64,
+}
+
+#[derive(FromRow)]
+pub struct MfaChallengeRecord {
+    pub id: Uuid,
+    pub user_id: Uuid,
+}
+
+#[derive(Serialize)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+/// Shown to the user once; only hashes are kept.
+#[derive(Serialize)]
+pub struct RecoveryCodes {
+    pub codes: Vec<String>,
+}
+
+#[derive(FromRow)]
+pub struct EmailLoginCode {
+    pub id: Uuid,
+    pub code_hash: String,
+}
+
+#[derive(FromRow)]
+pub struct RecoveryCodeRecord {
+    pub id: Uuid,
+    pub code_hash: String,
+}
+
 #[derive(Serialize)]
 pub struct JwtToken {
-    pub token: String,
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    pub refresh_token: String,
+    pub refresh_expires_in: i64,
 }
@@ -0,0 +1,42 @@
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sqlx::FromRow;
+
+#[derive(Clone, Debug)]
+pub struct ProviderEndpoints {
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+}
+
+/// The subset of an OpenID Provider's discovery document federation relies on.
+#[derive(Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ProviderTokenResponse {
+    pub access_token: String,
+}
+
+/// An account at an identity provider, as read from its userinfo endpoint.
+#[derive(Debug)]
+pub struct ExternalIdentity {
+    pub subject: String,
+    pub email: Option<String>,
+    pub email_verified: bool,
+    pub username: Option<String>,
+}
+
+#[derive(FromRow)]
+pub struct FederationState {
+    /// Tenant the login was started in, which the user is resolved in.
+    pub tenant_id: String,
+    pub provider: String,
+    pub code_verifier: String,
+    pub expires_at: DateTime<Utc>,
+}
@@ -1,5 +1,12 @@
+pub mod api_key;
 pub mod authenticate;
 pub mod request;
 pub mod response;
 pub mod user;
 pub mod claims;
+pub mod federation;
+pub mod oauth;
+pub mod organization;
+pub mod passkey;
+pub mod role;
+pub mod session;
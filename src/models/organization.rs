@@ -0,0 +1,121 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::fmt;
+use uuid::Uuid;
+
+/// Role of a user within an organization, ordered from least to most privileged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrganizationRole {
+    Member,
+    Admin,
+    Owner,
+}
+
+impl OrganizationRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrganizationRole::Member => "member",
+            OrganizationRole::Admin => "admin",
+            OrganizationRole::Owner => "owner",
+        }
+    }
+}
+
+impl TryFrom<String> for OrganizationRole {
+    type Error = UnknownOrganizationRole;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "member" => Ok(OrganizationRole::Member),
+            "admin" => Ok(OrganizationRole::Admin),
+            "owner" => Ok(OrganizationRole::Owner),
+            _ => Err(UnknownOrganizationRole(value)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownOrganizationRole(String);
+
+impl fmt::Display for UnknownOrganizationRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown organization role {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownOrganizationRole {}
+
+/// An organization as seen by one of its members.
+#[derive(Serialize, FromRow)]
+pub struct Organization {
+    pub id: Uuid,
+    pub name: String,
+    #[sqlx(try_from = "String")]
+    pub role: OrganizationRole,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, FromRow)]
+pub struct OrganizationMember {
+    pub user_id: Uuid,
+    pub username: String,
+    pub email: String,
+    #[sqlx(try_from = "String")]
+    pub role: OrganizationRole,
+    pub joined_at: DateTime<Utc>,
+}
+
+/// An invitation still waiting to be accepted.
+#[derive(Serialize, FromRow)]
+pub struct OrganizationInvitation {
+    pub id: Uuid,
+    pub email: String,
+    #[sqlx(try_from = "String")]
+    pub role: OrganizationRole,
+    pub invited_by: Option<Uuid>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An invitation looked up by its token, with the tenant of its organization.
+#[derive(FromRow)]
+pub struct InvitationRecord {
+    pub id: Uuid,
+    pub organization_id: Uuid,
+    pub tenant_id: String,
+    pub email: String,
+    #[sqlx(try_from = "String")]
+    pub role: OrganizationRole,
+    pub expires_at: DateTime<Utc>,
+    pub accepted_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// The organization a session acts in, as carried in its access tokens.
+#[derive(Debug, FromRow)]
+pub struct ActiveOrganization {
+    pub organization_id: Uuid,
+    #[sqlx(try_from = "String")]
+    pub role: OrganizationRole,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roles_are_ordered_by_privilege() {
+        assert!(OrganizationRole::Owner > OrganizationRole::Admin);
+        assert!(OrganizationRole::Admin > OrganizationRole::Member);
+    }
+
+    #[test]
+    fn test_role_round_trips_through_its_name() {
+        for role in [OrganizationRole::Member, OrganizationRole::Admin, OrganizationRole::Owner] {
+            assert_eq!(OrganizationRole::try_from(role.as_str().to_string()).unwrap(), role);
+        }
+        assert!(OrganizationRole::try_from("superuser".to_string()).is_err());
+    }
+}
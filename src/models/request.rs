@@ -1,3 +1,5 @@
+use crate::models::organization::OrganizationRole;
+use crate::models::passkey::RegistrationCredential;
 use serde::Deserialize;
 use uuid::Uuid;
 use validator::{Validate, ValidationError};
@@ -42,6 +44,14 @@ pub struct Token {
     pub token: String,
 }
 
+#[derive(Deserialize, Debug, Validate)]
+pub struct FederatedCallback {
+    #[validate(length(min = 1, message = "State is required"))]
+    pub state: String,
+    #[validate(length(min = 1, message = "Code is required"))]
+    pub code: String,
+}
+
 #[derive(Deserialize, Debug, Validate)]
 pub struct ResendToken {
     pub user_id: Uuid,
@@ -52,6 +62,161 @@ pub struct Login {
     pub identity: String,
     pub password: String,
 }
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct EmailLoginRequest {
+    #[validate(email(message = "Invalid email format"))]
+    pub email: String,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct EmailCodeLogin {
+    #[validate(email(message = "Invalid email format"))]
+    pub email: String,
+    #[validate(length(equal = 6, message = "Code must be 6 digits"))]
+    pub code: String,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct Refresh {
+    pub refresh_token: String,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct MfaLogin {
+    pub challenge_token: String,
+    /// Either a TOTP code or a recovery code must be given.
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct TotpCode {
+    pub code: String,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct PasskeyRegistration {
+    #[validate(length(max = 100, message = "Name must be at most 100 characters"))]
+    pub name: Option<String>,
+    pub credential: RegistrationCredential,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct CreateOAuthClient {
+    #[validate(length(min = 1, max = 100, message = "Name must be between 1 and 100 characters"))]
+    pub name: String,
+    #[serde(default)]
+    pub redirect_uris: Vec<String>,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    /// Public clients get no secret and can only use the authorization code flow.
+    #[serde(default = "default_confidential")]
+    pub confidential: bool,
+}
+
+fn default_confidential() -> bool {
+    true
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct CreateRole {
+    #[validate(length(min = 1, max = 64, message = "Name must be between 1 and 64 characters"))]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Names of existing permissions granted by the role.
+    #[serde(default)]
+    pub permissions: Vec<String>,
+}
+
+/// Query of the admin user listing.
+#[derive(Deserialize, Debug, Validate)]
+pub struct ListUsers {
+    #[serde(default = "default_page")]
+    #[validate(range(min = 1, message = "Page must be at least 1"))]
+    pub page: i64,
+    #[serde(default = "default_per_page")]
+    #[validate(range(min = 1, max = 100, message = "Page size must be between 1 and 100"))]
+    pub per_page: i64,
+    /// Matched against email and username, case-insensitively.
+    pub search: Option<String>,
+}
+
+fn default_page() -> i64 {
+    1
+}
+
+fn default_per_page() -> i64 {
+    20
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct CreateApiKey {
+    #[validate(length(min = 1, max = 100, message = "Name must be between 1 and 100 characters"))]
+    pub name: String,
+    /// Names of permissions the key may use; a key without scopes only identifies the user.
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    /// Days until the key expires; keys without one last until revoked.
+    #[validate(range(min = 1, max = 3650, message = "Expiry must be between 1 and 3650 days"))]
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct CreateOrganization {
+    #[validate(length(min = 1, max = 100, message = "Name must be between 1 and 100 characters"))]
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct InviteMember {
+    #[validate(email(message = "Invalid email format"))]
+    pub email: String,
+    #[serde(default = "default_invitation_role")]
+    pub role: OrganizationRole,
+}
+
+fn default_invitation_role() -> OrganizationRole {
+    OrganizationRole::Member
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UpdateMemberRole {
+    pub role: OrganizationRole,
+}
+
+/// The organization to act in from now on, or `null` for none.
+#[derive(Deserialize, Debug)]
+pub struct SwitchOrganization {
+    pub organization_id: Option<Uuid>,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct ForgotPassword {
+    #[validate(email(message = "Invalid email format"))]
+    pub email: String,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct ChangePassword {
+    pub current_password: String,
+    #[validate(custom(function = "validate_password"))]
+    pub new_password: String,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct ChangeEmail {
+    #[validate(email(message = "Invalid email format"))]
+    pub new_email: String,
+}
+
+#[derive(Deserialize, Debug, Validate)]
+pub struct ResetPassword {
+    pub token: String,
+    #[validate(custom(function = "validate_password"))]
+    pub password: String,
+}
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -99,6 +264,28 @@ mod tests {
         assert!(errors.contains_key("username"));
     }
 
+    #[test]
+    fn test_reset_password_validates_password() {
+        let payload = ResetPassword {
+            token: "token".into(),
+            password: "weak".into(),
+        };
+
+        let result = payload.validate();
+        assert!(result.is_err());
+        assert!(result.unwrap_err().field_errors().contains_key("password"));
+    }
+
+    #[test]
+    fn test_change_email_requires_valid_email() {
+        let payload = ChangeEmail {
+            new_email: "not-an-email".into(),
+        };
+
+        let result = payload.validate();
+        assert!(result.unwrap_err().field_errors().contains_key("new_email"));
+    }
+
     #[test]
     fn test_password_too_short() {
         let result = validate_password("A1@bc");
@@ -133,4 +320,20 @@ mod tests {
         assert!(result.is_err());
         assert_eq!(result.unwrap_err().code, "password_no_special_char");
     }
+
+    #[test]
+    fn test_list_users_defaults_and_limits() {
+        let query: ListUsers = serde_json::from_str(r#"{"search": "alice"}"#).unwrap();
+        assert_eq!((query.page, query.per_page), (1, 20));
+        assert!(query.validate().is_ok());
+
+        let query = ListUsers {
+            page: 0,
+            per_page: 500,
+            search: None,
+        };
+        let errors = query.validate().unwrap_err();
+        assert!(errors.field_errors().contains_key("page"));
+        assert!(errors.field_errors().contains_key("per_page"));
+    }
 }
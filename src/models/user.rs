@@ -1,16 +1,31 @@
 use chrono::{DateTime, Utc};
-use serde::{Serialize, Serializer};
+use serde::Serialize;
 use sqlx::FromRow;
 use uuid::Uuid;
 
 #[derive(Serialize, FromRow)]
 pub struct User {
     pub id: Uuid,
+    pub tenant_id: String,
     pub email: String,
     #[serde(skip_serializing)]
     pub password_hash: String,
     pub username: String,
+    pub pending_email: Option<String>,
+    #[serde(skip_serializing)]
+    pub totp_secret: Option<String>,
+    pub totp_enabled_at: Option<DateTime<Utc>>,
     pub is_active: bool,
+    /// Whether the email address was confirmed, by its owner or an administrator.
+    pub is_verified: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
+
+#[derive(Serialize)]
+pub struct UserPage {
+    pub users: Vec<User>,
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+}
@@ -0,0 +1,106 @@
+use crate::AppState;
+use crate::handlers::admin::{
+    activate_user, assign_role, create_oauth_client, create_role, deactivate_user, delete_user, get_user,
+    list_oauth_clients, list_permissions, list_roles, list_user_roles, list_users, remove_role, reset_user_password,
+    revoke_oauth_client, verify_user,
+};
+use crate::middleware::permission::RequirePermission;
+use axum::Router;
+use axum::routing::{delete, get, post, put};
+use std::sync::Arc;
+
+pub fn router(state: Arc<AppState>) -> Router {
+    let oauth_clients = Router::new()
+        .route("/oauth/clients", get(list_oauth_clients).post(create_oauth_client))
+        .route("/oauth/clients/{client_id}", delete(revoke_oauth_client))
+        .route_layer(RequirePermission::new(state.clone(), "oauth_clients:manage"));
+    let roles = Router::new()
+        .route("/roles", get(list_roles).post(create_role))
+        .route("/permissions", get(list_permissions))
+        .route("/users/{user_id}/roles", get(list_user_roles))
+        .route("/users/{user_id}/roles/{role}", put(assign_role).delete(remove_role))
+        .route_layer(RequirePermission::new(state.clone(), "roles:manage"));
+    let users = Router::new()
+        .route("/users", get(list_users))
+        .route("/users/{user_id}", get(get_user).delete(delete_user))
+        .route("/users/{user_id}/activate", post(activate_user))
+        .route("/users/{user_id}/deactivate", post(deactivate_user))
+        .route("/users/{user_id}/verify", post(verify_user))
+        .route("/users/{user_id}/password-reset", post(reset_user_password))
+        .route_layer(RequirePermission::new(state.clone(), "users:manage"));
+
+    oauth_clients.merge(roles).merge(users).with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state::{test_access_token, test_state_with_pool};
+    use crate::extractors::tenant::Tenant;
+    use axum::body::Body;
+    use axum::extract::Request;
+    use http::header::AUTHORIZATION;
+    use http::{Method, StatusCode};
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+    use uuid::Uuid;
+
+    async fn status(app: &Router, token: &str, method: Method, uri: &str, tenant: &str) -> StatusCode {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(AUTHORIZATION, format!("Bearer {}", token))
+            .extension(Tenant(tenant.to_string()))
+            .body(Body::empty())
+            .unwrap();
+        app.clone().oneshot(request).await.unwrap().status()
+    }
+
+    #[sqlx::test]
+    async fn test_users_of_another_tenant_are_not_found(pool: PgPool) {
+        let user_id = Uuid::new_v4();
+        sqlx::query(
+            r#"
+            INSERT INTO users (id, tenant_id, username, email, password_hash, is_active)
+            VALUES ($1, 'acme', 'bob', 'bob@example.com', 'hash', true)
+            "#,
+        )
+        .bind(user_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+        let state = Arc::new(test_state_with_pool(pool.clone()));
+        let admin = test_access_token(&state, &pool, "default", Some("admin")).await;
+        let acme_admin = test_access_token(&state, &pool, "acme", Some("admin")).await;
+        let app = router(state);
+
+        let requests = [
+            (Method::GET, format!("/users/{}", user_id)),
+            (Method::POST, format!("/users/{}/activate", user_id)),
+            (Method::POST, format!("/users/{}/deactivate", user_id)),
+            (Method::POST, format!("/users/{}/verify", user_id)),
+            (Method::POST, format!("/users/{}/password-reset", user_id)),
+            (Method::GET, format!("/users/{}/roles", user_id)),
+            (Method::PUT, format!("/users/{}/roles/admin", user_id)),
+            (Method::DELETE, format!("/users/{}/roles/admin", user_id)),
+            (Method::DELETE, format!("/users/{}", user_id)),
+        ];
+        for (method, uri) in requests {
+            assert_eq!(status(&app, &admin, method, &uri, "default").await, StatusCode::NOT_FOUND, "{}", uri);
+        }
+
+        let (is_active, is_verified, roles) = sqlx::query_as::<_, (bool, bool, i64)>(
+            "SELECT is_active, is_verified, (SELECT COUNT(*) FROM user_roles WHERE user_id = $1) FROM users WHERE id = $1",
+        )
+        .bind(user_id)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert!(is_active);
+        assert!(!is_verified);
+        assert_eq!(roles, 0);
+
+        let uri = format!("/users/{}", user_id);
+        assert_eq!(status(&app, &acme_admin, Method::GET, &uri, "acme").await, StatusCode::OK);
+    }
+}
@@ -0,0 +1,12 @@
+use crate::AppState;
+use crate::handlers::api_keys::{create_api_key, list_api_keys, revoke_api_key};
+use axum::Router;
+use axum::routing::{delete, get};
+use std::sync::Arc;
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/", get(list_api_keys).post(create_api_key))
+        .route("/{api_key_id}", delete(revoke_api_key))
+        .with_state(state)
+}
@@ -1,8 +1,14 @@
 use crate::AppState;
-use crate::handlers::authentication::{login, register_user, resend_token, verify_user};
-use crate::services::authentication::Authentication as AuthenticationService;
+use crate::handlers::authentication::{
+    confirm_email_change, forgot_password, login, login_email_code, login_magic_link, login_mfa,
+    logout, refresh_token, register_user, request_email_code, request_magic_link, resend_token, reset_password,
+    verify_user,
+};
+use crate::handlers::mfa::{confirm_totp, enroll_totp, regenerate_recovery_codes};
+use crate::handlers::user::{change_email, change_password, me};
+use crate::middleware::rate_limit::RateLimitLayer;
 use axum::Router;
-use axum::routing::post;
+use axum::routing::{get, post};
 use std::sync::Arc;
 
 pub fn router(state: Arc<AppState>) -> Router {
@@ -11,5 +17,80 @@ pub fn router(state: Arc<AppState>) -> Router {
         .route("/verify", post(verify_user))
         .route("/resend-token", post(resend_token))
         .route("/login", post(login))
+        .route("/login/mfa", post(login_mfa))
+        .route("/login/email-code", post(request_email_code))
+        .route("/login/email-code/verify", post(login_email_code))
+        .route("/login/magic-link", post(request_magic_link))
+        .route("/login/magic-link/verify", post(login_magic_link))
+        .route("/token/refresh", post(refresh_token))
+        .route("/logout", post(logout))
+        .route("/me", get(me))
+        .route("/password/forgot", post(forgot_password))
+        .route("/password/reset", post(reset_password))
+        .route("/password/change", post(change_password))
+        .route("/email/change", post(change_email))
+        .route("/email/confirm", post(confirm_email_change))
+        .route("/mfa/totp/enroll", post(enroll_totp))
+        .route("/mfa/totp/confirm", post(confirm_totp))
+        .route("/mfa/recovery-codes", post(regenerate_recovery_codes))
+        .route_layer(RateLimitLayer::new(state.clone()))
         .with_state(state)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_state::test_state_with_pool;
+    use axum::body::{Body, to_bytes};
+    use axum::extract::Request;
+    use http::StatusCode;
+    use http::header::CONTENT_TYPE;
+    use sqlx::PgPool;
+    use std::time::Duration;
+    use tower::ServiceExt;
+    use uuid::Uuid;
+
+    async fn forgot_password(app: &Router, email: &str) -> (StatusCode, String) {
+        let request = Request::post("/password/forgot")
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(format!(r#"{{"email":"{}"}}"#, email)))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[sqlx::test]
+    async fn test_forgot_password_answers_alike_and_emails_in_background(pool: PgPool) {
+        let user_id = Uuid::new_v4();
+        sqlx::query(
+            "INSERT INTO users (id, username, email, password_hash, is_active) VALUES ($1, 'bob', 'bob@example.com', '', true)",
+        )
+        .bind(user_id)
+        .execute(&pool)
+        .await
+        .unwrap();
+        let app = router(Arc::new(test_state_with_pool(pool.clone())));
+
+        let known = forgot_password(&app, "bob@example.com").await;
+        let unknown = forgot_password(&app, "nobody@example.com").await;
+        assert_eq!(known.0, StatusCode::OK);
+        assert_eq!(known, unknown);
+
+        // The reset token is issued by the task sending the email, after the response.
+        let mut issued = 0;
+        for _ in 0..50 {
+            issued = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM verification_tokens WHERE user_id = $1")
+                .bind(user_id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            if issued > 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(issued, 1);
+    }
+}
@@ -0,0 +1,13 @@
+use crate::AppState;
+use crate::handlers::federation::{begin_login, list_providers, login_callback};
+use axum::Router;
+use axum::routing::{get, post};
+use std::sync::Arc;
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/", get(list_providers))
+        .route("/callback", post(login_callback))
+        .route("/{provider}", get(begin_login))
+        .with_state(state)
+}
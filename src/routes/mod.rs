@@ -1,3 +1,11 @@
+pub mod admin;
+pub mod api_keys;
 pub mod authentication;
 pub mod error;
+pub mod federation;
 pub mod health;
+pub mod oauth;
+pub mod organizations;
+pub mod passkeys;
+pub mod sessions;
+pub mod well_known;
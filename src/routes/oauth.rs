@@ -0,0 +1,15 @@
+use crate::AppState;
+use crate::handlers::oauth::{approve, authorize, introspect, revoke, token, userinfo};
+use axum::Router;
+use axum::routing::{get, post};
+use std::sync::Arc;
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/authorize", get(authorize).post(approve))
+        .route("/token", post(token))
+        .route("/introspect", post(introspect))
+        .route("/revoke", post(revoke))
+        .route("/userinfo", get(userinfo))
+        .with_state(state)
+}
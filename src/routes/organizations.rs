@@ -0,0 +1,25 @@
+use crate::AppState;
+use crate::handlers::organizations::{
+    accept_invitation, create_organization, delete_organization, get_organization, invite_member,
+    list_invitations, list_members, list_organizations, remove_member, revoke_invitation, switch_organization,
+    update_member_role,
+};
+use axum::Router;
+use axum::routing::{delete, get, post, put};
+use std::sync::Arc;
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/", get(list_organizations).post(create_organization))
+        .route("/active", post(switch_organization))
+        .route("/invitations/accept", post(accept_invitation))
+        .route("/{organization_id}", get(get_organization).delete(delete_organization))
+        .route("/{organization_id}/members", get(list_members))
+        .route(
+            "/{organization_id}/members/{user_id}",
+            put(update_member_role).delete(remove_member),
+        )
+        .route("/{organization_id}/invitations", get(list_invitations).post(invite_member))
+        .route("/{organization_id}/invitations/{invitation_id}", delete(revoke_invitation))
+        .with_state(state)
+}
@@ -0,0 +1,19 @@
+use crate::AppState;
+use crate::handlers::passkeys::{
+    begin_authentication, begin_registration, delete_passkey, finish_authentication, finish_registration,
+    list_passkeys,
+};
+use axum::Router;
+use axum::routing::{delete, get, post};
+use std::sync::Arc;
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/", get(list_passkeys))
+        .route("/{id}", delete(delete_passkey))
+        .route("/register/begin", post(begin_registration))
+        .route("/register/finish", post(finish_registration))
+        .route("/login/begin", post(begin_authentication))
+        .route("/login/finish", post(finish_authentication))
+        .with_state(state)
+}
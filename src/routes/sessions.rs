@@ -0,0 +1,13 @@
+use crate::AppState;
+use crate::handlers::sessions::{list_sessions, revoke_other_sessions, revoke_session};
+use axum::Router;
+use axum::routing::{delete, get, post};
+use std::sync::Arc;
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/", get(list_sessions))
+        .route("/{id}", delete(revoke_session))
+        .route("/revoke-others", post(revoke_other_sessions))
+        .with_state(state)
+}
@@ -0,0 +1,12 @@
+use crate::AppState;
+use crate::handlers::well_known::{jwks, openid_configuration};
+use axum::Router;
+use axum::routing::get;
+use std::sync::Arc;
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/jwks.json", get(jwks))
+        .route("/openid-configuration", get(openid_configuration))
+        .with_state(state)
+}
@@ -0,0 +1,215 @@
+use crate::error::api_key::ApiKeyError;
+use crate::models::api_key::{ApiKey, ApiKeyGrant, ApiKeyRecord, CreatedApiKey};
+use crate::models::request::CreateApiKey;
+use crate::utils::security::{generate_token, hash_password, verify_password};
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+use tracing::{error, info};
+use uuid::Uuid;
+
+/// Start of every API key, which tells them apart from access tokens.
+pub const API_KEY_PREFIX: &str = "ak_";
+const PREFIX_LENGTH: usize = 12;
+
+pub struct ApiKeys {
+    pool: PgPool,
+}
+
+impl ApiKeys {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Creates a key for the user, returning the only copy of its secret.
+    pub async fn create_api_key(&self, user_id: &Uuid, request: &CreateApiKey) -> Result<CreatedApiKey, ApiKeyError> {
+        let known = sqlx::query_scalar::<_, String>("SELECT name FROM permissions WHERE name = ANY($1)")
+            .bind(&request.scopes)
+            .fetch_all(&self.pool)
+            .await;
+        let known = match known {
+            Ok(known) => known,
+            Err(e) => {
+                error!("Failed to fetch permissions: {}", e);
+                return Err(ApiKeyError::InternalServerError);
+            }
+        };
+        if let Some(unknown) = request.scopes.iter().find(|scope| !known.contains(scope)) {
+            return Err(ApiKeyError::UnknownScope(unknown.clone()));
+        }
+
+        let prefix = Uuid::new_v4().simple().to_string()[..PREFIX_LENGTH].to_string();
+        let secret = generate_token();
+        let secret_hash = hash_password(&secret).map_err(|_| ApiKeyError::InternalServerError)?;
+        let mut scopes = request.scopes.clone();
+        scopes.sort();
+        scopes.dedup();
+
+        match sqlx::query_as::<_, ApiKey>(
+            r#"
+            INSERT INTO api_keys (id, user_id, name, prefix, secret_hash, scopes, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id, name, prefix, scopes, expires_at, last_used_at, created_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(request.name.trim())
+        .bind(&prefix)
+        .bind(secret_hash)
+        .bind(&scopes)
+        .bind(request.expires_in_days.map(|days| Utc::now() + Duration::days(days)))
+        .fetch_one(&self.pool)
+        .await
+        {
+            Ok(api_key) => {
+                info!("API key {} created for user {}", api_key.id, user_id);
+                Ok(CreatedApiKey {
+                    key: format!("{}{}_{}", API_KEY_PREFIX, prefix, secret),
+                    api_key,
+                })
+            }
+            Err(e) => {
+                error!("Failed to save API key for user {}: {}", user_id, e);
+                Err(ApiKeyError::InternalServerError)
+            }
+        }
+    }
+
+    /// Keys of the user that were not revoked, including expired ones.
+    pub async fn list_api_keys(&self, user_id: &Uuid) -> Result<Vec<ApiKey>, ApiKeyError> {
+        match sqlx::query_as::<_, ApiKey>(
+            r#"
+            SELECT id, name, prefix, scopes, expires_at, last_used_at, created_at FROM api_keys
+            WHERE user_id = $1 AND revoked_at IS NULL
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        {
+            Ok(api_keys) => Ok(api_keys),
+            Err(e) => {
+                error!("Failed to list API keys of user {}: {}", user_id, e);
+                Err(ApiKeyError::InternalServerError)
+            }
+        }
+    }
+
+    pub async fn revoke_api_key(&self, user_id: &Uuid, api_key_id: &Uuid) -> Result<(), ApiKeyError> {
+        match sqlx::query(
+            "UPDATE api_keys SET revoked_at = NOW() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL",
+        )
+        .bind(api_key_id)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(res) if res.rows_affected() == 0 => Err(ApiKeyError::ApiKeyNotFound),
+            Ok(_) => {
+                info!("API key {} of user {} revoked", api_key_id, user_id);
+                Ok(())
+            }
+            Err(e) => {
+                error!("Failed to revoke API key {}: {}", api_key_id, e);
+                Err(ApiKeyError::InternalServerError)
+            }
+        }
+    }
+
+    /// Checks a key presented with a request and records its use.
+    pub async fn authenticate(&self, key: &str) -> Result<ApiKeyGrant, ApiKeyError> {
+        let (prefix, secret) = parse_key(key).ok_or(ApiKeyError::InvalidApiKey)?;
+
+        let record = sqlx::query_as::<_, ApiKeyRecord>(
+            r#"
+            SELECT id, user_id, secret_hash, scopes FROM api_keys
+            WHERE prefix = $1 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > NOW())
+            "#,
+        )
+        .bind(prefix)
+        .fetch_optional(&self.pool)
+        .await;
+        let record = match record {
+            Ok(Some(record)) => record,
+            Ok(None) => return Err(ApiKeyError::InvalidApiKey),
+            Err(e) => {
+                error!("Failed to fetch API key: {}", e);
+                return Err(ApiKeyError::InternalServerError);
+            }
+        };
+        if !verify_password(secret, &record.secret_hash) {
+            return Err(ApiKeyError::InvalidApiKey);
+        }
+
+        // Roles may have been taken away since the key was created.
+        let permissions = sqlx::query_scalar::<_, Vec<String>>(
+            r#"
+            SELECT COALESCE(array_agg(DISTINCT p.name ORDER BY p.name), '{}')
+            FROM user_roles ur
+            JOIN role_permissions rp ON rp.role_id = ur.role_id
+            JOIN permissions p ON p.id = rp.permission_id
+            WHERE ur.user_id = $1 AND p.name = ANY($2)
+            "#,
+        )
+        .bind(record.user_id)
+        .bind(&record.scopes)
+        .fetch_one(&self.pool)
+        .await;
+        let permissions = match permissions {
+            Ok(permissions) => permissions,
+            Err(e) => {
+                error!("Failed to fetch permissions of user {}: {}", record.user_id, e);
+                return Err(ApiKeyError::InternalServerError);
+            }
+        };
+
+        // Only written about once a minute, so busy keys do not write on every request.
+        if let Err(e) = sqlx::query(
+            r#"
+            UPDATE api_keys SET last_used_at = NOW()
+            WHERE id = $1 AND (last_used_at IS NULL OR last_used_at < NOW() - INTERVAL '1 minute')
+            "#,
+        )
+        .bind(record.id)
+        .execute(&self.pool)
+        .await
+        {
+            error!("Failed to record use of API key {}: {}", record.id, e);
+        }
+
+        Ok(ApiKeyGrant {
+            user_id: record.user_id,
+            permissions,
+        })
+    }
+}
+
+/// Splits `ak_<prefix>_<secret>` into its prefix and secret.
+fn parse_key(key: &str) -> Option<(&str, &str)> {
+    let (prefix, secret) = key.strip_prefix(API_KEY_PREFIX)?.split_once('_')?;
+    let valid = prefix.len() == PREFIX_LENGTH && prefix.bytes().all(|b| b.is_ascii_hexdigit()) && !secret.is_empty();
+    valid.then_some((prefix, secret))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_is_split_into_prefix_and_secret() {
+        assert_eq!(
+            parse_key("ak_0123456789ab_se_cr-et"),
+            Some(("0123456789ab", "se_cr-et"))
+        );
+    }
+
+    #[test]
+    fn test_malformed_keys_are_rejected() {
+        assert_eq!(parse_key("0123456789ab_secret"), None);
+        assert_eq!(parse_key("ak_0123456789ab"), None);
+        assert_eq!(parse_key("ak_0123456789ab_"), None);
+        assert_eq!(parse_key("ak_short_secret"), None);
+        assert_eq!(parse_key("ak_0123456789xy_secret"), None);
+    }
+}
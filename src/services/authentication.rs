@@ -1,45 +1,81 @@
 use crate::config::Config;
 use crate::error::authentication::AuthenticationError;
-use crate::models::authenticate::ActivationToken;
-use crate::models::request::RegisterUser;
+use crate::models::authenticate::{
+    ActivationToken, ActiveRefreshToken, EmailLoginCode, JwtToken, LoginResponse, MfaChallenge, MfaChallengeRecord,
+    RefreshToken, TokenPurpose,
+};
+use crate::extractors::client_context::ClientContext;
 use crate::services::email::EmailService;
-use crate::services::traits::EmailServiceBase;
-use crate::utils::security::{hash_password, verify_password};
-use chrono::{Duration, Utc};
-use sqlx::{AnyPool, Error, PgPool, Row};
+use crate::services::traits::{EmailServiceBase, TokenDenylist};
+use crate::utils::security::{generate_numeric_code, generate_token, hash_password, hash_token, verify_password};
+use crate::utils::user_agent::describe_device;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{Error, PgConnection, PgPool, Row};
 use std::sync::Arc;
-use tracing::info;
+use tracing::{info, warn};
 use tracing::log::error;
-use tracing_subscriber::fmt::format;
 use uuid::Uuid;
-use crate::models::claims::Claims;
+use crate::models::claims::{Claims, IdTokenClaims};
+use crate::models::organization::ActiveOrganization;
+use crate::models::role::UserAccess;
+use crate::models::session::Session;
 use crate::models::user::User;
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use crate::services::jwt_keys::JwtKeyring;
+use jsonwebtoken::Algorithm;
+use jsonwebtoken::jwk::JwkSet;
+
+const MFA_CHALLENGE_TTL_SECONDS: i64 = 300;
+const MFA_CHALLENGE_MAX_ATTEMPTS: i32 = 5;
+const EMAIL_LOGIN_CODE_TTL_SECONDS: i64 = 600;
+const EMAIL_LOGIN_CODE_MAX_ATTEMPTS: i32 = 5;
+const MAGIC_LINK_TTL_MINUTES: i64 = 15;
+
+/// How long logins are blocked after `failures` consecutive failures: doubling
+/// from `base_seconds`, and `lockout_seconds` once `threshold` is reached.
+fn login_block(failures: u32, threshold: u32, base_seconds: i64, lockout_seconds: i64) -> Duration {
+    if failures >= threshold {
+        return Duration::seconds(lockout_seconds);
+    }
+    let backoff = base_seconds.saturating_mul(1 << failures.saturating_sub(1).min(32));
+    Duration::seconds(backoff.min(lockout_seconds))
+}
+
+/// Deactivated accounts, and those not activated yet, get no tokens by any
+/// login method nor by refreshing tokens they already had.
+fn ensure_active(user: &User) -> Result<(), AuthenticationError> {
+    if !user.is_active {
+        return Err(AuthenticationError::AccountInactive);
+    }
+    Ok(())
+}
+
 pub struct Authentication {
     pool: PgPool,
     config: Arc<Config>,
+    keyring: JwtKeyring,
+    denylist: Arc<dyn TokenDenylist>,
 }
 
 impl Authentication {
-    pub fn new(pool: PgPool, config: Arc<Config>) -> Self {
-        Self { pool, config }
+    pub fn new(pool: PgPool, config: Arc<Config>, keyring: JwtKeyring, denylist: Arc<dyn TokenDenylist>) -> Self {
+        Self { pool, config, keyring, denylist }
     }
 
     pub async fn send_activation_token(
         &self,
         email_service: &EmailService,
-        user_id: Uuid,
+        user: &User,
     ) -> Result<(), AuthenticationError> {
-        info!("Sending activation token for user {}", user_id);
+        info!("Sending activation token for user {}", user.id);
         let activation_token = ActivationToken {
-            user_id,
+            user_id: user.id,
             token: Uuid::new_v4().to_string(),
             expires_at: Utc::now() + Duration::days(15),
         };
 
         let verify_url = format!(
             "{}?token={}",
-            self.config.app.verification_url, activation_token.token
+            self.config.app_for(&user.tenant_id).verification_url, activation_token.token
         );
         match self.save_activation_token(&activation_token).await {
             Ok(_) => {}
@@ -62,7 +98,8 @@ impl Authentication {
 
         let _ = email_service
             .send_email(
-                "test@example.com".parse().unwrap(),
+                &user.tenant_id,
+                user.email.clone(),
                 vec![],
                 vec![],
                 "Account Activation".parse().unwrap(),
@@ -77,10 +114,11 @@ impl Authentication {
         let result = sqlx::query(
             r#"
                     SELECT * FROM verification_tokens
-                    WHERE token = $1 AND expires_at > NOW()
+                    WHERE token = $1 AND purpose = $2 AND expires_at > NOW()
                     "#,
         )
         .bind(token)
+        .bind(TokenPurpose::Activation.as_str())
         .fetch_optional(&self.pool)
         .await;
 
@@ -105,87 +143,1811 @@ impl Authentication {
     pub async fn resend_activation_token(
         &self,
         email_service: &EmailService,
-        user_id: &Uuid,
+        user: &User,
     ) -> Result<(), AuthenticationError> {
-        self.remove_old_activation_token(user_id).await;
-        self.send_activation_token(email_service, user_id.clone())
+        self.remove_old_activation_token(&user.id).await;
+        self.send_activation_token(email_service, user)
             .await
     }
 
-    pub async fn login(&self, user: User, password: String) -> Result<String, AuthenticationError> {
-        if !verify_password(&password, &user.password_hash) {
-            return Err(AuthenticationError::InternalServerError)
+    pub async fn send_password_reset_token(
+        &self,
+        email_service: &EmailService,
+        user: &User,
+    ) -> Result<(), AuthenticationError> {
+        info!("Sending password reset token for user {}", user.id);
+        let token = self
+            .issue_verification_token(&user.id, TokenPurpose::PasswordReset, Duration::hours(1))
+            .await?;
+
+        let reset_url = format!("{}?token={}", self.config.app_for(&user.tenant_id).password_reset_url, token);
+        let template_string = format!(
+            r#"
+            Hello {},
+
+            We received a request to reset your password. Click the link below to choose a new one:
+            <a href={}>reset password</a>
+
+            The link expires in one hour. If you did not request this, you can ignore this email.
+
+                Best regards,
+                Your App Team
+                "#,
+            user.username, reset_url,
+        );
+
+        let _ = email_service
+            .send_email(
+                &user.tenant_id,
+                user.email.clone(),
+                vec![],
+                vec![],
+                "Password Reset".to_string(),
+                template_string,
+            )
+            .await;
+
+        Ok(())
+    }
+
+    /// Sends a confirmation link to the pending address and a heads-up to the current one.
+    pub async fn send_email_change_token(
+        &self,
+        email_service: &EmailService,
+        user: &User,
+        new_email: &str,
+    ) -> Result<(), AuthenticationError> {
+        info!("Sending email change token for user {}", user.id);
+        let token = self
+            .issue_verification_token(&user.id, TokenPurpose::EmailChange, Duration::days(1))
+            .await?;
+
+        let confirm_url = format!("{}?token={}", self.config.app_for(&user.tenant_id).email_change_url, token);
+        let confirm_template = format!(
+            r#"
+            Hello {},
+
+            Please click the link below to confirm this address for your account:
+            <a href={}>confirm email</a>
+
+                Best regards,
+                Your App Team
+                "#,
+            user.username, confirm_url,
+        );
+        let _ = email_service
+            .send_email(
+                &user.tenant_id,
+                new_email.to_string(),
+                vec![],
+                vec![],
+                "Confirm your new email address".to_string(),
+                confirm_template,
+            )
+            .await;
+
+        let notice_template = format!(
+            r#"
+            Hello {},
+
+            A request was made to change the email address of your account to {}.
+            If this was not you, please change your password immediately.
+
+                Best regards,
+                Your App Team
+                "#,
+            user.username, new_email,
+        );
+        let _ = email_service
+            .send_email(
+                &user.tenant_id,
+                user.email.clone(),
+                vec![],
+                vec![],
+                "Email address change requested".to_string(),
+                notice_template,
+            )
+            .await;
+
+        Ok(())
+    }
+
+    /// Emails a single-use sign-in link, replacing any link still pending for the user.
+    pub async fn send_magic_link(
+        &self,
+        email_service: &EmailService,
+        user: &User,
+    ) -> Result<(), AuthenticationError> {
+        info!("Sending magic link for user {}", user.id);
+        let token = self
+            .issue_verification_token(
+                &user.id,
+                TokenPurpose::MagicLink,
+                Duration::minutes(MAGIC_LINK_TTL_MINUTES),
+            )
+            .await?;
+
+        let login_url = format!("{}?token={}", self.config.app_for(&user.tenant_id).magic_link_url, token);
+        let template_string = format!(
+            r#"
+            Hello {},
+
+            Click the link below to sign in:
+            <a href={}>sign in</a>
+
+            The link expires in {} minutes and can only be used once. If you did not try to sign in, you can ignore this email.
+
+                Best regards,
+                Your App Team
+                "#,
+            user.username, login_url, MAGIC_LINK_TTL_MINUTES,
+        );
+
+        let _ = email_service
+            .send_email(
+                &user.tenant_id,
+                user.email.clone(),
+                vec![],
+                vec![],
+                "Your sign-in link".to_string(),
+                template_string,
+            )
+            .await;
+
+        Ok(())
+    }
+
+    /// Redeems a single-use token, returning the id of the user it was issued to.
+    pub async fn consume_verification_token(
+        &self,
+        token: &str,
+        purpose: TokenPurpose,
+    ) -> Result<Uuid, AuthenticationError> {
+        let result = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            DELETE FROM verification_tokens
+            WHERE token = $1 AND purpose = $2 AND expires_at > NOW()
+            RETURNING user_id
+            "#,
+        )
+        .bind(hash_token(token))
+        .bind(purpose.as_str())
+        .fetch_optional(&self.pool)
+        .await;
+
+        match result {
+            Ok(Some(user_id)) => Ok(user_id),
+            Ok(None) => Err(AuthenticationError::InvalidToken),
+            Err(e) => {
+                error!("Failed to consume {} token: {}", purpose.as_str(), e);
+                Err(AuthenticationError::InternalServerError)
+            }
         }
+    }
 
-        let token = self.create_token(&user)?;
-        Ok(token)
+    /// Sets the password of the account a reset token was issued to and ends
+    /// all of its sessions. The token is only used up if all of it succeeds.
+    pub async fn reset_password(&self, token: &str, password: &str) -> Result<Uuid, AuthenticationError> {
+        let password_hash = hash_password(password).map_err(|_| AuthenticationError::InternalServerError)?;
+        let result: Result<Option<(Uuid, Vec<Uuid>)>, sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            let user_id = sqlx::query_scalar::<_, Uuid>(
+                r#"
+                DELETE FROM verification_tokens
+                WHERE token = $1 AND purpose = $2 AND expires_at > NOW()
+                RETURNING user_id
+                "#,
+            )
+            .bind(hash_token(token))
+            .bind(TokenPurpose::PasswordReset.as_str())
+            .fetch_optional(&mut *tx)
+            .await?;
+            let Some(user_id) = user_id else {
+                return Ok(None);
+            };
+            sqlx::query("UPDATE users SET password_hash = $1, updated_at = NOW() WHERE id = $2")
+                .bind(&password_hash)
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await?;
+            let revoked = revoke_session_rows(&mut tx, &user_id, None, None).await?;
+            tx.commit().await?;
+            Ok(Some((user_id, revoked)))
+        }
+        .await;
+
+        match result {
+            Ok(Some((user_id, revoked))) => {
+                self.deny_sessions(&revoked).await?;
+                info!("Password reset for user {}", user_id);
+                Ok(user_id)
+            }
+            Ok(None) => Err(AuthenticationError::InvalidToken),
+            Err(e) => {
+                error!("Failed to reset password: {}", e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
     }
 
-    fn create_token(&self, user: &User) -> Result<String, AuthenticationError> {
-        let expiration = Utc::now().checked_add_signed(Duration::days(self.config.jwt.expiration))
-            .expect("valid timestamp").timestamp() as usize;
-        let claims = Claims {
-            sub: user.username.clone(),
-            exp: expiration,
-            iat: Utc::now().timestamp() as usize,
-        };
+    /// Checks a password change requested by `user` before it is applied. A
+    /// wrong current password counts as a failed login, so a stolen session
+    /// cannot be used to guess the password.
+    pub async fn check_password_change(
+        &self,
+        user: &User,
+        current_password: &str,
+        new_password: &str,
+        client: &ClientContext,
+    ) -> Result<(), AuthenticationError> {
+        self.check_login_throttle(Some(&user.id), client).await?;
+        if !verify_password(current_password, &user.password_hash) {
+            self.record_failed_login(Some(&user.id), client).await?;
+            return Err(AuthenticationError::InvalidCredentials);
+        }
+        self.reset_failed_logins(&user.id).await?;
+        if verify_password(new_password, &user.password_hash) {
+            return Err(AuthenticationError::InvalidInput(
+                "New password must be different from the current password".to_string(),
+            ));
+        }
+        Ok(())
+    }
 
-        match encode(&Header::default(), &claims, &EncodingKey::from_secret(&self.config.jwt.secret.as_bytes())) {
-            Ok(token) => Ok(token),
-            Err(_) => Err(AuthenticationError::InternalServerError),
+    pub async fn send_password_changed_notification(&self, email_service: &EmailService, user: &User) {
+        let template_string = format!(
+            r#"
+            Hello {},
+
+            The password of your account was just changed. If this was not you,
+            please reset your password immediately and contact support.
+
+                Best regards,
+                Your App Team
+                "#,
+            user.username,
+        );
+
+        let _ = email_service
+            .send_email(
+                &user.tenant_id,
+                user.email.clone(),
+                vec![],
+                vec![],
+                "Your password was changed".to_string(),
+                template_string,
+            )
+            .await;
+    }
+
+    pub async fn login(
+        &self,
+        user: User,
+        password: String,
+        client: &ClientContext,
+    ) -> Result<LoginResponse, AuthenticationError> {
+        self.check_login_throttle(Some(&user.id), client).await?;
+        if !verify_password(&password, &user.password_hash) {
+            self.record_failed_login(Some(&user.id), client).await?;
+            return Err(AuthenticationError::InvalidCredentials)
         }
+
+        self.reset_failed_logins(&user.id).await?;
+        self.complete_login(&user, client).await
     }
 
-    async fn remove_old_activation_token(&self, user_id: &Uuid) {
-        let _ = sqlx::query(
+    /// Rejects a login attempt while the account, if known, or the client's
+    /// address is backing off or locked out after failed logins.
+    pub async fn check_login_throttle(
+        &self,
+        user_id: Option<&Uuid>,
+        client: &ClientContext,
+    ) -> Result<(), AuthenticationError> {
+        let blocks = sqlx::query_as::<_, (String, DateTime<Utc>, i32)>(
             r#"
-                    DELETE FROM verification_tokens
-                    WHERE user_id = $1
-                    "#,
+            SELECT scope, blocked_until, failures FROM login_failures
+            WHERE blocked_until > NOW()
+                AND ((scope = 'account' AND subject = $1) OR (scope = 'ip' AND subject = $2))
+            ORDER BY blocked_until DESC
+            "#,
         )
-        .bind(user_id)
-        .execute(&self.pool)
+        .bind(user_id.map(Uuid::to_string))
+        .bind(client.ip_address.as_deref())
+        .fetch_all(&self.pool)
         .await;
+        let blocks = match blocks {
+            Ok(blocks) => blocks,
+            Err(e) => {
+                error!("Failed to check login failures: {}", e);
+                return Err(AuthenticationError::InternalServerError);
+            }
+        };
+
+        let Some((_, blocked_until, _)) = blocks.first() else {
+            return Ok(());
+        };
+        let account_locked = blocks.iter().any(|(scope, _, failures)| {
+            scope == "account" && *failures as u32 >= self.config.auth.max_failed_logins
+        });
+        let retry_after = (*blocked_until - Utc::now()).num_seconds().max(0) as u64 + 1;
+        Err(AuthenticationError::LoginThrottled { retry_after, account_locked })
     }
 
-    async fn activate_user(&self, user_id: Uuid) -> Result<(), AuthenticationError> {
-        let res = sqlx::query!(
+    /// Counts a failed login against the account, if known, and the client's
+    /// address, blocking further attempts from either for a while.
+    pub async fn record_failed_login(
+        &self,
+        user_id: Option<&Uuid>,
+        client: &ClientContext,
+    ) -> Result<(), AuthenticationError> {
+        let auth = &self.config.auth;
+        if let Some(user_id) = user_id {
+            warn!("Failed login for user {}", user_id);
+            self.record_login_failure("account", &user_id.to_string(), auth.max_failed_logins).await?;
+        }
+        if let Some(ip_address) = &client.ip_address {
+            self.record_login_failure("ip", ip_address, auth.max_failed_logins_per_ip).await?;
+        }
+        Ok(())
+    }
+
+    async fn record_login_failure(&self, scope: &str, subject: &str, threshold: u32) -> Result<(), AuthenticationError> {
+        let auth = &self.config.auth;
+        let failures = sqlx::query_scalar::<_, i32>(
             r#"
-                    UPDATE users
-                    SET is_active = true
-                    WHERE id = $1
-                    "#,
-            user_id
+            INSERT INTO login_failures (scope, subject, failures, last_failed_at)
+            VALUES ($1, $2, 1, NOW())
+            ON CONFLICT (scope, subject) DO UPDATE
+            SET failures = CASE
+                    WHEN login_failures.last_failed_at < NOW() - make_interval(secs => $3) THEN 1
+                    ELSE login_failures.failures + 1
+                END,
+                last_failed_at = NOW()
+            RETURNING failures
+            "#,
         )
-        .execute(&self.pool)
+        .bind(scope)
+        .bind(subject)
+        .bind(auth.lockout_seconds as f64)
+        .fetch_one(&self.pool)
         .await;
-        match res {
+        let failures = match failures {
+            Ok(failures) => failures as u32,
+            Err(e) => {
+                error!("Failed to record login failure: {}", e);
+                return Err(AuthenticationError::InternalServerError);
+            }
+        };
+
+        let block = login_block(failures, threshold, auth.login_backoff_base_seconds, auth.lockout_seconds);
+        if failures == threshold {
+            warn!("Logins for {} {} locked after {} failures", scope, subject, failures);
+        }
+        match sqlx::query("UPDATE login_failures SET blocked_until = $3 WHERE scope = $1 AND subject = $2")
+            .bind(scope)
+            .bind(subject)
+            .bind(Utc::now() + block)
+            .execute(&self.pool)
+            .await
+        {
             Ok(_) => Ok(()),
             Err(e) => {
-                error!("Failed to activate user: {}", e);
+                error!("Failed to block logins for {} {}: {}", scope, subject, e);
                 Err(AuthenticationError::InternalServerError)
             }
         }
     }
 
-    async fn save_activation_token(&self, activation_token: &ActivationToken) -> Result<(), Error> {
+    /// Clears the account's failed logins after a successful one. Those of the
+    /// address are left to expire, or an attacker could clear them by logging
+    /// into an account of their own between guesses.
+    async fn reset_failed_logins(&self, user_id: &Uuid) -> Result<(), AuthenticationError> {
+        match sqlx::query("DELETE FROM login_failures WHERE scope = 'account' AND subject = $1")
+            .bind(user_id.to_string())
+            .execute(&self.pool)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("Failed to reset login failures for user {}: {}", user_id, e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    /// Finishes a login whose first factor has been checked, asking for MFA when enabled.
+    pub async fn complete_login(&self, user: &User, client: &ClientContext) -> Result<LoginResponse, AuthenticationError> {
+        ensure_active(user)?;
+        if user.totp_enabled_at.is_some() {
+            let challenge = self.create_mfa_challenge(&user.id).await?;
+            return Ok(LoginResponse::MfaRequired(challenge));
+        }
+
+        let token = self.start_session(user, client, None).await?;
+        Ok(LoginResponse::Token(token))
+    }
+
+    /// Emails a 6-digit login code, replacing any code still pending for the user.
+    pub async fn send_email_login_code(
+        &self,
+        email_service: &EmailService,
+        user: &User,
+    ) -> Result<(), AuthenticationError> {
+        info!("Sending email login code for user {}", user.id);
+        let code = generate_numeric_code(6);
+        self.save_email_login_code(&user.id, &code).await?;
+
+        let template_string = format!(
+            r#"
+            Hello {},
+
+            Your sign-in code is: <b>{}</b>
+
+            The code expires in {} minutes. If you did not try to sign in, you can ignore this email.
+
+                Best regards,
+                Your App Team
+                "#,
+            user.username,
+            code,
+            EMAIL_LOGIN_CODE_TTL_SECONDS / 60,
+        );
+
+        let _ = email_service
+            .send_email(
+                &user.tenant_id,
+                user.email.clone(),
+                vec![],
+                vec![],
+                "Your sign-in code".to_string(),
+                template_string,
+            )
+            .await;
+
+        Ok(())
+    }
+
+    /// Replaces the user's pending code. Attempts made on a code that has not
+    /// expired carry over, so requesting new codes gives no extra guesses.
+    async fn save_email_login_code(&self, user_id: &Uuid, code: &str) -> Result<(), AuthenticationError> {
+        let code_hash = match hash_password(code) {
+            Ok(hash) => hash,
+            Err(_) => return Err(AuthenticationError::InternalServerError),
+        };
+
         match sqlx::query(
             r#"
-            INSERT INTO verification_tokens (user_id, token, expires_at)
-            VALUES ($1, $2, $3)
+            INSERT INTO email_login_codes (id, user_id, code_hash, expires_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (user_id) DO UPDATE
+            SET id = EXCLUDED.id, code_hash = EXCLUDED.code_hash,
+                attempts = CASE WHEN email_login_codes.expires_at > NOW() THEN email_login_codes.attempts ELSE 0 END,
+                expires_at = EXCLUDED.expires_at, created_at = NOW()
             "#,
         )
-        .bind(activation_token.user_id)
-        .bind(activation_token.token.clone())
-        .bind(activation_token.expires_at)
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(code_hash)
+        .bind(Utc::now() + Duration::seconds(EMAIL_LOGIN_CODE_TTL_SECONDS))
         .execute(&self.pool)
         .await
         {
             Ok(_) => Ok(()),
             Err(e) => {
-                error!("Failed to save activation token : {}", e);
-                Err(e)
+                error!("Failed to save email login code: {}", e);
+                Err(AuthenticationError::InternalServerError)
             }
         }
     }
+
+    /// Exchanges a pending email login code for a login response.
+    ///
+    /// Wrong guesses count against the code, which stops working after
+    /// `EMAIL_LOGIN_CODE_MAX_ATTEMPTS` attempts or once it expires, and as
+    /// failed logins of the account, which back off like password logins.
+    pub async fn login_with_email_code(
+        &self,
+        user: &User,
+        code: &str,
+        client: &ClientContext,
+    ) -> Result<LoginResponse, AuthenticationError> {
+        self.check_login_throttle(Some(&user.id), client).await?;
+        let result = sqlx::query_as::<_, EmailLoginCode>(
+            r#"
+            SELECT id, code_hash FROM email_login_codes
+            WHERE user_id = $1 AND expires_at > NOW() AND attempts < $2
+            "#,
+        )
+        .bind(user.id)
+        .bind(EMAIL_LOGIN_CODE_MAX_ATTEMPTS)
+        .fetch_optional(&self.pool)
+        .await;
+
+        let login_code = match result {
+            Ok(Some(login_code)) => login_code,
+            Ok(None) => {
+                self.record_failed_login(Some(&user.id), client).await?;
+                return Err(AuthenticationError::InvalidCredentials);
+            }
+            Err(e) => {
+                error!("Failed to fetch email login code: {}", e);
+                return Err(AuthenticationError::InternalServerError);
+            }
+        };
+
+        if !verify_password(code, &login_code.code_hash) {
+            if let Err(e) = sqlx::query("UPDATE email_login_codes SET attempts = attempts + 1 WHERE id = $1")
+                .bind(login_code.id)
+                .execute(&self.pool)
+                .await
+            {
+                error!("Failed to record email login code attempt: {}", e);
+                return Err(AuthenticationError::InternalServerError);
+            }
+            self.record_failed_login(Some(&user.id), client).await?;
+            return Err(AuthenticationError::InvalidCredentials);
+        }
+
+        // Deleting by id makes a concurrent second redemption fail.
+        match sqlx::query("DELETE FROM email_login_codes WHERE id = $1")
+            .bind(login_code.id)
+            .execute(&self.pool)
+            .await
+        {
+            Ok(res) if res.rows_affected() == 0 => return Err(AuthenticationError::InvalidCredentials),
+            Ok(_) => {}
+            Err(e) => {
+                error!("Failed to consume email login code: {}", e);
+                return Err(AuthenticationError::InternalServerError);
+            }
+        }
+
+        self.reset_failed_logins(&user.id).await?;
+        self.complete_login(user, client).await
+    }
+
+    async fn create_mfa_challenge(&self, user_id: &Uuid) -> Result<MfaChallenge, AuthenticationError> {
+        let challenge_token = generate_token();
+        let expires_in = MFA_CHALLENGE_TTL_SECONDS;
+
+        match sqlx::query(
+            r#"
+            INSERT INTO mfa_challenges (id, user_id, token_hash, expires_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(hash_token(&challenge_token))
+        .bind(Utc::now() + Duration::seconds(expires_in))
+        .execute(&self.pool)
+        .await
+        {
+            Ok(_) => Ok(MfaChallenge {
+                mfa_required: true,
+                challenge_token,
+                expires_in,
+            }),
+            Err(e) => {
+                error!("Failed to save MFA challenge: {}", e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    /// Looks up a pending MFA challenge that has not expired or run out of attempts.
+    pub async fn find_mfa_challenge(&self, challenge_token: &str) -> Result<MfaChallengeRecord, AuthenticationError> {
+        let result = sqlx::query_as::<_, MfaChallengeRecord>(
+            r#"
+            SELECT id, user_id FROM mfa_challenges
+            WHERE token_hash = $1 AND expires_at > NOW() AND attempts < $2
+            "#,
+        )
+        .bind(hash_token(challenge_token))
+        .bind(MFA_CHALLENGE_MAX_ATTEMPTS)
+        .fetch_optional(&self.pool)
+        .await;
+
+        match result {
+            Ok(Some(challenge)) => Ok(challenge),
+            Ok(None) => Err(AuthenticationError::InvalidToken),
+            Err(e) => {
+                error!("Failed to fetch MFA challenge: {}", e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    pub async fn record_failed_mfa_attempt(&self, challenge_id: &Uuid) -> Result<(), AuthenticationError> {
+        match sqlx::query("UPDATE mfa_challenges SET attempts = attempts + 1 WHERE id = $1")
+            .bind(challenge_id)
+            .execute(&self.pool)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("Failed to record MFA attempt: {}", e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    /// Deletes a satisfied challenge so it cannot be redeemed twice.
+    pub async fn complete_mfa_challenge(&self, challenge_id: &Uuid) -> Result<(), AuthenticationError> {
+        match sqlx::query("DELETE FROM mfa_challenges WHERE id = $1")
+            .bind(challenge_id)
+            .execute(&self.pool)
+            .await
+        {
+            Ok(res) if res.rows_affected() == 0 => Err(AuthenticationError::InvalidToken),
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("Failed to complete MFA challenge: {}", e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    /// Records a new session for a user who just signed in and issues its first
+    /// tokens. `oauth_client_id` is the OAuth client the session is started for,
+    /// the only one that may then redeem its refresh tokens.
+    pub async fn start_session(
+        &self,
+        user: &User,
+        client: &ClientContext,
+        oauth_client_id: Option<&str>,
+    ) -> Result<JwtToken, AuthenticationError> {
+        ensure_active(user)?;
+        let session_id = Uuid::new_v4();
+        match sqlx::query(
+            r#"
+            INSERT INTO sessions (id, user_id, device, user_agent, ip_address, expires_at, client_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(session_id)
+        .bind(user.id)
+        .bind(client.user_agent.as_deref().and_then(describe_device))
+        .bind(&client.user_agent)
+        .bind(&client.ip_address)
+        .bind(Utc::now() + Duration::seconds(self.config.jwt.refresh_expiration))
+        .bind(oauth_client_id)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(_) => {}
+            Err(e) => {
+                error!("Failed to save session: {}", e);
+                return Err(AuthenticationError::InternalServerError);
+            }
+        }
+
+        self.issue_tokens(user, session_id).await
+    }
+
+    /// Issues new tokens in a session after a refresh, unless it was revoked meanwhile.
+    pub async fn refresh_session(&self, user: &User, session_id: Uuid) -> Result<JwtToken, AuthenticationError> {
+        ensure_active(user)?;
+        match sqlx::query(
+            r#"
+            UPDATE sessions
+            SET last_seen_at = NOW(), expires_at = $1
+            WHERE id = $2 AND revoked_at IS NULL
+            "#,
+        )
+        .bind(Utc::now() + Duration::seconds(self.config.jwt.refresh_expiration))
+        .bind(session_id)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(res) if res.rows_affected() == 0 => return Err(AuthenticationError::InvalidRefreshToken),
+            Ok(_) => {}
+            Err(e) => {
+                error!("Failed to update session {}: {}", session_id, e);
+                return Err(AuthenticationError::InternalServerError);
+            }
+        }
+
+        self.issue_tokens(user, session_id).await
+    }
+
+    /// Makes the session act in `organization_id`, or in none, and issues
+    /// tokens carrying it. The caller checks that the user is a member.
+    pub async fn switch_organization(
+        &self,
+        user: &User,
+        session_id: Uuid,
+        organization_id: Option<Uuid>,
+    ) -> Result<JwtToken, AuthenticationError> {
+        match sqlx::query(
+            r#"
+            UPDATE sessions
+            SET organization_id = $1, last_seen_at = NOW()
+            WHERE id = $2 AND user_id = $3 AND revoked_at IS NULL
+            "#,
+        )
+        .bind(organization_id)
+        .bind(session_id)
+        .bind(user.id)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(res) if res.rows_affected() == 0 => return Err(AuthenticationError::SessionNotFound),
+            Ok(_) => {}
+            Err(e) => {
+                error!("Failed to switch organization of session {}: {}", session_id, e);
+                return Err(AuthenticationError::InternalServerError);
+            }
+        }
+
+        self.issue_tokens(user, session_id).await
+    }
+
+    pub async fn list_sessions(&self, user_id: &Uuid) -> Result<Vec<Session>, AuthenticationError> {
+        match sqlx::query_as::<_, Session>(
+            r#"
+            SELECT id, device, user_agent, ip_address, created_at, last_seen_at FROM sessions
+            WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+            ORDER BY last_seen_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        {
+            Ok(sessions) => Ok(sessions),
+            Err(e) => {
+                error!("Failed to list sessions for user {}: {}", user_id, e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    /// Ends one of the user's sessions, invalidating its access and refresh tokens.
+    pub async fn revoke_session(&self, user_id: &Uuid, session_id: &Uuid) -> Result<(), AuthenticationError> {
+        let revoked = self.revoke_sessions(user_id, Some(session_id), None).await?;
+        if revoked == 0 {
+            return Err(AuthenticationError::SessionNotFound);
+        }
+        Ok(())
+    }
+
+    /// Ends every session of the user except `current_session_id`, returning how many.
+    pub async fn revoke_other_sessions(
+        &self,
+        user_id: &Uuid,
+        current_session_id: &Uuid,
+    ) -> Result<u64, AuthenticationError> {
+        self.revoke_sessions(user_id, None, Some(current_session_id)).await
+    }
+
+    /// Revokes the user's active sessions, only `only` or all but `except`
+    /// when given, together with their refresh tokens. Their access tokens
+    /// are denied for as long as any of them may still be valid.
+    async fn revoke_sessions(
+        &self,
+        user_id: &Uuid,
+        only: Option<&Uuid>,
+        except: Option<&Uuid>,
+    ) -> Result<u64, AuthenticationError> {
+        let result: Result<Vec<Uuid>, sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            let revoked = revoke_session_rows(&mut tx, user_id, only, except).await?;
+            tx.commit().await?;
+            Ok(revoked)
+        }
+        .await;
+        let revoked = match result {
+            Ok(revoked) => revoked,
+            Err(e) => {
+                error!("Failed to revoke sessions for user {}: {}", user_id, e);
+                return Err(AuthenticationError::InternalServerError);
+            }
+        };
+
+        self.deny_sessions(&revoked).await?;
+        Ok(revoked.len() as u64)
+    }
+
+    /// Rejects the access tokens of revoked sessions until they would have expired.
+    async fn deny_sessions(&self, revoked: &[Uuid]) -> Result<(), AuthenticationError> {
+        let denied_until = Utc::now() + Duration::seconds(self.config.jwt.expiration);
+        for session_id in revoked {
+            self.denylist.deny(session_id.to_string(), denied_until).await?;
+        }
+        Ok(())
+    }
+
+    /// Issues a new access token together with a refresh token, both tied to the session `session_id`.
+    async fn issue_tokens(&self, user: &User, session_id: Uuid) -> Result<JwtToken, AuthenticationError> {
+        let access = self.user_access(&user.id).await?;
+        let organization = self.active_organization(&session_id).await?;
+        let access_token = self.create_token(user, &session_id, access, organization.as_ref())?;
+        let refresh_token = generate_token();
+
+        match sqlx::query(
+            r#"
+            INSERT INTO refresh_tokens (id, user_id, family_id, token_hash, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user.id)
+        .bind(session_id)
+        .bind(hash_token(&refresh_token))
+        .bind(Utc::now() + Duration::seconds(self.config.jwt.refresh_expiration))
+        .execute(&self.pool)
+        .await
+        {
+            Ok(_) => {}
+            Err(e) => {
+                error!("Failed to save refresh token: {}", e);
+                return Err(AuthenticationError::InternalServerError);
+            }
+        };
+
+        Ok(JwtToken {
+            access_token,
+            token_type: "Bearer".to_string(),
+            expires_in: self.config.jwt.expiration,
+            refresh_token,
+            refresh_expires_in: self.config.jwt.refresh_expiration,
+        })
+    }
+
+    /// Marks a refresh token as used so it can be rotated.
+    ///
+    /// Presenting a token that was already used revokes its whole family, since
+    /// it means either the client or an attacker holds a stale copy. Tokens of
+    /// a session started for an OAuth client are only redeemed with
+    /// `oauth_client_id` set to that client, other tokens only without one.
+    pub async fn consume_refresh_token(
+        &self,
+        refresh_token: &str,
+        oauth_client_id: Option<&str>,
+    ) -> Result<RefreshToken, AuthenticationError> {
+        let token_hash = hash_token(refresh_token);
+        let result = sqlx::query_as::<_, RefreshToken>(
+            r#"
+            UPDATE refresh_tokens
+            SET used_at = NOW()
+            WHERE token_hash = $1 AND used_at IS NULL AND revoked_at IS NULL AND expires_at > NOW()
+                AND family_id IN (SELECT id FROM sessions WHERE client_id IS NOT DISTINCT FROM $2)
+            RETURNING user_id, family_id
+            "#,
+        )
+        .bind(&token_hash)
+        .bind(oauth_client_id)
+        .fetch_optional(&self.pool)
+        .await;
+
+        match result {
+            Ok(Some(token)) => Ok(token),
+            Ok(None) => {
+                self.revoke_reused_refresh_token_family(&token_hash).await?;
+                Err(AuthenticationError::InvalidRefreshToken)
+            }
+            Err(e) => {
+                error!("Failed to consume refresh token: {}", e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    async fn revoke_reused_refresh_token_family(&self, token_hash: &str) -> Result<(), AuthenticationError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE refresh_tokens
+            SET revoked_at = NOW()
+            WHERE revoked_at IS NULL AND family_id = (
+                SELECT family_id FROM refresh_tokens
+                WHERE token_hash = $1 AND used_at IS NOT NULL
+            )
+            "#,
+        )
+        .bind(token_hash)
+        .execute(&self.pool)
+        .await;
+
+        match result {
+            Ok(res) => {
+                if res.rows_affected() > 0 {
+                    warn!("Refresh token reuse detected, revoked {} tokens", res.rows_affected());
+                }
+                Ok(())
+            }
+            Err(e) => {
+                error!("Failed to revoke refresh token family: {}", e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    /// Looks up a refresh token that is neither used, revoked nor expired.
+    pub async fn find_active_refresh_token(
+        &self,
+        refresh_token: &str,
+    ) -> Result<Option<ActiveRefreshToken>, AuthenticationError> {
+        match sqlx::query_as::<_, ActiveRefreshToken>(
+            r#"
+            SELECT user_id, expires_at, created_at FROM refresh_tokens
+            WHERE token_hash = $1 AND used_at IS NULL AND revoked_at IS NULL AND expires_at > NOW()
+            "#,
+        )
+        .bind(hash_token(refresh_token))
+        .fetch_optional(&self.pool)
+        .await
+        {
+            Ok(token) => Ok(token),
+            Err(e) => {
+                error!("Failed to fetch refresh token: {}", e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    /// Revokes the family of a refresh token issued to `client_id` through
+    /// `/oauth/token`. `None` for unknown tokens, otherwise whether the token
+    /// was issued to the client, tokens of others being left alone.
+    pub async fn revoke_refresh_token(
+        &self,
+        refresh_token: &str,
+        client_id: &str,
+    ) -> Result<Option<bool>, AuthenticationError> {
+        match sqlx::query_scalar::<_, bool>(
+            r#"
+            WITH token AS (
+                SELECT rt.family_id, s.client_id FROM refresh_tokens rt
+                LEFT JOIN sessions s ON s.id = rt.family_id
+                WHERE rt.token_hash = $1
+            ), revoked AS (
+                UPDATE refresh_tokens
+                SET revoked_at = NOW()
+                WHERE revoked_at IS NULL AND family_id = (SELECT family_id FROM token WHERE client_id = $2)
+            )
+            SELECT client_id IS NOT DISTINCT FROM $2 FROM token
+            "#,
+        )
+        .bind(hash_token(refresh_token))
+        .bind(client_id)
+        .fetch_optional(&self.pool)
+        .await
+        {
+            Ok(issued) => Ok(issued),
+            Err(e) => {
+                error!("Failed to revoke refresh token: {}", e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    /// Whether a user access token belongs to a session started for `client_id`.
+    pub async fn is_issued_to_client(&self, claims: &Claims, client_id: &str) -> Result<bool, AuthenticationError> {
+        let Some(session_id) = claims.jti.as_deref().and_then(|jti| Uuid::parse_str(jti).ok()) else {
+            return Ok(false);
+        };
+        match sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM sessions WHERE id = $1 AND client_id = $2)")
+            .bind(session_id)
+            .bind(client_id)
+            .fetch_one(&self.pool)
+            .await
+        {
+            Ok(issued) => Ok(issued),
+            Err(e) => {
+                error!("Failed to fetch session {}: {}", session_id, e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    /// Revokes an access token until it expires on its own. Tokens of a login
+    /// session end the whole session, since refreshing would otherwise hand
+    /// out new tokens with the same `jti`.
+    pub async fn revoke_access_token(&self, token: &str, claims: &Claims) -> Result<(), AuthenticationError> {
+        let session = claims.jti.as_deref().and_then(|jti| Uuid::parse_str(jti).ok());
+        if let (Ok(user_id), Some(session_id)) = (Uuid::parse_str(&claims.sub), session) {
+            self.revoke_sessions(&user_id, Some(&session_id), None).await?;
+        } else {
+            let expires_at = DateTime::from_timestamp(claims.exp as i64, 0).unwrap_or_else(Utc::now);
+            self.denylist.deny(hash_token(token), expires_at).await?;
+        }
+        info!("Access token of {} revoked", claims.sub);
+        Ok(())
+    }
+
+    /// Whether an access token was revoked, through its `jti` or, for tokens
+    /// without one, the token itself.
+    pub async fn is_access_token_revoked(&self, token: &str, claims: &Claims) -> Result<bool, AuthenticationError> {
+        let key = claims.jti.clone().unwrap_or_else(|| hash_token(token));
+        let denied_until = self.denylist.denied_until(key).await?;
+        Ok(denied_until.is_some_and(|until| until > Utc::now()))
+    }
+
+    /// Revokes every session and refresh token of a user.
+    pub async fn revoke_user_sessions(&self, user_id: &Uuid) -> Result<(), AuthenticationError> {
+        self.revoke_sessions(user_id, None, None).await?;
+        Ok(())
+    }
+
+    /// The organization a session acts in, provided the user is still a member.
+    async fn active_organization(&self, session_id: &Uuid) -> Result<Option<ActiveOrganization>, AuthenticationError> {
+        match sqlx::query_as::<_, ActiveOrganization>(
+            r#"
+            SELECT m.organization_id, m.role
+            FROM sessions s
+            JOIN organization_members m ON m.organization_id = s.organization_id AND m.user_id = s.user_id
+            WHERE s.id = $1
+            "#,
+        )
+        .bind(session_id)
+        .fetch_optional(&self.pool)
+        .await
+        {
+            Ok(organization) => Ok(organization),
+            Err(e) => {
+                error!("Failed to fetch active organization of session {}: {}", session_id, e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    /// Roles of a user and the permissions they grant, to embed in its access tokens.
+    async fn user_access(&self, user_id: &Uuid) -> Result<UserAccess, AuthenticationError> {
+        match sqlx::query_as::<_, UserAccess>(
+            r#"
+            SELECT
+                COALESCE(array_agg(DISTINCT r.name) FILTER (WHERE r.name IS NOT NULL), '{}') AS roles,
+                COALESCE(array_agg(DISTINCT p.name) FILTER (WHERE p.name IS NOT NULL), '{}') AS permissions
+            FROM user_roles ur
+            JOIN roles r ON r.id = ur.role_id
+            LEFT JOIN role_permissions rp ON rp.role_id = r.id
+            LEFT JOIN permissions p ON p.id = rp.permission_id
+            WHERE ur.user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await
+        {
+            Ok(access) => Ok(access),
+            Err(e) => {
+                error!("Failed to fetch roles of user {}: {}", user_id, e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    fn create_token(
+        &self,
+        user: &User,
+        session_id: &Uuid,
+        access: UserAccess,
+        organization: Option<&ActiveOrganization>,
+    ) -> Result<String, AuthenticationError> {
+        let expiration = Utc::now().checked_add_signed(Duration::seconds(self.config.jwt.expiration))
+            .expect("valid timestamp").timestamp() as usize;
+        let claims = Claims {
+            sub: user.id.to_string(),
+            exp: expiration,
+            iat: Utc::now().timestamp() as usize,
+            jti: Some(session_id.to_string()),
+            scope: None,
+            aud: None,
+            tid: Some(user.tenant_id.clone()),
+            roles: access.roles,
+            permissions: access.permissions,
+            org: organization.map(|organization| organization.organization_id.to_string()),
+            org_role: organization.map(|organization| organization.role.as_str().to_string()),
+        };
+
+        match self.keyring.sign(&claims) {
+            Ok(token) => Ok(token),
+            Err(_) => Err(AuthenticationError::InternalServerError),
+        }
+    }
+
+    /// Signs an access token for a client acting on its own behalf.
+    pub fn create_client_token(&self, client_id: &str, scope: &str) -> Result<String, AuthenticationError> {
+        let now = Utc::now();
+        let claims = Claims {
+            sub: client_id.to_string(),
+            exp: (now + Duration::seconds(self.config.jwt.expiration)).timestamp() as usize,
+            iat: now.timestamp() as usize,
+            jti: None,
+            scope: Some(scope.to_string()),
+            aud: Some(self.config.oidc.client_token_audience.clone()),
+            tid: None,
+            roles: vec![],
+            permissions: vec![],
+            org: None,
+            org_role: None,
+        };
+
+        match self.keyring.sign(&claims) {
+            Ok(token) => Ok(token),
+            Err(_) => Err(AuthenticationError::InternalServerError),
+        }
+    }
+
+    /// Signs an OpenID Connect ID token for `client_id`, with profile claims limited to `scope`.
+    pub fn create_id_token(
+        &self,
+        user: &User,
+        client_id: &str,
+        nonce: Option<String>,
+        scope: &str,
+    ) -> Result<String, AuthenticationError> {
+        let now = Utc::now();
+        let scopes: Vec<&str> = scope.split_whitespace().collect();
+        let claims = IdTokenClaims {
+            iss: self.config.oidc.issuer.clone(),
+            sub: user.id.to_string(),
+            aud: client_id.to_string(),
+            exp: (now + Duration::seconds(self.config.jwt.expiration)).timestamp() as usize,
+            iat: now.timestamp() as usize,
+            nonce,
+            email: scopes.contains(&"email").then(|| user.email.clone()),
+            email_verified: scopes.contains(&"email").then_some(user.is_verified),
+            preferred_username: scopes.contains(&"profile").then(|| user.username.clone()),
+        };
+
+        match self.keyring.sign(&claims) {
+            Ok(token) => Ok(token),
+            Err(_) => Err(AuthenticationError::InternalServerError),
+        }
+    }
+
+    pub fn access_token_expiration(&self) -> i64 {
+        self.config.jwt.expiration
+    }
+
+    pub fn signing_algorithm(&self) -> Algorithm {
+        self.keyring.signing_algorithm()
+    }
+
+    pub fn decode_token(&self, token: &str) -> Result<Claims, AuthenticationError> {
+        match self.keyring.verify::<Claims>(token) {
+            Ok(claims) => Ok(claims),
+            Err(_) => Err(AuthenticationError::InvalidToken),
+        }
+    }
+
+    /// Decodes a client_credentials token, which [`decode_token`](Self::decode_token) rejects.
+    pub fn decode_client_token(&self, token: &str) -> Result<Claims, AuthenticationError> {
+        match self
+            .keyring
+            .verify_for_audience::<Claims>(token, &self.config.oidc.client_token_audience)
+        {
+            Ok(claims) => Ok(claims),
+            Err(_) => Err(AuthenticationError::InvalidToken),
+        }
+    }
+
+    /// Public keys other services use to verify our tokens.
+    pub fn jwks(&self) -> JwkSet {
+        self.keyring.jwks()
+    }
+
+    async fn remove_old_activation_token(&self, user_id: &Uuid) {
+        let _ = sqlx::query(
+            r#"
+                    DELETE FROM verification_tokens
+                    WHERE user_id = $1 AND purpose = $2
+                    "#,
+        )
+        .bind(user_id)
+        .bind(TokenPurpose::Activation.as_str())
+        .execute(&self.pool)
+        .await;
+    }
+
+    /// Replaces any outstanding token of `purpose` with a new one, stored hashed.
+    async fn issue_verification_token(
+        &self,
+        user_id: &Uuid,
+        purpose: TokenPurpose,
+        ttl: Duration,
+    ) -> Result<String, AuthenticationError> {
+        self.remove_verification_tokens(user_id, purpose).await?;
+
+        let token = generate_token();
+        self.save_verification_token(user_id, &hash_token(&token), purpose, Utc::now() + ttl)
+            .await?;
+        Ok(token)
+    }
+
+    async fn remove_verification_tokens(
+        &self,
+        user_id: &Uuid,
+        purpose: TokenPurpose,
+    ) -> Result<(), AuthenticationError> {
+        match sqlx::query("DELETE FROM verification_tokens WHERE user_id = $1 AND purpose = $2")
+            .bind(user_id)
+            .bind(purpose.as_str())
+            .execute(&self.pool)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("Failed to remove {} tokens: {}", purpose.as_str(), e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    async fn save_verification_token(
+        &self,
+        user_id: &Uuid,
+        token_hash: &str,
+        purpose: TokenPurpose,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), AuthenticationError> {
+        match sqlx::query(
+            r#"
+            INSERT INTO verification_tokens (user_id, token, purpose, expires_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(user_id)
+        .bind(token_hash)
+        .bind(purpose.as_str())
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("Failed to save {} token: {}", purpose.as_str(), e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    async fn activate_user(&self, user_id: Uuid) -> Result<(), AuthenticationError> {
+        let res = sqlx::query!(
+            r#"
+                    UPDATE users
+                    SET is_active = true, is_verified = true
+                    WHERE id = $1
+                    "#,
+            user_id
+        )
+        .execute(&self.pool)
+        .await;
+        match res {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("Failed to activate user: {}", e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    async fn save_activation_token(&self, activation_token: &ActivationToken) -> Result<(), Error> {
+        match sqlx::query(
+            r#"
+            INSERT INTO verification_tokens (user_id, token, purpose, expires_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(activation_token.user_id)
+        .bind(activation_token.token.clone())
+        .bind(TokenPurpose::Activation.as_str())
+        .bind(activation_token.expires_at)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("Failed to save activation token : {}", e);
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Revokes the matching sessions of a user along with their refresh tokens,
+/// returning the ids of the sessions revoked.
+async fn revoke_session_rows(
+    conn: &mut PgConnection,
+    user_id: &Uuid,
+    only: Option<&Uuid>,
+    except: Option<&Uuid>,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let revoked = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        UPDATE sessions
+        SET revoked_at = NOW()
+        WHERE user_id = $1 AND revoked_at IS NULL
+            AND ($2::UUID IS NULL OR id = $2)
+            AND ($3::UUID IS NULL OR id <> $3)
+        RETURNING id
+        "#,
+    )
+    .bind(user_id)
+    .bind(only)
+    .bind(except)
+    .fetch_all(&mut *conn)
+    .await?;
+    sqlx::query(
+        r#"
+        UPDATE refresh_tokens
+        SET revoked_at = NOW()
+        WHERE revoked_at IS NULL AND family_id = ANY($1)
+        "#,
+    )
+    .bind(&revoked)
+    .execute(&mut *conn)
+    .await?;
+    Ok(revoked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::organization::OrganizationRole;
+    use crate::config::test_config;
+    use crate::services::denylist::PostgresDenylist;
+    use crate::services::jwt_keys::JwtKey;
+    use crate::utils::security::hash_password;
+    use crate::services::jwt_keys::tests::rsa_key;
+    use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+
+    fn test_user() -> User {
+        User {
+            id: Uuid::new_v4(),
+            tenant_id: "default".to_string(),
+            email: "test@example.com".to_string(),
+            password_hash: String::new(),
+            username: "user123".to_string(),
+            pending_email: None,
+            totp_secret: None,
+            totp_enabled_at: None,
+            is_active: true,
+            is_verified: true,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn test_user_with_password(password: &str) -> User {
+        User {
+            password_hash: hash_password(password).unwrap(),
+            ..test_user()
+        }
+    }
+
+    fn test_service() -> Authentication {
+        test_service_with_key(JwtKey::from_secret(None, "test-secret"))
+    }
+
+    fn test_service_with_key(signing_key: JwtKey) -> Authentication {
+        let pool = PgPool::connect_lazy(&test_config().database.url).unwrap();
+        test_service_with_pool(pool, signing_key)
+    }
+
+    fn test_service_with_pool(pool: PgPool, signing_key: JwtKey) -> Authentication {
+        let config = Arc::new(test_config());
+        let denylist = Arc::new(PostgresDenylist::new(pool.clone()));
+        Authentication::new(pool, config, JwtKeyring::new(signing_key, vec![]).unwrap(), denylist)
+    }
+
+    #[tokio::test]
+    async fn test_inactive_user_cannot_log_in_or_refresh() {
+        let service = test_service();
+        let user = User {
+            is_active: false,
+            ..test_user()
+        };
+        let client = ClientContext {
+            user_agent: None,
+            ip_address: None,
+        };
+
+        assert!(matches!(
+            service.complete_login(&user, &client).await,
+            Err(AuthenticationError::AccountInactive)
+        ));
+        assert!(matches!(
+            service.start_session(&user, &client, None).await,
+            Err(AuthenticationError::AccountInactive)
+        ));
+        assert!(matches!(
+            service.refresh_session(&user, Uuid::new_v4()).await,
+            Err(AuthenticationError::AccountInactive)
+        ));
+    }
+
+    /// An active user and the OAuth clients `app` and `other-app`.
+    async fn oauth_fixture(pool: PgPool) -> (Authentication, User, ClientContext) {
+        let service = test_service_with_pool(pool.clone(), JwtKey::from_secret(None, "test-secret"));
+        let user = test_user();
+        sqlx::query("INSERT INTO users (id, username, email, password_hash, is_active) VALUES ($1, $2, $3, '', true)")
+            .bind(user.id)
+            .bind(&user.username)
+            .bind(&user.email)
+            .execute(&pool)
+            .await
+            .unwrap();
+        for client_id in ["app", "other-app"] {
+            sqlx::query("INSERT INTO oauth_clients (id, client_id, name) VALUES ($1, $2, $2)")
+                .bind(Uuid::new_v4())
+                .bind(client_id)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+        let client = ClientContext {
+            user_agent: None,
+            ip_address: None,
+        };
+        (service, user, client)
+    }
+
+    #[sqlx::test]
+    async fn test_refresh_token_is_only_redeemed_by_its_client(pool: PgPool) {
+        let (service, user, client) = oauth_fixture(pool).await;
+        let invalid = |result: Result<RefreshToken, AuthenticationError>| {
+            matches!(result, Err(AuthenticationError::InvalidRefreshToken))
+        };
+
+        let tokens = service.start_session(&user, &client, Some("app")).await.unwrap();
+        assert!(invalid(service.consume_refresh_token(&tokens.refresh_token, Some("other-app")).await));
+        assert!(invalid(service.consume_refresh_token(&tokens.refresh_token, None).await));
+        let consumed = service.consume_refresh_token(&tokens.refresh_token, Some("app")).await.unwrap();
+        assert_eq!(consumed.user_id, user.id);
+
+        let tokens = service.start_session(&user, &client, None).await.unwrap();
+        assert!(invalid(service.consume_refresh_token(&tokens.refresh_token, Some("app")).await));
+        assert!(service.consume_refresh_token(&tokens.refresh_token, None).await.is_ok());
+    }
+
+    #[sqlx::test]
+    async fn test_new_email_login_code_keeps_attempts_of_the_last(pool: PgPool) {
+        let (service, user, client) = oauth_fixture(pool.clone()).await;
+        let attempts = || async {
+            sqlx::query_scalar::<_, i32>("SELECT attempts FROM email_login_codes WHERE user_id = $1")
+                .bind(user.id)
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+        };
+
+        service.save_email_login_code(&user.id, "111111").await.unwrap();
+        assert!(matches!(
+            service.login_with_email_code(&user, "222222", &client).await,
+            Err(AuthenticationError::InvalidCredentials)
+        ));
+        assert_eq!(attempts().await, 1);
+        // The failure also backs off further logins of the account.
+        assert!(matches!(
+            service.login_with_email_code(&user, "111111", &client).await,
+            Err(AuthenticationError::LoginThrottled { .. })
+        ));
+
+        sqlx::query("UPDATE email_login_codes SET attempts = $1")
+            .bind(EMAIL_LOGIN_CODE_MAX_ATTEMPTS)
+            .execute(&pool)
+            .await
+            .unwrap();
+        service.save_email_login_code(&user.id, "333333").await.unwrap();
+        assert_eq!(attempts().await, EMAIL_LOGIN_CODE_MAX_ATTEMPTS);
+        service.reset_failed_logins(&user.id).await.unwrap();
+        assert!(matches!(
+            service.login_with_email_code(&user, "333333", &client).await,
+            Err(AuthenticationError::InvalidCredentials)
+        ));
+
+        // Attempts start over once the last code has expired.
+        sqlx::query("UPDATE email_login_codes SET expires_at = NOW() - INTERVAL '1 second'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        service.save_email_login_code(&user.id, "444444").await.unwrap();
+        assert_eq!(attempts().await, 0);
+    }
+
+    #[sqlx::test]
+    async fn test_refresh_token_is_only_revoked_by_its_client(pool: PgPool) {
+        let (service, user, client) = oauth_fixture(pool).await;
+        let tokens = service.start_session(&user, &client, Some("app")).await.unwrap();
+
+        assert_eq!(service.revoke_refresh_token("unknown", "app").await.unwrap(), None);
+        assert_eq!(service.revoke_refresh_token(&tokens.refresh_token, "other-app").await.unwrap(), Some(false));
+        let claims = service.decode_token(&tokens.access_token).unwrap();
+        assert!(!service.is_issued_to_client(&claims, "other-app").await.unwrap());
+        assert!(service.is_issued_to_client(&claims, "app").await.unwrap());
+
+        assert_eq!(service.revoke_refresh_token(&tokens.refresh_token, "app").await.unwrap(), Some(true));
+        assert!(service.consume_refresh_token(&tokens.refresh_token, Some("app")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_create_token_uses_access_expiration() {
+        let service = test_service();
+        let user = test_user();
+        let session_id = Uuid::new_v4();
+        let token = service.create_token(&user, &session_id, UserAccess::default(), None).unwrap();
+
+        let claims = decode::<Claims>(
+            &token,
+            &DecodingKey::from_secret(b"test-secret"),
+            &Validation::new(Algorithm::HS256),
+        )
+        .unwrap()
+        .claims;
+
+        assert_eq!(claims.sub, user.id.to_string());
+        assert_eq!(claims.jti, Some(session_id.to_string()));
+        assert_eq!(claims.exp - claims.iat, 900);
+    }
+
+    #[tokio::test]
+    async fn test_create_token_embeds_roles_and_permissions() {
+        let service = test_service();
+        let access = UserAccess {
+            roles: vec!["admin".to_string()],
+            permissions: vec!["roles:manage".to_string()],
+        };
+        let token = service.create_token(&test_user(), &Uuid::new_v4(), access, None).unwrap();
+
+        let claims = service.decode_token(&token).unwrap();
+        assert_eq!(claims.roles, vec!["admin"]);
+        assert_eq!(claims.permissions, vec!["roles:manage"]);
+        assert_eq!(claims.org, None);
+    }
+
+    #[tokio::test]
+    async fn test_create_token_embeds_active_organization() {
+        let service = test_service();
+        let organization = ActiveOrganization {
+            organization_id: Uuid::new_v4(),
+            role: OrganizationRole::Admin,
+        };
+        let token = service
+            .create_token(&test_user(), &Uuid::new_v4(), UserAccess::default(), Some(&organization))
+            .unwrap();
+
+        let claims = service.decode_token(&token).unwrap();
+        assert_eq!(claims.org, Some(organization.organization_id.to_string()));
+        assert_eq!(claims.org_role.as_deref(), Some("admin"));
+    }
+
+    #[tokio::test]
+    async fn test_decode_token_round_trip() {
+        let service = test_service();
+        let user = test_user();
+        let token = service.create_token(&user, &Uuid::new_v4(), UserAccess::default(), None).unwrap();
+
+        let claims = service.decode_token(&token).unwrap();
+        assert_eq!(claims.sub, user.id.to_string());
+        assert_eq!(claims.tid.as_deref(), Some("default"));
+    }
+
+    #[tokio::test]
+    async fn test_id_token_claims_follow_scope() {
+        let service = test_service();
+        let user = test_user();
+        let token = service
+            .create_id_token(&user, "web", Some("n-0S6_WzA2Mj".to_string()), "openid profile")
+            .unwrap();
+
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_audience(&["web"]);
+        validation.set_issuer(&["http://localhost:3000"]);
+        let claims = decode::<IdTokenClaims>(&token, &DecodingKey::from_secret(b"test-secret"), &validation)
+            .unwrap()
+            .claims;
+
+        assert_eq!(claims.sub, user.id.to_string());
+        assert_eq!(claims.nonce.as_deref(), Some("n-0S6_WzA2Mj"));
+        assert_eq!(claims.preferred_username.as_deref(), Some("user123"));
+        assert!(claims.email.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_client_token_carries_scope_and_audience() {
+        let service = test_service();
+        let token = service.create_client_token("reporting-job", "reports:read").unwrap();
+
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.set_audience(&["http://localhost:3000/api"]);
+        let claims = decode::<Claims>(&token, &DecodingKey::from_secret(b"test-secret"), &validation)
+            .unwrap()
+            .claims;
+        assert_eq!(claims.sub, "reporting-job");
+        assert_eq!(claims.scope.as_deref(), Some("reports:read"));
+
+        // User-facing endpoints must not accept it.
+        assert!(service.decode_token(&token).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_decode_client_token_only_accepts_client_tokens() {
+        let service = test_service();
+        let client_token = service.create_client_token("reporting-job", "reports:read").unwrap();
+        let user_token = service.create_token(&test_user(), &Uuid::new_v4(), UserAccess::default(), None).unwrap();
+        let id_token = service.create_id_token(&test_user(), "web", None, "openid").unwrap();
+
+        assert_eq!(service.decode_client_token(&client_token).unwrap().sub, "reporting-job");
+        assert!(service.decode_client_token(&user_token).is_err());
+        assert!(service.decode_client_token(&id_token).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_id_token_is_not_accepted_as_access_token() {
+        let service = test_service();
+        let token = service
+            .create_id_token(&test_user(), "web", None, "openid")
+            .unwrap();
+
+        assert!(service.decode_token(&token).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_decode_token_rejects_tampered_token() {
+        let service = test_service();
+        let token = service.create_token(&test_user(), &Uuid::new_v4(), UserAccess::default(), None).unwrap();
+
+        let result = service.decode_token(&format!("{}x", token));
+        assert!(matches!(result, Err(AuthenticationError::InvalidToken)));
+    }
+
+    #[tokio::test]
+    async fn test_asymmetric_token_carries_kid_and_verifies() {
+        let service = test_service_with_key(rsa_key("rsa-1"));
+        let user = test_user();
+        let token = service.create_token(&user, &Uuid::new_v4(), UserAccess::default(), None).unwrap();
+
+        let header = decode_header(&token).unwrap();
+        assert_eq!(header.alg, Algorithm::RS256);
+        assert_eq!(header.kid.as_deref(), Some("rsa-1"));
+
+        let claims = service.decode_token(&token).unwrap();
+        assert_eq!(claims.sub, user.id.to_string());
+        assert_eq!(claims.tid.as_deref(), Some("default"));
+    }
+
+    #[tokio::test]
+    async fn test_token_verifies_against_published_jwks() {
+        let service = test_service_with_key(rsa_key("rsa-1"));
+        let token = service.create_token(&test_user(), &Uuid::new_v4(), UserAccess::default(), None).unwrap();
+
+        let jwks = service.jwks();
+        let jwk = jwks.find("rsa-1").unwrap();
+        let key = DecodingKey::from_jwk(jwk).unwrap();
+        assert!(decode::<Claims>(&token, &key, &Validation::new(Algorithm::RS256)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_token_with_unknown_kid_is_rejected() {
+        let signer = test_service_with_key(rsa_key("rsa-1"));
+        let verifier = test_service_with_key(rsa_key("rsa-2"));
+        let token = signer.create_token(&test_user(), &Uuid::new_v4(), UserAccess::default(), None).unwrap();
+
+        let result = verifier.decode_token(&token);
+        assert!(matches!(result, Err(AuthenticationError::InvalidToken)));
+    }
+
+    #[sqlx::test]
+    async fn test_password_change_requires_current_password(pool: PgPool) {
+        let (service, _, client) = oauth_fixture(pool).await;
+        let user = test_user_with_password("Current1@pass");
+        let result = service.check_password_change(&user, "Wrong1@pass", "Another1@pass", &client).await;
+        assert!(matches!(result, Err(AuthenticationError::InvalidCredentials)));
+        // The wrong guess backs off further attempts like a failed login.
+        let result = service.check_password_change(&user, "Current1@pass", "Another1@pass", &client).await;
+        assert!(matches!(result, Err(AuthenticationError::LoginThrottled { .. })));
+    }
+
+    #[sqlx::test]
+    async fn test_password_change_rejects_reuse(pool: PgPool) {
+        let (service, _, client) = oauth_fixture(pool).await;
+        let user = test_user_with_password("Current1@pass");
+        let result = service.check_password_change(&user, "Current1@pass", "Current1@pass", &client).await;
+        assert!(matches!(result, Err(AuthenticationError::InvalidInput(_))));
+    }
+
+    #[sqlx::test]
+    async fn test_password_change_accepts_new_password(pool: PgPool) {
+        let (service, _, client) = oauth_fixture(pool).await;
+        let user = test_user_with_password("Current1@pass");
+        let result = service.check_password_change(&user, "Current1@pass", "Another1@pass", &client).await;
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_login_block_doubles_until_lockout() {
+        let blocks: Vec<i64> = (1..=6).map(|failures| login_block(failures, 5, 1, 900).num_seconds()).collect();
+        assert_eq!(blocks, vec![1, 2, 4, 8, 900, 900]);
+    }
+
+    #[test]
+    fn test_login_block_backoff_is_capped_at_lockout() {
+        assert_eq!(login_block(19, 20, 1, 900).num_seconds(), 900);
+        assert_eq!(login_block(u32::MAX - 1, u32::MAX, 1, 900).num_seconds(), 900);
+    }
+
+    #[sqlx::test]
+    async fn test_password_reset_ends_sessions_and_uses_up_the_token(pool: PgPool) {
+        let (service, user, client) = oauth_fixture(pool.clone()).await;
+        let tokens = service.start_session(&user, &client, None).await.unwrap();
+        let token = service
+            .issue_verification_token(&user.id, TokenPurpose::PasswordReset, Duration::hours(1))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            service.reset_password("unknown", "Another1@pass").await,
+            Err(AuthenticationError::InvalidToken)
+        ));
+        assert_eq!(service.reset_password(&token, "Another1@pass").await.unwrap(), user.id);
+        let password_hash: String = sqlx::query_scalar("SELECT password_hash FROM users WHERE id = $1")
+            .bind(user.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert!(verify_password("Another1@pass", &password_hash));
+        assert!(matches!(
+            service.consume_refresh_token(&tokens.refresh_token, None).await,
+            Err(AuthenticationError::InvalidRefreshToken)
+        ));
+        assert!(matches!(
+            service.reset_password(&token, "Third1@pass").await,
+            Err(AuthenticationError::InvalidToken)
+        ));
+    }
+
+    #[sqlx::test]
+    async fn test_refresh_token_rotates_on_use(pool: PgPool) {
+        let (service, user, client) = oauth_fixture(pool).await;
+        let first = service.start_session(&user, &client, None).await.unwrap();
+
+        let consumed = service.consume_refresh_token(&first.refresh_token, None).await.unwrap();
+        let second = service.refresh_session(&user, consumed.family_id).await.unwrap();
+        assert_ne!(second.refresh_token, first.refresh_token);
+
+        let rotated = service.consume_refresh_token(&second.refresh_token, None).await.unwrap();
+        assert_eq!(rotated.family_id, consumed.family_id);
+        assert_eq!(rotated.user_id, user.id);
+    }
+
+    #[sqlx::test]
+    async fn test_reused_refresh_token_revokes_its_family(pool: PgPool) {
+        let (service, user, client) = oauth_fixture(pool).await;
+        let first = service.start_session(&user, &client, None).await.unwrap();
+        let consumed = service.consume_refresh_token(&first.refresh_token, None).await.unwrap();
+        let second = service.refresh_session(&user, consumed.family_id).await.unwrap();
+
+        assert!(matches!(
+            service.consume_refresh_token(&first.refresh_token, None).await,
+            Err(AuthenticationError::InvalidRefreshToken)
+        ));
+        // Whoever replayed the old token may hold the new one; it is revoked too.
+        assert!(matches!(
+            service.consume_refresh_token(&second.refresh_token, None).await,
+            Err(AuthenticationError::InvalidRefreshToken)
+        ));
+    }
+
+    #[sqlx::test]
+    async fn test_refresh_fails_after_session_is_revoked(pool: PgPool) {
+        let (service, user, client) = oauth_fixture(pool.clone()).await;
+        let tokens = service.start_session(&user, &client, None).await.unwrap();
+        let session_id: Uuid = sqlx::query_scalar("SELECT id FROM sessions WHERE user_id = $1")
+            .bind(user.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+
+        service.revoke_session(&user.id, &session_id).await.unwrap();
+        assert!(matches!(
+            service.consume_refresh_token(&tokens.refresh_token, None).await,
+            Err(AuthenticationError::InvalidRefreshToken)
+        ));
+        assert!(matches!(
+            service.refresh_session(&user, session_id).await,
+            Err(AuthenticationError::InvalidRefreshToken)
+        ));
+    }
 }
@@ -0,0 +1,267 @@
+use crate::error::authentication::AuthenticationError;
+use crate::services::traits::{DenylistFuture, TokenDenylist};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+/// Denylist kept in the `token_denylist` table, shared by every instance.
+pub struct PostgresDenylist {
+    pool: PgPool,
+}
+
+impl PostgresDenylist {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+impl TokenDenylist for PostgresDenylist {
+    fn deny(&self, jti: String, expires_at: DateTime<Utc>) -> DenylistFuture<'_, ()> {
+        Box::pin(async move {
+            match sqlx::query(
+                r#"
+                INSERT INTO token_denylist (jti, expires_at)
+                VALUES ($1, $2)
+                ON CONFLICT (jti) DO UPDATE SET expires_at = GREATEST(token_denylist.expires_at, EXCLUDED.expires_at)
+                "#,
+            )
+            .bind(&jti)
+            .bind(expires_at)
+            .execute(&self.pool)
+            .await
+            {
+                Ok(_) => Ok(()),
+                Err(e) => {
+                    error!("Failed to deny token {}: {}", jti, e);
+                    Err(AuthenticationError::InternalServerError)
+                }
+            }
+        })
+    }
+
+    fn denied_until(&self, jti: String) -> DenylistFuture<'_, Option<DateTime<Utc>>> {
+        Box::pin(async move {
+            match sqlx::query_scalar::<_, DateTime<Utc>>(
+                "SELECT expires_at FROM token_denylist WHERE jti = $1 AND expires_at > NOW()",
+            )
+            .bind(&jti)
+            .fetch_optional(&self.pool)
+            .await
+            {
+                Ok(expires_at) => Ok(expires_at),
+                Err(e) => {
+                    error!("Failed to check token denylist: {}", e);
+                    Err(AuthenticationError::InternalServerError)
+                }
+            }
+        })
+    }
+
+    fn purge_expired(&self) -> DenylistFuture<'_, u64> {
+        Box::pin(async move {
+            match sqlx::query("DELETE FROM token_denylist WHERE expires_at <= NOW()")
+                .execute(&self.pool)
+                .await
+            {
+                Ok(res) => Ok(res.rows_affected()),
+                Err(e) => {
+                    error!("Failed to purge token denylist: {}", e);
+                    Err(AuthenticationError::InternalServerError)
+                }
+            }
+        })
+    }
+}
+
+enum CacheEntry {
+    Denied(DateTime<Utc>),
+    Allowed(Instant),
+}
+
+/// In-memory cache in front of another denylist. Denials are kept until they
+/// expire, since they cannot be lifted; lookups that found nothing are trusted
+/// for `ttl`.
+pub struct CachedDenylist {
+    store: Arc<dyn TokenDenylist>,
+    ttl: Duration,
+    entries: RwLock<HashMap<String, CacheEntry>>,
+}
+
+impl CachedDenylist {
+    pub fn new(store: Arc<dyn TokenDenylist>, ttl: Duration) -> Self {
+        Self {
+            store,
+            ttl,
+            entries: RwLock::new(HashMap::new()),
+        }
+    }
+
+    fn cached(&self, jti: &str) -> Option<Option<DateTime<Utc>>> {
+        let entries = self.entries.read().unwrap();
+        match entries.get(jti)? {
+            CacheEntry::Denied(until) => Some(Some(*until)),
+            CacheEntry::Allowed(checked_at) if checked_at.elapsed() < self.ttl => Some(None),
+            CacheEntry::Allowed(_) => None,
+        }
+    }
+}
+
+impl TokenDenylist for CachedDenylist {
+    fn deny(&self, jti: String, expires_at: DateTime<Utc>) -> DenylistFuture<'_, ()> {
+        Box::pin(async move {
+            self.store.deny(jti.clone(), expires_at).await?;
+            let mut entries = self.entries.write().unwrap();
+            let until = match entries.get(&jti) {
+                Some(CacheEntry::Denied(until)) => expires_at.max(*until),
+                _ => expires_at,
+            };
+            entries.insert(jti, CacheEntry::Denied(until));
+            Ok(())
+        })
+    }
+
+    fn denied_until(&self, jti: String) -> DenylistFuture<'_, Option<DateTime<Utc>>> {
+        Box::pin(async move {
+            if let Some(cached) = self.cached(&jti) {
+                return Ok(cached);
+            }
+
+            let denied_until = self.store.denied_until(jti.clone()).await?;
+            let entry = match denied_until {
+                Some(until) => CacheEntry::Denied(until),
+                None => CacheEntry::Allowed(Instant::now()),
+            };
+            self.entries.write().unwrap().insert(jti, entry);
+            Ok(denied_until)
+        })
+    }
+
+    fn purge_expired(&self) -> DenylistFuture<'_, u64> {
+        Box::pin(async move {
+            let purged = self.store.purge_expired().await?;
+            let now = Utc::now();
+            self.entries.write().unwrap().retain(|_, entry| match entry {
+                CacheEntry::Denied(until) => *until > now,
+                CacheEntry::Allowed(checked_at) => checked_at.elapsed() < self.ttl,
+            });
+            Ok(purged)
+        })
+    }
+}
+
+/// Purges expired entries from `denylist` every `interval`, for as long as
+/// the process runs.
+pub fn spawn_sweeper(denylist: Arc<dyn TokenDenylist>, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match denylist.purge_expired().await {
+                Ok(0) => {}
+                Ok(purged) => info!("Purged {} expired token denylist entries", purged),
+                // Already logged by the store, try again on the next tick.
+                Err(_) => {}
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Denylist held in memory that counts the lookups reaching it.
+    #[derive(Default)]
+    struct MemoryDenylist {
+        entries: RwLock<HashMap<String, DateTime<Utc>>>,
+        lookups: AtomicUsize,
+    }
+
+    impl TokenDenylist for MemoryDenylist {
+        fn deny(&self, jti: String, expires_at: DateTime<Utc>) -> DenylistFuture<'_, ()> {
+            self.entries.write().unwrap().insert(jti, expires_at);
+            Box::pin(async { Ok(()) })
+        }
+
+        fn denied_until(&self, jti: String) -> DenylistFuture<'_, Option<DateTime<Utc>>> {
+            self.lookups.fetch_add(1, Ordering::SeqCst);
+            let until = self.entries.read().unwrap().get(&jti).copied().filter(|until| *until > Utc::now());
+            Box::pin(async move { Ok(until) })
+        }
+
+        fn purge_expired(&self) -> DenylistFuture<'_, u64> {
+            let mut entries = self.entries.write().unwrap();
+            let before = entries.len();
+            entries.retain(|_, until| *until > Utc::now());
+            let purged = (before - entries.len()) as u64;
+            Box::pin(async move { Ok(purged) })
+        }
+    }
+
+    fn cached(ttl: Duration) -> (Arc<MemoryDenylist>, CachedDenylist) {
+        let store = Arc::new(MemoryDenylist::default());
+        let cache = CachedDenylist::new(store.clone(), ttl);
+        (store, cache)
+    }
+
+    #[tokio::test]
+    async fn test_denied_token_is_answered_from_cache() {
+        let (store, cache) = cached(Duration::from_secs(60));
+        let until = Utc::now() + chrono::Duration::minutes(15);
+
+        cache.deny("session".to_string(), until).await.unwrap();
+
+        assert_eq!(cache.denied_until("session".to_string()).await.unwrap(), Some(until));
+        assert_eq!(store.lookups.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_allowed_token_is_cached_for_ttl() {
+        let (store, cache) = cached(Duration::from_secs(60));
+
+        assert_eq!(cache.denied_until("session".to_string()).await.unwrap(), None);
+        assert_eq!(cache.denied_until("session".to_string()).await.unwrap(), None);
+        assert_eq!(store.lookups.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_denial_made_elsewhere_applies_after_ttl() {
+        let (store, cache) = cached(Duration::ZERO);
+        let until = Utc::now() + chrono::Duration::minutes(15);
+
+        assert_eq!(cache.denied_until("session".to_string()).await.unwrap(), None);
+        store.deny("session".to_string(), until).await.unwrap();
+
+        assert_eq!(cache.denied_until("session".to_string()).await.unwrap(), Some(until));
+    }
+
+    #[tokio::test]
+    async fn test_deny_keeps_the_later_expiry() {
+        let (_, cache) = cached(Duration::from_secs(60));
+        let later = Utc::now() + chrono::Duration::minutes(15);
+
+        cache.deny("session".to_string(), later).await.unwrap();
+        cache.deny("session".to_string(), later - chrono::Duration::minutes(5)).await.unwrap();
+
+        assert_eq!(cache.denied_until("session".to_string()).await.unwrap(), Some(later));
+    }
+
+    #[tokio::test]
+    async fn test_purge_drops_expired_entries() {
+        let (store, cache) = cached(Duration::from_secs(60));
+        let now = Utc::now();
+        cache.deny("expired".to_string(), now - chrono::Duration::seconds(1)).await.unwrap();
+        cache.deny("active".to_string(), now + chrono::Duration::minutes(15)).await.unwrap();
+
+        assert_eq!(cache.purge_expired().await.unwrap(), 1);
+
+        assert_eq!(cache.denied_until("expired".to_string()).await.unwrap(), None);
+        assert!(cache.denied_until("active".to_string()).await.unwrap().is_some());
+        assert_eq!(store.lookups.load(Ordering::SeqCst), 1);
+    }
+}
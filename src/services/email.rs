@@ -9,26 +9,30 @@ use lettre::transport::smtp::client::Tls;
 use lettre::{Message, SmtpTransport, Transport};
 use std::pin::Pin;
 use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tracing::{error, info};
 
+/// Emails sent off the request path at once; further ones are dropped, so a
+/// flood of requests cannot pile up tasks or tie up the database pool.
+const MAX_BACKGROUND_EMAILS: usize = 32;
+
 pub struct EmailService {
     pub config: Arc<Config>,
+    background: Arc<Semaphore>,
 }
 
 impl EmailServiceBase for EmailService {
     fn send_email(
         &self,
+        tenant_id: &str,
         to: String,
         cc: Vec<String>,
         bcc: Vec<String>,
         subject: String,
         content: String,
     ) -> Pin<Box<dyn Future<Output = Result<(), EmailError>> + Send>> {
-        let from = match format!(
-            "{}<{}>",
-            self.config.smtp.from_name, self.config.smtp.from_email
-        )
-        .parse()
+        let (from_name, from_email) = self.config.sender_for(tenant_id);
+        let from = match format!("{}<{}>", from_name, from_email).parse()
         {
             Ok(from) => from,
             Err(err) => {
@@ -66,27 +70,43 @@ impl EmailServiceBase for EmailService {
             .port(self.config.smtp.port) // e.g., "smtp.gmail.com"
             .credentials(creds);
 
-        if self.config.smtp.tls == false {
+        if !self.config.smtp.tls {
             mailer_builder = mailer_builder.tls(Tls::None);
         }
 
         let mailer = mailer_builder.build();
 
-        match mailer.send(&email) {
-            Ok(_) => Box::pin(async move {
-                info!("Successfully sent email to {}", to);
-                Ok(())
-            }),
-            Err(e) => Box::pin(async move {
-                error!("Failed to send email: {:?}", e);
-                Err(Other(anyhow!(e.to_string())))
-            }),
-        }
+        // The transport is synchronous; keep it off the threads driving async tasks.
+        Box::pin(async move {
+            match tokio::task::spawn_blocking(move || mailer.send(&email)).await {
+                Ok(Ok(_)) => {
+                    info!("Successfully sent email to {}", to);
+                    Ok(())
+                }
+                Ok(Err(e)) => {
+                    error!("Failed to send email: {:?}", e);
+                    Err(Other(anyhow!(e.to_string())))
+                }
+                Err(e) => {
+                    error!("Email task failed: {}", e);
+                    Err(EmailError::InternalServerError)
+                }
+            }
+        })
     }
 }
 
 impl EmailService {
     pub fn new(config: Arc<Config>) -> Self {
-        Self { config }
+        Self {
+            config,
+            background: Arc::new(Semaphore::new(MAX_BACKGROUND_EMAILS)),
+        }
+    }
+
+    /// Reserves a slot to send an email in the background, held until the
+    /// permit is dropped; `None` while [`MAX_BACKGROUND_EMAILS`] are pending.
+    pub fn try_reserve_background(&self) -> Option<OwnedSemaphorePermit> {
+        self.background.clone().try_acquire_owned().ok()
     }
 }
@@ -0,0 +1,714 @@
+use crate::config::{Config, IdentityProviderConfig};
+use crate::error::federation::FederationError;
+use crate::models::federation::{
+    ExternalIdentity, FederationState, ProviderEndpoints, ProviderMetadata, ProviderTokenResponse,
+};
+use crate::models::user::User;
+use crate::utils::security::{generate_numeric_code, generate_token, hash_password, hash_token};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{Duration, Utc};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use sqlx::{Error, PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tracing::info;
+use tracing::log::error;
+use url::Url;
+use uuid::Uuid;
+
+const STATE_TTL_SECONDS: i64 = 600;
+const HTTP_TIMEOUT_SECONDS: u64 = 10;
+
+/// Sign-in through external identity providers with the authorization code
+/// flow, linking their accounts to local users.
+pub struct Federation {
+    pool: PgPool,
+    config: Arc<Config>,
+    http: reqwest::Client,
+    discovered: RwLock<HashMap<String, ProviderEndpoints>>,
+}
+
+impl Federation {
+    pub fn new(pool: PgPool, config: Arc<Config>) -> anyhow::Result<Self> {
+        let http = reqwest::Client::builder()
+            .user_agent(concat!("auth-service/", env!("CARGO_PKG_VERSION")))
+            .timeout(std::time::Duration::from_secs(HTTP_TIMEOUT_SECONDS))
+            .build()?;
+        Ok(Self {
+            pool,
+            config,
+            http,
+            discovered: RwLock::new(HashMap::new()),
+        })
+    }
+
+    pub fn provider_names(&self) -> Vec<String> {
+        self.config
+            .federation
+            .providers
+            .iter()
+            .map(|provider| provider.name.clone())
+            .collect()
+    }
+
+    /// Starts a login to `tenant_id`, returning the provider URL to send the browser to.
+    pub async fn begin_login(&self, tenant_id: &str, provider_name: &str) -> Result<String, FederationError> {
+        let provider = self.provider(provider_name)?;
+        let endpoints = self.endpoints(provider).await?;
+
+        let state = generate_token();
+        let code_verifier = generate_token();
+        match sqlx::query(
+            r#"
+            INSERT INTO federation_states (id, tenant_id, state_hash, provider, code_verifier, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(tenant_id)
+        .bind(hash_token(&state))
+        .bind(&provider.name)
+        .bind(&code_verifier)
+        .bind(Utc::now() + Duration::seconds(STATE_TTL_SECONDS))
+        .execute(&self.pool)
+        .await
+        {
+            Ok(_) => {}
+            Err(e) => {
+                error!("Failed to save federation state: {}", e);
+                return Err(FederationError::InternalServerError);
+            }
+        }
+
+        authorization_url(
+            provider,
+            &endpoints,
+            &self.config.federation.redirect_url,
+            &state,
+            &code_challenge(&code_verifier),
+        )
+    }
+
+    /// Completes a login from the `state` and `code` the provider redirected
+    /// back with, returning the linked local user of the tenant the login was
+    /// started in.
+    pub async fn finish_login(&self, state: &str, code: &str) -> Result<User, FederationError> {
+        let federation_state = self.consume_state(state).await?;
+        let provider = self.provider(&federation_state.provider)?;
+        let identity = self
+            .fetch_identity(provider, code, &federation_state.code_verifier)
+            .await?;
+        self.resolve_user(&federation_state.tenant_id, provider, &identity).await
+    }
+
+    /// Redeems an authorization code at the provider and reads the account it was issued for.
+    pub async fn fetch_identity(
+        &self,
+        provider: &IdentityProviderConfig,
+        code: &str,
+        code_verifier: &str,
+    ) -> Result<ExternalIdentity, FederationError> {
+        let endpoints = self.endpoints(provider).await?;
+
+        let response = self
+            .http
+            .post(&endpoints.token_endpoint)
+            .header(reqwest::header::ACCEPT, "application/json")
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", self.config.federation.redirect_url.as_str()),
+                ("client_id", provider.client_id.as_str()),
+                ("client_secret", provider.client_secret.as_str()),
+                ("code_verifier", code_verifier),
+            ])
+            .send()
+            .await
+            .map_err(|e| upstream_error(provider, "token request failed", e))?;
+        if !response.status().is_success() {
+            return Err(FederationError::Provider(format!(
+                "{} rejected the authorization code",
+                provider.name
+            )));
+        }
+        let token: ProviderTokenResponse = response
+            .json()
+            .await
+            .map_err(|e| upstream_error(provider, "malformed token response", e))?;
+
+        let profile: Value = self
+            .http
+            .get(&endpoints.userinfo_endpoint)
+            .bearer_auth(&token.access_token)
+            .header(reqwest::header::ACCEPT, "application/json")
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| upstream_error(provider, "userinfo request failed", e))?
+            .json()
+            .await
+            .map_err(|e| upstream_error(provider, "malformed userinfo response", e))?;
+
+        parse_identity(provider, &profile)
+    }
+
+    /// The provider's endpoints, filling in the ones not configured from its
+    /// discovery document, which is fetched once.
+    pub async fn endpoints(&self, provider: &IdentityProviderConfig) -> Result<ProviderEndpoints, FederationError> {
+        if let (Some(authorization_endpoint), Some(token_endpoint), Some(userinfo_endpoint)) = (
+            &provider.authorization_endpoint,
+            &provider.token_endpoint,
+            &provider.userinfo_endpoint,
+        ) {
+            return Ok(ProviderEndpoints {
+                authorization_endpoint: authorization_endpoint.clone(),
+                token_endpoint: token_endpoint.clone(),
+                userinfo_endpoint: userinfo_endpoint.clone(),
+            });
+        }
+        if let Some(endpoints) = self.discovered.read().unwrap().get(&provider.name) {
+            return Ok(endpoints.clone());
+        }
+
+        let issuer = provider.issuer.as_deref().ok_or_else(|| {
+            error!("Identity provider {} needs either an issuer or all endpoints", provider.name);
+            FederationError::InternalServerError
+        })?;
+        let issuer = issuer.trim_end_matches('/');
+        let metadata: ProviderMetadata = self
+            .http
+            .get(format!("{}/.well-known/openid-configuration", issuer))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| upstream_error(provider, "discovery failed", e))?
+            .json()
+            .await
+            .map_err(|e| upstream_error(provider, "malformed discovery document", e))?;
+        // OpenID Connect Discovery section 4.3.
+        if metadata.issuer.trim_end_matches('/') != issuer {
+            error!("Identity provider {} reported issuer {}", provider.name, metadata.issuer);
+            return Err(FederationError::Provider(format!("{} discovery issuer mismatch", provider.name)));
+        }
+
+        let endpoints = ProviderEndpoints {
+            authorization_endpoint: provider
+                .authorization_endpoint
+                .clone()
+                .unwrap_or(metadata.authorization_endpoint),
+            token_endpoint: provider.token_endpoint.clone().unwrap_or(metadata.token_endpoint),
+            userinfo_endpoint: provider
+                .userinfo_endpoint
+                .clone()
+                .or(metadata.userinfo_endpoint)
+                .ok_or_else(|| FederationError::Provider(format!("{} has no userinfo endpoint", provider.name)))?,
+        };
+        self.discovered
+            .write()
+            .unwrap()
+            .insert(provider.name.clone(), endpoints.clone());
+        Ok(endpoints)
+    }
+
+    fn provider(&self, name: &str) -> Result<&IdentityProviderConfig, FederationError> {
+        self.config
+            .federation
+            .providers
+            .iter()
+            .find(|provider| provider.name == name)
+            .ok_or(FederationError::UnknownProvider)
+    }
+
+    /// Deletes the state so it cannot be replayed, then checks its expiry.
+    async fn consume_state(&self, state: &str) -> Result<FederationState, FederationError> {
+        let result = sqlx::query_as::<_, FederationState>(
+            r#"
+            DELETE FROM federation_states
+            WHERE state_hash = $1
+            RETURNING tenant_id, provider, code_verifier, expires_at
+            "#,
+        )
+        .bind(hash_token(state))
+        .fetch_optional(&self.pool)
+        .await;
+
+        match result {
+            Ok(Some(federation_state)) if federation_state.expires_at > Utc::now() => Ok(federation_state),
+            Ok(_) => Err(FederationError::InvalidState),
+            Err(e) => {
+                error!("Failed to consume federation state: {}", e);
+                Err(FederationError::InternalServerError)
+            }
+        }
+    }
+
+    /// Finds the user of `tenant_id` linked to an external identity, otherwise
+    /// links the account with the same verified email or creates one.
+    async fn resolve_user(
+        &self,
+        tenant_id: &str,
+        provider: &IdentityProviderConfig,
+        identity: &ExternalIdentity,
+    ) -> Result<User, FederationError> {
+        let mut tx = self.pool.begin().await.map_err(|e| {
+            error!("Failed to start transaction: {}", e);
+            FederationError::InternalServerError
+        })?;
+
+        let linked = sqlx::query_as::<_, User>(
+            r#"
+            SELECT users.* FROM users
+            JOIN federated_identities ON federated_identities.user_id = users.id
+            WHERE federated_identities.tenant_id = $1
+                AND federated_identities.provider = $2
+                AND federated_identities.subject = $3
+            "#,
+        )
+        .bind(tenant_id)
+        .bind(&provider.name)
+        .bind(&identity.subject)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch federated identity: {}", e);
+            FederationError::InternalServerError
+        })?;
+
+        let user = match linked {
+            Some(user) => {
+                if !user.is_active {
+                    return Err(FederationError::AccountInactive);
+                }
+                sqlx::query(
+                    r#"
+                    UPDATE federated_identities
+                    SET email = $1, last_login_at = NOW()
+                    WHERE tenant_id = $2 AND provider = $3 AND subject = $4
+                    "#,
+                )
+                .bind(&identity.email)
+                .bind(tenant_id)
+                .bind(&provider.name)
+                .bind(&identity.subject)
+                .execute(&mut *tx)
+                .await
+                .map_err(|e| {
+                    error!("Failed to update federated identity: {}", e);
+                    FederationError::InternalServerError
+                })?;
+                user
+            }
+            None => {
+                let email = identity.email.as_deref().ok_or_else(|| {
+                    FederationError::Provider(format!("{} did not share an email address", provider.name))
+                })?;
+                // Linking by email is only safe when the provider vouches for it.
+                if !identity.email_verified {
+                    return Err(FederationError::EmailNotVerified);
+                }
+
+                let existing = sqlx::query_as::<_, User>("SELECT * FROM users WHERE tenant_id = $1 AND email = $2")
+                    .bind(tenant_id)
+                    .bind(email)
+                    .fetch_optional(&mut *tx)
+                    .await
+                    .map_err(|e| {
+                        error!("Failed to fetch user by email: {}", e);
+                        FederationError::InternalServerError
+                    })?;
+                let user = match existing {
+                    // An unverified account may have been registered by someone else.
+                    Some(user) if !user.is_active => return Err(FederationError::AccountInactive),
+                    Some(user) => {
+                        info!("Linking {} identity to existing user {}", provider.name, user.id);
+                        user
+                    }
+                    None => {
+                        let user = create_user(&mut tx, tenant_id, email, identity).await?;
+                        info!("Created user {} from {} identity", user.id, provider.name);
+                        user
+                    }
+                };
+                link_identity(&mut tx, &user, provider, identity).await?;
+                user
+            }
+        };
+
+        tx.commit().await.map_err(|e| {
+            error!("Failed to commit federated login: {}", e);
+            FederationError::InternalServerError
+        })?;
+        Ok(user)
+    }
+}
+
+/// Creates an active account with an unusable random password; the user can
+/// set one through the password reset flow.
+async fn create_user(
+    tx: &mut Transaction<'_, Postgres>,
+    tenant_id: &str,
+    email: &str,
+    identity: &ExternalIdentity,
+) -> Result<User, FederationError> {
+    let password_hash = hash_password(&generate_token()).map_err(|_| FederationError::InternalServerError)?;
+
+    let base = username_candidate(identity.username.as_deref(), email);
+    let mut username = base.clone();
+    for _ in 0..5 {
+        match sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS(SELECT 1 FROM users WHERE tenant_id = $1 AND username = $2)",
+        )
+        .bind(tenant_id)
+        .bind(&username)
+        .fetch_one(&mut **tx)
+        .await
+        {
+            Ok(false) => break,
+            Ok(true) => username = format!("{}{}", base, generate_numeric_code(4)),
+            Err(e) => {
+                error!("Failed to check username availability: {}", e);
+                return Err(FederationError::InternalServerError);
+            }
+        }
+    }
+
+    match sqlx::query_as::<_, User>(
+        r#"
+        INSERT INTO users (id, tenant_id, username, email, password_hash, is_active, is_verified)
+        VALUES ($1, $2, $3, $4, $5, true, true)
+        RETURNING *
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(tenant_id)
+    .bind(&username)
+    .bind(email)
+    .bind(password_hash)
+    .fetch_one(&mut **tx)
+    .await
+    {
+        Ok(user) => Ok(user),
+        Err(e) => {
+            if let Error::Database(db_err) = &e
+                && (db_err.constraint() == Some("users_tenant_id_username_key")
+                    || db_err.constraint() == Some("users_tenant_id_email_key"))
+            {
+                return Err(FederationError::AccountAlreadyExists);
+            }
+            error!("Failed to save federated user: {}", e);
+            Err(FederationError::InternalServerError)
+        }
+    }
+}
+
+async fn link_identity(
+    tx: &mut Transaction<'_, Postgres>,
+    user: &User,
+    provider: &IdentityProviderConfig,
+    identity: &ExternalIdentity,
+) -> Result<(), FederationError> {
+    match sqlx::query(
+        r#"
+        INSERT INTO federated_identities (id, tenant_id, user_id, provider, subject, email, last_login_at)
+        VALUES ($1, $2, $3, $4, $5, $6, NOW())
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(&user.tenant_id)
+    .bind(user.id)
+    .bind(&provider.name)
+    .bind(&identity.subject)
+    .bind(&identity.email)
+    .execute(&mut **tx)
+    .await
+    {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            error!("Failed to save federated identity: {}", e);
+            Err(FederationError::InternalServerError)
+        }
+    }
+}
+
+/// The provider authorization request, with PKCE on top of the client secret.
+pub fn authorization_url(
+    provider: &IdentityProviderConfig,
+    endpoints: &ProviderEndpoints,
+    redirect_url: &str,
+    state: &str,
+    code_challenge: &str,
+) -> Result<String, FederationError> {
+    let mut url = Url::parse(&endpoints.authorization_endpoint).map_err(|e| {
+        error!("Invalid authorization endpoint for {}: {}", provider.name, e);
+        FederationError::InternalServerError
+    })?;
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &provider.client_id)
+        .append_pair("redirect_uri", redirect_url)
+        .append_pair("scope", &provider.scopes.join(" "))
+        .append_pair("state", state)
+        .append_pair("code_challenge", code_challenge)
+        .append_pair("code_challenge_method", "S256");
+    Ok(url.to_string())
+}
+
+/// Reads a userinfo response, accepting string or numeric subjects and
+/// `email_verified` sent as a boolean or a string.
+pub fn parse_identity(provider: &IdentityProviderConfig, profile: &Value) -> Result<ExternalIdentity, FederationError> {
+    let subject = match profile.get(&provider.subject_claim) {
+        Some(Value::String(subject)) if !subject.is_empty() => subject.clone(),
+        Some(Value::Number(subject)) => subject.to_string(),
+        _ => {
+            return Err(FederationError::Provider(format!(
+                "{} did not return a {} claim",
+                provider.name, provider.subject_claim
+            )));
+        }
+    };
+    let email = profile
+        .get("email")
+        .and_then(Value::as_str)
+        .map(|email| email.trim().to_lowercase())
+        .filter(|email| !email.is_empty());
+    let email_verified = match profile.get("email_verified") {
+        Some(Value::Bool(verified)) => *verified,
+        Some(Value::String(verified)) => verified == "true",
+        _ => provider.trust_email,
+    };
+    let username = ["preferred_username", "login"]
+        .iter()
+        .find_map(|claim| profile.get(*claim).and_then(Value::as_str))
+        .map(str::to_string);
+
+    Ok(ExternalIdentity {
+        subject,
+        email,
+        email_verified,
+        username,
+    })
+}
+
+/// A username for a new account, from the provider's username or else the
+/// local part of the email, reduced to characters safe in URLs.
+fn username_candidate(username: Option<&str>, email: &str) -> String {
+    let source = username.unwrap_or_else(|| email.split('@').next().unwrap_or_default());
+    let candidate: String = source
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        .take(30)
+        .collect();
+    if candidate.len() < 3 {
+        format!("user{}", generate_numeric_code(6))
+    } else {
+        candidate
+    }
+}
+
+fn code_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+fn upstream_error(provider: &IdentityProviderConfig, context: &str, e: reqwest::Error) -> FederationError {
+    error!("Identity provider {}: {}: {}", provider.name, context, e);
+    FederationError::Provider(format!("{} {}", provider.name, context))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use crate::services::oauth::verify_pkce;
+    use axum::extract::Form;
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use http::{HeaderMap, StatusCode};
+    use serde_json::json;
+
+    const VERIFIER: &str = "dBjftJeZ4CVP-mJ0kzLEhFwQmLhTZPfEXb-Jm9GlFq0";
+
+    fn provider(issuer: &str) -> IdentityProviderConfig {
+        IdentityProviderConfig {
+            name: "stub".to_string(),
+            issuer: Some(issuer.to_string()),
+            authorization_endpoint: None,
+            token_endpoint: None,
+            userinfo_endpoint: None,
+            client_id: "client".to_string(),
+            client_secret: "secret".to_string(),
+            scopes: vec!["openid".to_string(), "email".to_string()],
+            subject_claim: "sub".to_string(),
+            trust_email: false,
+        }
+    }
+
+    fn federation() -> Federation {
+        let config = test_config();
+        let pool = PgPool::connect_lazy(&config.database.url).unwrap();
+        Federation::new(pool, Arc::new(config)).unwrap()
+    }
+
+    /// Serves discovery, token and userinfo endpoints on a random local port.
+    /// The token endpoint only accepts `good-code` with the right client
+    /// credentials and PKCE verifier.
+    async fn stub_idp(reported_issuer: Option<&str>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let issuer = reported_issuer.map(str::to_string).unwrap_or_else(|| base.clone());
+
+        let metadata = json!({
+            "issuer": issuer,
+            "authorization_endpoint": format!("{}/authorize", base),
+            "token_endpoint": format!("{}/token", base),
+            "userinfo_endpoint": format!("{}/userinfo", base),
+        });
+        let app = Router::new()
+            .route(
+                "/.well-known/openid-configuration",
+                get(move || async move { Json(metadata) }),
+            )
+            .route(
+                "/token",
+                post(|Form(form): Form<HashMap<String, String>>| async move {
+                    let field = |name: &str| form.get(name).map(String::as_str).unwrap_or_default();
+                    let valid = field("grant_type") == "authorization_code"
+                        && field("code") == "good-code"
+                        && field("client_id") == "client"
+                        && field("client_secret") == "secret"
+                        && field("redirect_uri") == "http://localhost/login/callback"
+                        && verify_pkce(field("code_verifier"), &code_challenge(VERIFIER));
+                    if valid {
+                        (StatusCode::OK, Json(json!({"access_token": "upstream-token", "token_type": "Bearer"})))
+                    } else {
+                        (StatusCode::BAD_REQUEST, Json(json!({"error": "invalid_grant"})))
+                    }
+                }),
+            )
+            .route(
+                "/userinfo",
+                get(|headers: HeaderMap| async move {
+                    let authorized = headers.get("authorization").and_then(|v| v.to_str().ok())
+                        == Some("Bearer upstream-token");
+                    if !authorized {
+                        return (StatusCode::UNAUTHORIZED, Json(json!({})));
+                    }
+                    (
+                        StatusCode::OK,
+                        Json(json!({
+                            "sub": "248289761001",
+                            "email": "Jane@Example.com",
+                            "email_verified": true,
+                            "preferred_username": "jane",
+                        })),
+                    )
+                }),
+            );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        base
+    }
+
+    #[tokio::test]
+    async fn test_endpoints_are_discovered_from_issuer() {
+        let issuer = stub_idp(None).await;
+        let endpoints = federation().endpoints(&provider(&issuer)).await.unwrap();
+
+        assert_eq!(endpoints.authorization_endpoint, format!("{}/authorize", issuer));
+        assert_eq!(endpoints.token_endpoint, format!("{}/token", issuer));
+        assert_eq!(endpoints.userinfo_endpoint, format!("{}/userinfo", issuer));
+    }
+
+    #[tokio::test]
+    async fn test_discovery_rejects_issuer_mismatch() {
+        let issuer = stub_idp(Some("https://attacker.example")).await;
+        let result = federation().endpoints(&provider(&issuer)).await;
+
+        assert!(matches!(result, Err(FederationError::Provider(_))));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_identity_redeems_code_with_pkce() {
+        let issuer = stub_idp(None).await;
+        let identity = federation()
+            .fetch_identity(&provider(&issuer), "good-code", VERIFIER)
+            .await
+            .unwrap();
+
+        assert_eq!(identity.subject, "248289761001");
+        assert_eq!(identity.email.as_deref(), Some("jane@example.com"));
+        assert!(identity.email_verified);
+        assert_eq!(identity.username.as_deref(), Some("jane"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_identity_rejects_bad_code_or_verifier() {
+        let issuer = stub_idp(None).await;
+        let federation = federation();
+        let provider = provider(&issuer);
+
+        let bad_code = federation.fetch_identity(&provider, "bad-code", VERIFIER).await;
+        assert!(matches!(bad_code, Err(FederationError::Provider(_))));
+
+        let bad_verifier = federation
+            .fetch_identity(&provider, "good-code", &VERIFIER.replace('d', "e"))
+            .await;
+        assert!(matches!(bad_verifier, Err(FederationError::Provider(_))));
+    }
+
+    #[test]
+    fn test_authorization_url_carries_state_and_pkce() {
+        let endpoints = ProviderEndpoints {
+            authorization_endpoint: "https://idp.example.com/authorize?prompt=login".to_string(),
+            token_endpoint: "https://idp.example.com/token".to_string(),
+            userinfo_endpoint: "https://idp.example.com/userinfo".to_string(),
+        };
+        let url = authorization_url(
+            &provider("https://idp.example.com"),
+            &endpoints,
+            "http://localhost/login/callback",
+            "state-123",
+            &code_challenge(VERIFIER),
+        )
+        .unwrap();
+
+        let url = Url::parse(&url).unwrap();
+        let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(query["prompt"], "login");
+        assert_eq!(query["client_id"], "client");
+        assert_eq!(query["redirect_uri"], "http://localhost/login/callback");
+        assert_eq!(query["scope"], "openid email");
+        assert_eq!(query["state"], "state-123");
+        assert_eq!(query["code_challenge_method"], "S256");
+        assert!(verify_pkce(VERIFIER, &query["code_challenge"]));
+    }
+
+    #[test]
+    fn test_parse_identity_github_profile() {
+        let github = IdentityProviderConfig {
+            subject_claim: "id".to_string(),
+            trust_email: true,
+            ..provider("https://github.com")
+        };
+        let profile = json!({"id": 583231, "login": "octocat", "email": "octocat@github.com"});
+
+        let identity = parse_identity(&github, &profile).unwrap();
+        assert_eq!(identity.subject, "583231");
+        assert!(identity.email_verified);
+        assert_eq!(identity.username.as_deref(), Some("octocat"));
+
+        // Without trust_email a missing email_verified means unverified.
+        let identity = parse_identity(&IdentityProviderConfig { trust_email: false, ..github }, &profile).unwrap();
+        assert!(!identity.email_verified);
+
+        let no_subject = parse_identity(&provider("https://idp.example.com"), &profile);
+        assert!(matches!(no_subject, Err(FederationError::Provider(_))));
+    }
+
+    #[test]
+    fn test_username_candidate() {
+        assert_eq!(username_candidate(Some("jane doe!"), "jane@example.com"), "janedoe");
+        assert_eq!(username_candidate(None, "j.smith+tag@example.com"), "j.smithtag");
+        assert!(username_candidate(None, "j@example.com").starts_with("user"));
+    }
+}
@@ -0,0 +1,494 @@
+use crate::config::{JwtConfig, VerificationKeyConfig};
+use anyhow::{Context, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, Utc};
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters, Jwk,
+    JwkSet, KeyAlgorithm, OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse,
+    RSAKeyParameters,
+};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, decode_header, encode,
+};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use rsa::pkcs8::DecodePublicKey;
+use rsa::traits::PublicKeyParts;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use sha2::{Digest, Sha256};
+use std::fs;
+
+/// The keys used to sign and verify access tokens.
+///
+/// Exactly one key signs new tokens. Keys rotated out of signing stay in the
+/// keyring, selected by `kid`, so tokens they issued keep verifying until the
+/// key is retired.
+pub struct JwtKeyring {
+    signing_key: JwtKey,
+    verification_keys: Vec<JwtKey>,
+}
+
+impl JwtKeyring {
+    pub fn from_config(config: &JwtConfig) -> anyhow::Result<Self> {
+        let signing_key = JwtKey::from_config(config)?;
+        let verification_keys = config
+            .verification_keys
+            .iter()
+            .map(JwtKey::from_verification_config)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Self::new(signing_key, verification_keys)
+    }
+
+    pub fn new(signing_key: JwtKey, verification_keys: Vec<JwtKey>) -> anyhow::Result<Self> {
+        if signing_key.encoding_key.is_none() {
+            return Err(anyhow!("The signing key has no private key"));
+        }
+        if verification_keys.iter().any(|key| key.kid.is_none()) {
+            return Err(anyhow!("Verification keys must have a key_id"));
+        }
+        if verification_keys.iter().any(|key| key.kid == signing_key.kid) {
+            return Err(anyhow!("Verification key_id collides with the signing key"));
+        }
+
+        Ok(Self {
+            signing_key,
+            verification_keys,
+        })
+    }
+
+    pub fn sign<T: Serialize>(&self, claims: &T) -> jsonwebtoken::errors::Result<String> {
+        let mut header = Header::new(self.signing_key.algorithm);
+        header.kid = self.signing_key.kid.clone();
+
+        let encoding_key = self
+            .signing_key
+            .encoding_key
+            .as_ref()
+            .expect("checked in JwtKeyring::new");
+        encode(&header, claims, encoding_key)
+    }
+
+    /// Verifies `token` with the key named by its `kid`, or the signing key when it has none.
+    ///
+    /// Tokens carrying an `aud` claim are rejected, see [`verify_for_audience`](Self::verify_for_audience).
+    pub fn verify<T: DeserializeOwned>(&self, token: &str) -> Result<T, VerifyError> {
+        self.verify_with(token, |_| {})
+    }
+
+    /// Like [`verify`](Self::verify), for tokens whose `aud` must be `audience`.
+    pub fn verify_for_audience<T: DeserializeOwned>(&self, token: &str, audience: &str) -> Result<T, VerifyError> {
+        self.verify_with(token, |validation| {
+            validation.set_audience(&[audience]);
+            // Otherwise tokens without any `aud` would pass.
+            validation.set_required_spec_claims(&["exp", "aud"]);
+        })
+    }
+
+    fn verify_with<T: DeserializeOwned>(
+        &self,
+        token: &str,
+        configure: impl FnOnce(&mut Validation),
+    ) -> Result<T, VerifyError> {
+        let header = decode_header(token).map_err(|_| VerifyError::Malformed)?;
+        let key = match header.kid.as_deref() {
+            None => &self.signing_key,
+            Some(kid) => self.find(kid).ok_or(VerifyError::UnknownKey)?,
+        };
+
+        let mut validation = Validation::new(key.algorithm);
+        configure(&mut validation);
+        decode::<T>(token, &key.decoding_key, &validation)
+            .map(|data| data.claims)
+            .map_err(|_| VerifyError::Invalid)
+    }
+
+    pub fn signing_algorithm(&self) -> Algorithm {
+        self.signing_key.algorithm
+    }
+
+    /// Public keys other services use to verify our tokens.
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self
+                .active_keys()
+                .filter_map(|key| key.jwk.clone())
+                .collect(),
+        }
+    }
+
+    fn find(&self, kid: &str) -> Option<&JwtKey> {
+        self.active_keys().find(|key| key.kid.as_deref() == Some(kid))
+    }
+
+    fn active_keys(&self) -> impl Iterator<Item = &JwtKey> {
+        let now = Utc::now();
+        std::iter::once(&self.signing_key).chain(
+            self.verification_keys
+                .iter()
+                .filter(move |key| key.retire_at.is_none_or(|retire_at| retire_at > now)),
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum VerifyError {
+    Malformed,
+    UnknownKey,
+    Invalid,
+}
+
+/// A single signing or verification key.
+pub struct JwtKey {
+    pub kid: Option<String>,
+    pub algorithm: Algorithm,
+    /// `None` for verify-only keys.
+    pub encoding_key: Option<EncodingKey>,
+    pub decoding_key: DecodingKey,
+    /// Public half of the key as published in the JWKS, `None` for shared secrets.
+    pub jwk: Option<Jwk>,
+    pub retire_at: Option<DateTime<Utc>>,
+}
+
+impl JwtKey {
+    pub fn from_config(config: &JwtConfig) -> anyhow::Result<Self> {
+        if config.algorithm == Algorithm::HS256 {
+            if config.secret.is_empty() {
+                return Err(anyhow!("jwt.secret is required for HS256"));
+            }
+            return Ok(Self::from_secret(config.key_id.clone(), &config.secret));
+        }
+
+        let private_key_path = config
+            .private_key_path
+            .as_ref()
+            .ok_or_else(|| anyhow!("jwt.private_key_path is required for {:?}", config.algorithm))?;
+        let public_key_path = config
+            .public_key_path
+            .as_ref()
+            .ok_or_else(|| anyhow!("jwt.public_key_path is required for {:?}", config.algorithm))?;
+
+        let private_pem = fs::read(private_key_path)
+            .with_context(|| format!("Failed to read {}", private_key_path))?;
+        let public_pem = fs::read(public_key_path)
+            .with_context(|| format!("Failed to read {}", public_key_path))?;
+
+        Self::from_pem(config.key_id.clone(), config.algorithm, &private_pem, &public_pem)
+    }
+
+    fn from_verification_config(config: &VerificationKeyConfig) -> anyhow::Result<Self> {
+        let kid = Some(config.key_id.clone());
+        let mut key = if config.algorithm == Algorithm::HS256 {
+            let secret = config
+                .secret
+                .as_ref()
+                .ok_or_else(|| anyhow!("secret is required for HS256 key {}", config.key_id))?;
+            let mut key = Self::from_secret(kid, secret);
+            key.encoding_key = None;
+            key
+        } else {
+            let public_key_path = config
+                .public_key_path
+                .as_ref()
+                .ok_or_else(|| anyhow!("public_key_path is required for key {}", config.key_id))?;
+            let public_pem = fs::read(public_key_path)
+                .with_context(|| format!("Failed to read {}", public_key_path))?;
+            Self::from_public_pem(kid, config.algorithm, &public_pem)?
+        };
+        key.retire_at = config.retire_at;
+        Ok(key)
+    }
+
+    pub fn from_secret(kid: Option<String>, secret: &str) -> Self {
+        Self {
+            kid,
+            algorithm: Algorithm::HS256,
+            encoding_key: Some(EncodingKey::from_secret(secret.as_bytes())),
+            decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+            jwk: None,
+            retire_at: None,
+        }
+    }
+
+    pub fn from_pem(
+        kid: Option<String>,
+        algorithm: Algorithm,
+        private_pem: &[u8],
+        public_pem: &[u8],
+    ) -> anyhow::Result<Self> {
+        let encoding_key = match algorithm {
+            Algorithm::RS256 => EncodingKey::from_rsa_pem(private_pem)?,
+            Algorithm::ES256 => EncodingKey::from_ec_pem(private_pem)?,
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(private_pem)?,
+            other => return Err(anyhow!("Unsupported JWT algorithm {:?}", other)),
+        };
+
+        let mut key = Self::from_public_pem(kid, algorithm, public_pem)?;
+        key.encoding_key = Some(encoding_key);
+        Ok(key)
+    }
+
+    pub fn from_public_pem(
+        kid: Option<String>,
+        algorithm: Algorithm,
+        public_pem: &[u8],
+    ) -> anyhow::Result<Self> {
+        let public_pem_str = std::str::from_utf8(public_pem).context("Public key is not PEM")?;
+
+        let (parameters, key_algorithm) = match algorithm {
+            Algorithm::RS256 => {
+                let key = rsa::RsaPublicKey::from_public_key_pem(public_pem_str)
+                    .context("Invalid RSA public key")?;
+                let parameters = AlgorithmParameters::RSA(RSAKeyParameters {
+                    n: URL_SAFE_NO_PAD.encode(key.n().to_bytes_be()),
+                    e: URL_SAFE_NO_PAD.encode(key.e().to_bytes_be()),
+                    ..Default::default()
+                });
+                (parameters, KeyAlgorithm::RS256)
+            }
+            Algorithm::ES256 => {
+                let key = p256::PublicKey::from_public_key_pem(public_pem_str)
+                    .context("Invalid P-256 public key")?;
+                let point = key.to_encoded_point(false);
+                let (x, y) = point
+                    .x()
+                    .zip(point.y())
+                    .ok_or_else(|| anyhow!("Invalid P-256 public key"))?;
+                let parameters = AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+                    curve: EllipticCurve::P256,
+                    x: URL_SAFE_NO_PAD.encode(x),
+                    y: URL_SAFE_NO_PAD.encode(y),
+                    ..Default::default()
+                });
+                (parameters, KeyAlgorithm::ES256)
+            }
+            Algorithm::EdDSA => {
+                let key = ed25519_dalek::VerifyingKey::from_public_key_pem(public_pem_str)
+                    .context("Invalid Ed25519 public key")?;
+                let parameters = AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x: URL_SAFE_NO_PAD.encode(key.as_bytes()),
+                });
+                (parameters, KeyAlgorithm::EdDSA)
+            }
+            other => return Err(anyhow!("Unsupported JWT algorithm {:?}", other)),
+        };
+
+        let kid = kid.unwrap_or_else(|| thumbprint(&parameters));
+        let jwk = Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(key_algorithm),
+                key_id: Some(kid.clone()),
+                ..Default::default()
+            },
+            algorithm: parameters,
+        };
+
+        Ok(Self {
+            kid: Some(kid),
+            algorithm,
+            encoding_key: None,
+            decoding_key: DecodingKey::from_jwk(&jwk)?,
+            jwk: Some(jwk),
+            retire_at: None,
+        })
+    }
+}
+
+/// RFC 7638 JWK thumbprint, used as the default `kid`.
+fn thumbprint(parameters: &AlgorithmParameters) -> String {
+    // Required members only, in lexicographic order.
+    let canonical = match parameters {
+        AlgorithmParameters::RSA(p) => format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, p.e, p.n),
+        AlgorithmParameters::EllipticCurve(p) => format!(
+            r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#,
+            p.x, p.y
+        ),
+        AlgorithmParameters::OctetKeyPair(p) => {
+            format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, p.x)
+        }
+        AlgorithmParameters::OctetKey(p) => format!(r#"{{"k":"{}","kty":"oct"}}"#, p.value),
+    };
+    URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes()))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    use crate::models::claims::Claims;
+    use chrono::Duration;
+
+    pub fn rsa_key(kid: &str) -> JwtKey {
+        JwtKey::from_pem(
+            Some(kid.to_string()),
+            Algorithm::RS256,
+            include_bytes!("../../tests/fixtures/keys/rsa.pem"),
+            include_bytes!("../../tests/fixtures/keys/rsa.pub.pem"),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rsa_key_publishes_jwk() {
+        let key = rsa_key("rsa-1");
+        let jwk = key.jwk.unwrap();
+
+        assert_eq!(jwk.common.key_id.as_deref(), Some("rsa-1"));
+        assert_eq!(jwk.common.key_algorithm, Some(KeyAlgorithm::RS256));
+        assert!(matches!(jwk.algorithm, AlgorithmParameters::RSA(ref p) if p.e == "AQAB"));
+    }
+
+    #[test]
+    fn test_ec_key_publishes_jwk() {
+        let key = JwtKey::from_pem(
+            None,
+            Algorithm::ES256,
+            include_bytes!("../../tests/fixtures/keys/ec.pem"),
+            include_bytes!("../../tests/fixtures/keys/ec.pub.pem"),
+        )
+        .unwrap();
+
+        let jwk = key.jwk.unwrap();
+        assert_eq!(jwk.common.key_id, key.kid);
+        assert!(matches!(jwk.algorithm, AlgorithmParameters::EllipticCurve(_)));
+    }
+
+    #[test]
+    fn test_ed25519_key_publishes_jwk() {
+        let key = JwtKey::from_pem(
+            None,
+            Algorithm::EdDSA,
+            include_bytes!("../../tests/fixtures/keys/ed25519.pem"),
+            include_bytes!("../../tests/fixtures/keys/ed25519.pub.pem"),
+        )
+        .unwrap();
+
+        let jwk = key.jwk.unwrap();
+        assert_eq!(jwk.common.key_algorithm, Some(KeyAlgorithm::EdDSA));
+        assert!(matches!(jwk.algorithm, AlgorithmParameters::OctetKeyPair(ref p) if p.x.len() == 43));
+    }
+
+    #[test]
+    fn test_mismatched_algorithm_is_rejected() {
+        let result = JwtKey::from_pem(
+            None,
+            Algorithm::ES256,
+            include_bytes!("../../tests/fixtures/keys/rsa.pem"),
+            include_bytes!("../../tests/fixtures/keys/rsa.pub.pem"),
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_secret_key_is_not_published() {
+        let key = JwtKey::from_secret(None, "test-secret");
+        assert!(key.jwk.is_none());
+    }
+
+    fn ed25519_key(kid: &str) -> JwtKey {
+        JwtKey::from_pem(
+            Some(kid.to_string()),
+            Algorithm::EdDSA,
+            include_bytes!("../../tests/fixtures/keys/ed25519.pem"),
+            include_bytes!("../../tests/fixtures/keys/ed25519.pub.pem"),
+        )
+        .unwrap()
+    }
+
+    fn verify_only(mut key: JwtKey, retire_at: DateTime<Utc>) -> JwtKey {
+        key.encoding_key = None;
+        key.retire_at = Some(retire_at);
+        key
+    }
+
+    fn claims() -> Claims {
+        let now = Utc::now().timestamp() as usize;
+        Claims {
+            sub: "user".to_string(),
+            exp: now + 900,
+            iat: now,
+            jti: None,
+            scope: None,
+            aud: None,
+            tid: None,
+            roles: vec![],
+            permissions: vec![],
+            org: None,
+            org_role: None,
+        }
+    }
+
+    #[test]
+    fn test_audience_is_checked_only_when_expected() {
+        let keyring = JwtKeyring::new(rsa_key("rsa-1"), vec![]).unwrap();
+        let user_token = keyring.sign(&claims()).unwrap();
+        let client_token = keyring
+            .sign(&Claims {
+                aud: Some("https://api.example.com".to_string()),
+                ..claims()
+            })
+            .unwrap();
+
+        assert!(keyring.verify::<Claims>(&user_token).is_ok());
+        assert!(keyring.verify::<Claims>(&client_token).is_err());
+        assert!(keyring.verify_for_audience::<Claims>(&client_token, "https://api.example.com").is_ok());
+        assert!(keyring.verify_for_audience::<Claims>(&client_token, "https://other.example.com").is_err());
+        assert!(keyring.verify_for_audience::<Claims>(&user_token, "https://api.example.com").is_err());
+    }
+
+    #[test]
+    fn test_token_from_retired_signing_key_still_verifies() {
+        let old = JwtKeyring::new(rsa_key("old"), vec![]).unwrap();
+        let token = old.sign(&claims()).unwrap();
+
+        let rotated = JwtKeyring::new(
+            ed25519_key("new"),
+            vec![verify_only(rsa_key("old"), Utc::now() + Duration::hours(1))],
+        )
+        .unwrap();
+
+        let verified: Claims = rotated.verify(&token).unwrap();
+        assert_eq!(verified.sub, "user");
+        assert_eq!(rotated.jwks().keys.len(), 2);
+    }
+
+    #[test]
+    fn test_new_tokens_are_signed_with_current_key() {
+        let keyring = JwtKeyring::new(
+            ed25519_key("new"),
+            vec![verify_only(rsa_key("old"), Utc::now() + Duration::hours(1))],
+        )
+        .unwrap();
+
+        let token = keyring.sign(&claims()).unwrap();
+        let header = decode_header(&token).unwrap();
+        assert_eq!(header.kid.as_deref(), Some("new"));
+        assert_eq!(header.alg, Algorithm::EdDSA);
+    }
+
+    #[test]
+    fn test_token_from_expired_retirement_is_rejected() {
+        let old = JwtKeyring::new(rsa_key("old"), vec![]).unwrap();
+        let token = old.sign(&claims()).unwrap();
+
+        let rotated = JwtKeyring::new(
+            ed25519_key("new"),
+            vec![verify_only(rsa_key("old"), Utc::now() - Duration::seconds(1))],
+        )
+        .unwrap();
+
+        let result = rotated.verify::<Claims>(&token);
+        assert!(matches!(result, Err(VerifyError::UnknownKey)));
+        assert_eq!(rotated.jwks().keys.len(), 1);
+    }
+
+    #[test]
+    fn test_signing_key_without_private_key_is_rejected() {
+        let key = verify_only(rsa_key("old"), Utc::now());
+        assert!(JwtKeyring::new(key, vec![]).is_err());
+    }
+}
@@ -0,0 +1,361 @@
+use crate::config::Config;
+use crate::error::authentication::AuthenticationError;
+use crate::models::authenticate::{RecoveryCodeRecord, RecoveryCodes, TotpEnrollment};
+use crate::models::user::User;
+use crate::utils::clock::Clock;
+use crate::utils::security::{decrypt_secret, encrypt_secret, hash_password, verify_password};
+use crate::utils::totp;
+use anyhow::anyhow;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use sqlx::PgPool;
+use std::sync::Arc;
+use tracing::info;
+use tracing::log::error;
+use uuid::Uuid;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_ALPHABET: &[u8; 32] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+pub struct Mfa {
+    pool: PgPool,
+    config: Arc<Config>,
+    clock: Arc<dyn Clock>,
+    encryption_key: [u8; 32],
+}
+
+impl Mfa {
+    pub fn new(pool: PgPool, config: Arc<Config>, clock: Arc<dyn Clock>) -> anyhow::Result<Self> {
+        let encryption_key = STANDARD
+            .decode(&config.mfa.secret)
+            .ok()
+            .and_then(|key| <[u8; 32]>::try_from(key).ok())
+            .ok_or_else(|| anyhow!("mfa.secret must be a base64-encoded 32 byte key"))?;
+
+        Ok(Self {
+            pool,
+            config,
+            clock,
+            encryption_key,
+        })
+    }
+
+    /// Generates a new TOTP secret for `user`, pending until confirmed with a first code.
+    pub async fn begin_totp_enrollment(&self, user: &User) -> Result<TotpEnrollment, AuthenticationError> {
+        if user.totp_enabled_at.is_some() {
+            return Err(AuthenticationError::MfaAlreadyEnabled);
+        }
+
+        let secret = totp::generate_secret();
+        let encrypted = encrypt_secret(&self.encryption_key, &secret);
+        match sqlx::query(
+            r#"
+            UPDATE users
+            SET totp_secret = $1, totp_enabled_at = NULL, totp_last_step = NULL, updated_at = NOW()
+            WHERE id = $2
+            "#,
+        )
+        .bind(encrypted)
+        .bind(user.id)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(_) => {}
+            Err(e) => {
+                error!("Failed to save TOTP secret for user {}: {}", user.id, e);
+                return Err(AuthenticationError::InternalServerError);
+            }
+        };
+
+        Ok(TotpEnrollment {
+            secret: totp::base32_encode(&secret),
+            otpauth_uri: totp::otpauth_uri(&self.config.mfa.issuer, &user.email, &secret),
+        })
+    }
+
+    /// Enables TOTP once the first code checks out, returning the initial recovery codes.
+    pub async fn confirm_totp_enrollment(&self, user: &User, code: &str) -> Result<RecoveryCodes, AuthenticationError> {
+        if user.totp_enabled_at.is_some() {
+            return Err(AuthenticationError::MfaAlreadyEnabled);
+        }
+        let encrypted = user
+            .totp_secret
+            .as_ref()
+            .ok_or(AuthenticationError::MfaNotEnrolled)?;
+        let step = self.check_code(encrypted, code)?;
+
+        match sqlx::query(
+            r#"
+            UPDATE users
+            SET totp_enabled_at = NOW(), totp_last_step = $1, updated_at = NOW()
+            WHERE id = $2 AND totp_enabled_at IS NULL
+            "#,
+        )
+        .bind(step)
+        .bind(user.id)
+        .execute(&self.pool)
+        .await
+        {
+            // A concurrent confirmation got there first and issued the recovery codes.
+            Ok(res) if res.rows_affected() == 0 => return Err(AuthenticationError::MfaAlreadyEnabled),
+            Ok(_) => {
+                info!("TOTP enabled for user {}", user.id);
+            }
+            Err(e) => {
+                error!("Failed to enable TOTP for user {}: {}", user.id, e);
+                return Err(AuthenticationError::InternalServerError);
+            }
+        }
+
+        self.replace_recovery_codes(&user.id).await
+    }
+
+    /// Issues a fresh set of recovery codes, invalidating any previous set.
+    pub async fn regenerate_recovery_codes(&self, user: &User) -> Result<RecoveryCodes, AuthenticationError> {
+        if user.totp_enabled_at.is_none() {
+            return Err(AuthenticationError::MfaNotEnrolled);
+        }
+        self.replace_recovery_codes(&user.id).await
+    }
+
+    /// Redeems one unused recovery code in place of a TOTP code.
+    pub async fn verify_recovery_code(&self, user: &User, code: &str) -> Result<(), AuthenticationError> {
+        let records = match sqlx::query_as::<_, RecoveryCodeRecord>(
+            "SELECT id, code_hash FROM recovery_codes WHERE user_id = $1 AND used_at IS NULL",
+        )
+        .bind(user.id)
+        .fetch_all(&self.pool)
+        .await
+        {
+            Ok(records) => records,
+            Err(e) => {
+                error!("Failed to fetch recovery codes for user {}: {}", user.id, e);
+                return Err(AuthenticationError::InternalServerError);
+            }
+        };
+
+        let code = normalize_recovery_code(code);
+        let record = records
+            .iter()
+            .find(|record| verify_password(&code, &record.code_hash))
+            .ok_or(AuthenticationError::InvalidMfaCode)?;
+
+        match sqlx::query("UPDATE recovery_codes SET used_at = NOW() WHERE id = $1 AND used_at IS NULL")
+            .bind(record.id)
+            .execute(&self.pool)
+            .await
+        {
+            Ok(res) if res.rows_affected() == 0 => Err(AuthenticationError::InvalidMfaCode),
+            Ok(_) => {
+                info!("Recovery code used by user {}", user.id);
+                Ok(())
+            }
+            Err(e) => {
+                error!("Failed to mark recovery code used: {}", e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    async fn replace_recovery_codes(&self, user_id: &Uuid) -> Result<RecoveryCodes, AuthenticationError> {
+        let codes: Vec<String> = (0..RECOVERY_CODE_COUNT).map(|_| generate_recovery_code()).collect();
+        let mut hashes = Vec::with_capacity(codes.len());
+        for code in &codes {
+            match hash_password(&normalize_recovery_code(code)) {
+                Ok(hash) => hashes.push(hash),
+                Err(_) => return Err(AuthenticationError::InternalServerError),
+            }
+        }
+
+        let result: Result<(), sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1")
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await?;
+            for hash in &hashes {
+                sqlx::query("INSERT INTO recovery_codes (id, user_id, code_hash) VALUES ($1, $2, $3)")
+                    .bind(Uuid::new_v4())
+                    .bind(user_id)
+                    .bind(hash)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            tx.commit().await
+        }
+        .await;
+
+        match result {
+            Ok(_) => Ok(RecoveryCodes { codes }),
+            Err(e) => {
+                error!("Failed to save recovery codes for user {}: {}", user_id, e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    /// Verifies a TOTP code for an enrolled user, refusing codes at or before the last accepted step.
+    pub async fn verify_totp(&self, user: &User, code: &str) -> Result<(), AuthenticationError> {
+        let encrypted = match (&user.totp_secret, user.totp_enabled_at) {
+            (Some(secret), Some(_)) => secret,
+            _ => return Err(AuthenticationError::MfaNotEnrolled),
+        };
+        let step = self.check_code(encrypted, code)?;
+
+        self.record_totp_step(&user.id, step).await
+    }
+
+    async fn record_totp_step(&self, user_id: &Uuid, step: i64) -> Result<(), AuthenticationError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET totp_last_step = $1
+            WHERE id = $2 AND (totp_last_step IS NULL OR totp_last_step < $1)
+            "#,
+        )
+        .bind(step)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await;
+
+        match result {
+            Ok(res) if res.rows_affected() == 0 => Err(AuthenticationError::InvalidMfaCode),
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("Failed to record TOTP step for user {}: {}", user_id, e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    fn check_code(&self, encrypted_secret: &str, code: &str) -> Result<i64, AuthenticationError> {
+        let secret = match decrypt_secret(&self.encryption_key, encrypted_secret) {
+            Some(secret) => secret,
+            None => {
+                error!("Failed to decrypt TOTP secret");
+                return Err(AuthenticationError::InternalServerError);
+            }
+        };
+
+        totp::verify(&secret, code.trim(), self.clock.now()).ok_or(AuthenticationError::InvalidMfaCode)
+    }
+}
+
+/// A code like `ABCDE-FGH23`, avoiding characters that are easily confused.
+fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; 10];
+    OsRng.fill_bytes(&mut bytes);
+    let chars: String = bytes
+        .iter()
+        .map(|b| RECOVERY_CODE_ALPHABET[(*b as usize) % RECOVERY_CODE_ALPHABET.len()] as char)
+        .collect();
+    format!("{}-{}", &chars[..5], &chars[5..])
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use crate::utils::clock::FixedClock;
+    use chrono::{TimeZone, Utc};
+
+    const SECRET: &[u8] = b"12345678901234567890";
+
+    fn test_service(timestamp: i64) -> Mfa {
+        let config = Arc::new(test_config());
+        let pool = PgPool::connect_lazy(&config.database.url).unwrap();
+        let clock = Arc::new(FixedClock(Utc.timestamp_opt(timestamp, 0).unwrap()));
+        Mfa::new(pool, config, clock).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_check_code_uses_clock() {
+        let service = test_service(59);
+        let encrypted = encrypt_secret(&service.encryption_key, SECRET);
+
+        assert_eq!(service.check_code(&encrypted, "287082").unwrap(), 1);
+        assert!(matches!(
+            service.check_code(&encrypted, "123456"),
+            Err(AuthenticationError::InvalidMfaCode)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_check_code_rejects_code_from_another_time() {
+        let service = test_service(1111111109);
+        let encrypted = encrypt_secret(&service.encryption_key, SECRET);
+
+        assert!(service.check_code(&encrypted, "081804").is_ok());
+        assert!(service.check_code(&encrypted, "287082").is_err());
+    }
+
+    #[test]
+    fn test_generate_recovery_code_format() {
+        let code = generate_recovery_code();
+        assert_eq!(code.len(), 11);
+        assert_eq!(&code[5..6], "-");
+        assert!(
+            code.chars()
+                .filter(|c| *c != '-')
+                .all(|c| RECOVERY_CODE_ALPHABET.contains(&(c as u8)))
+        );
+    }
+
+    #[test]
+    fn test_normalize_recovery_code() {
+        assert_eq!(normalize_recovery_code(" abcde-fgh23 "), "ABCDEFGH23");
+        assert_eq!(
+            normalize_recovery_code("ABCDE-FGH23"),
+            normalize_recovery_code("abcdefgh23")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_new_rejects_invalid_key() {
+        let mut config = test_config();
+        config.mfa.secret = "too-short".to_string();
+        let pool = PgPool::connect_lazy(&config.database.url).unwrap();
+        assert!(Mfa::new(pool, Arc::new(config), Arc::new(FixedClock(Utc::now()))).is_err());
+    }
+
+    #[sqlx::test]
+    async fn test_enrollment_is_only_confirmed_once(pool: PgPool) {
+        let config = Arc::new(test_config());
+        let clock = Arc::new(FixedClock(Utc.timestamp_opt(59, 0).unwrap()));
+        let service = Mfa::new(pool.clone(), config, clock).unwrap();
+        let encrypted = encrypt_secret(&service.encryption_key, SECRET);
+        let user_id = Uuid::new_v4();
+        sqlx::query("INSERT INTO users (id, username, email, password_hash, totp_secret) VALUES ($1, 'bob', 'bob@example.com', '', $2)")
+            .bind(user_id)
+            .bind(&encrypted)
+            .execute(&pool)
+            .await
+            .unwrap();
+        // Both confirmations start from the user as loaded before either ran.
+        let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+
+        let codes = service.confirm_totp_enrollment(&user, "287082").await.unwrap();
+        assert!(matches!(
+            service.confirm_totp_enrollment(&user, "287082").await,
+            Err(AuthenticationError::MfaAlreadyEnabled)
+        ));
+        let stored: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM recovery_codes WHERE user_id = $1")
+            .bind(user_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(stored, codes.codes.len() as i64);
+    }
+}
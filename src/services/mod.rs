@@ -1,4 +1,13 @@
+pub mod api_keys;
 pub mod authentication;
+pub mod denylist;
 pub mod email;
-mod traits;
+pub mod federation;
+pub mod jwt_keys;
+pub mod mfa;
+pub mod oauth;
+pub mod organizations;
+pub mod passkeys;
+pub mod roles;
+pub mod traits;
 pub mod users;
@@ -0,0 +1,500 @@
+use crate::config::Config;
+use crate::error::oauth::OAuthError;
+use crate::models::oauth::{
+    AuthorizationCode, AuthorizationRequest, OAuthClient, OpenIdConfiguration, RegisteredClient,
+};
+use crate::models::request::CreateOAuthClient;
+use crate::models::user::User;
+use crate::utils::security::{generate_token, hash_password, hash_token, verify_password};
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use chrono::{Duration, Utc};
+use jsonwebtoken::Algorithm;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::sync::Arc;
+use tracing::info;
+use url::Url;
+use tracing::log::error;
+use uuid::Uuid;
+
+const AUTHORIZATION_CODE_TTL_SECONDS: i64 = 60;
+pub const SUPPORTED_SCOPES: [&str; 3] = ["openid", "email", "profile"];
+
+pub struct OAuth {
+    pool: PgPool,
+    config: Arc<Config>,
+}
+
+impl OAuth {
+    pub fn new(pool: PgPool, config: Arc<Config>) -> Self {
+        Self { pool, config }
+    }
+
+    /// Provider metadata served at `/.well-known/openid-configuration`.
+    pub fn openid_configuration(&self, signing_algorithm: Algorithm) -> OpenIdConfiguration {
+        let issuer = self.config.oidc.issuer.trim_end_matches('/');
+        OpenIdConfiguration {
+            issuer: issuer.to_string(),
+            authorization_endpoint: format!("{}/oauth/authorize", issuer),
+            token_endpoint: format!("{}/oauth/token", issuer),
+            userinfo_endpoint: format!("{}/oauth/userinfo", issuer),
+            jwks_uri: format!("{}/.well-known/jwks.json", issuer),
+            introspection_endpoint: format!("{}/oauth/introspect", issuer),
+            revocation_endpoint: format!("{}/oauth/revoke", issuer),
+            response_types_supported: vec!["code"],
+            grant_types_supported: vec!["authorization_code", "refresh_token", "client_credentials"],
+            subject_types_supported: vec!["public"],
+            id_token_signing_alg_values_supported: vec![format!("{:?}", signing_algorithm)],
+            scopes_supported: SUPPORTED_SCOPES.to_vec(),
+            token_endpoint_auth_methods_supported: vec!["client_secret_basic", "client_secret_post", "none"],
+            code_challenge_methods_supported: vec!["S256"],
+            claims_supported: vec!["sub", "iss", "aud", "exp", "iat", "nonce", "email", "email_verified", "preferred_username"],
+        }
+    }
+
+    /// The login page URL carrying the authorization request along.
+    pub fn login_redirect(&self, request: &AuthorizationRequest) -> Result<String, OAuthError> {
+        let mut login_url = Url::parse(&self.config.oidc.login_url).map_err(|e| {
+            error!("Invalid oidc.login_url: {}", e);
+            OAuthError::ServerError
+        })?;
+        {
+            let mut query = login_url.query_pairs_mut();
+            query
+                .append_pair("response_type", &request.response_type)
+                .append_pair("client_id", &request.client_id)
+                .append_pair("redirect_uri", &request.redirect_uri)
+                .append_pair("scope", &request.scope);
+            let optional = [
+                ("state", &request.state),
+                ("nonce", &request.nonce),
+                ("code_challenge", &request.code_challenge),
+                ("code_challenge_method", &request.code_challenge_method),
+            ];
+            for (name, value) in optional {
+                if let Some(value) = value {
+                    query.append_pair(name, value);
+                }
+            }
+        }
+        Ok(login_url.to_string())
+    }
+
+    pub async fn find_client(&self, client_id: &str) -> Result<Option<OAuthClient>, OAuthError> {
+        match sqlx::query_as::<_, OAuthClient>(
+            "SELECT * FROM oauth_clients WHERE client_id = $1 AND revoked_at IS NULL",
+        )
+        .bind(client_id)
+        .fetch_optional(&self.pool)
+        .await
+        {
+            Ok(client) => Ok(client),
+            Err(e) => {
+                error!("Failed to fetch OAuth client {}: {}", client_id, e);
+                Err(OAuthError::ServerError)
+            }
+        }
+    }
+
+    pub async fn create_client(&self, request: &CreateOAuthClient) -> Result<RegisteredClient, OAuthError> {
+        if let Some(uri) = request.redirect_uris.iter().find(|uri| Url::parse(uri).is_err()) {
+            return Err(OAuthError::InvalidRequest(format!("Invalid redirect URI {}", uri)));
+        }
+        if request.scopes.iter().any(|scope| scope.is_empty() || scope.contains(char::is_whitespace)) {
+            return Err(OAuthError::InvalidRequest("Scopes must be non-empty and contain no spaces".to_string()));
+        }
+
+        let client_id = Uuid::new_v4().simple().to_string();
+        let client_secret = request.confidential.then(generate_token);
+        let client_secret_hash = match client_secret.as_deref().map(hash_password).transpose() {
+            Ok(hash) => hash,
+            Err(_) => return Err(OAuthError::ServerError),
+        };
+
+        match sqlx::query(
+            r#"
+            INSERT INTO oauth_clients (id, client_id, client_secret_hash, name, redirect_uris, scopes)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(&client_id)
+        .bind(client_secret_hash)
+        .bind(&request.name)
+        .bind(&request.redirect_uris)
+        .bind(&request.scopes)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(_) => {
+                info!("OAuth client {} registered", client_id);
+                Ok(RegisteredClient {
+                    client_id,
+                    client_secret,
+                })
+            }
+            Err(e) => {
+                error!("Failed to register OAuth client: {}", e);
+                Err(OAuthError::ServerError)
+            }
+        }
+    }
+
+    pub async fn list_clients(&self) -> Result<Vec<OAuthClient>, OAuthError> {
+        match sqlx::query_as::<_, OAuthClient>("SELECT * FROM oauth_clients ORDER BY created_at")
+            .fetch_all(&self.pool)
+            .await
+        {
+            Ok(clients) => Ok(clients),
+            Err(e) => {
+                error!("Failed to fetch OAuth clients: {}", e);
+                Err(OAuthError::ServerError)
+            }
+        }
+    }
+
+    /// Stops a client from authenticating; tokens it already holds run until they expire.
+    pub async fn revoke_client(&self, client_id: &str) -> Result<(), OAuthError> {
+        match sqlx::query("UPDATE oauth_clients SET revoked_at = NOW() WHERE client_id = $1 AND revoked_at IS NULL")
+            .bind(client_id)
+            .execute(&self.pool)
+            .await
+        {
+            Ok(res) if res.rows_affected() == 0 => Err(OAuthError::InvalidRequest("Unknown client_id".to_string())),
+            Ok(_) => {
+                info!("OAuth client {} revoked", client_id);
+                Ok(())
+            }
+            Err(e) => {
+                error!("Failed to revoke OAuth client {}: {}", client_id, e);
+                Err(OAuthError::ServerError)
+            }
+        }
+    }
+
+    /// Authenticates a client at the token endpoint.
+    ///
+    /// Confidential clients must present their secret; public clients must not.
+    pub async fn authenticate_client(
+        &self,
+        client_id: &str,
+        client_secret: Option<&str>,
+    ) -> Result<OAuthClient, OAuthError> {
+        let client = self.find_client(client_id).await?.ok_or(OAuthError::InvalidClient)?;
+
+        let authenticated = match (&client.client_secret_hash, client_secret) {
+            (Some(hash), Some(secret)) => verify_password(secret, hash),
+            (None, None) => true,
+            _ => false,
+        };
+        if !authenticated {
+            return Err(OAuthError::InvalidClient);
+        }
+        Ok(client)
+    }
+
+    pub async fn create_authorization_code(
+        &self,
+        user: &User,
+        request: &AuthorizationRequest,
+    ) -> Result<String, OAuthError> {
+        let code = generate_token();
+
+        match sqlx::query(
+            r#"
+            INSERT INTO oauth_authorization_codes
+                (id, code_hash, client_id, user_id, redirect_uri, scope, nonce, code_challenge, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(hash_token(&code))
+        .bind(&request.client_id)
+        .bind(user.id)
+        .bind(&request.redirect_uri)
+        .bind(normalize_scope(&request.scope))
+        .bind(&request.nonce)
+        .bind(&request.code_challenge)
+        .bind(Utc::now() + Duration::seconds(AUTHORIZATION_CODE_TTL_SECONDS))
+        .execute(&self.pool)
+        .await
+        {
+            Ok(_) => {
+                info!("Authorization code issued to client {} for user {}", request.client_id, user.id);
+                Ok(code)
+            }
+            Err(e) => {
+                error!("Failed to save authorization code: {}", e);
+                Err(OAuthError::ServerError)
+            }
+        }
+    }
+
+    /// Redeems an authorization code, which is deleted whether or not the
+    /// remaining checks pass so it can never be tried twice.
+    pub async fn exchange_authorization_code(
+        &self,
+        client: &OAuthClient,
+        code: &str,
+        redirect_uri: Option<&str>,
+        code_verifier: Option<&str>,
+    ) -> Result<AuthorizationCode, OAuthError> {
+        let result = sqlx::query_as::<_, AuthorizationCode>(
+            r#"
+            DELETE FROM oauth_authorization_codes
+            WHERE code_hash = $1
+            RETURNING client_id, user_id, redirect_uri, scope, nonce, code_challenge, expires_at
+            "#,
+        )
+        .bind(hash_token(code))
+        .fetch_optional(&self.pool)
+        .await;
+
+        let authorization_code = match result {
+            Ok(Some(authorization_code)) => authorization_code,
+            Ok(None) => return Err(OAuthError::InvalidGrant("Invalid authorization code".to_string())),
+            Err(e) => {
+                error!("Failed to consume authorization code: {}", e);
+                return Err(OAuthError::ServerError);
+            }
+        };
+
+        check_code_redemption(&authorization_code, client, redirect_uri, code_verifier)?;
+        Ok(authorization_code)
+    }
+}
+
+/// Checks an authorization request from a known client against its registration.
+///
+/// Errors here are safe to report back to the client's redirect URI; a bad
+/// `redirect_uri` must be caught before, see [`redirect_uri_is_registered`].
+pub fn check_authorization_request(request: &AuthorizationRequest) -> Result<(), OAuthError> {
+    if request.response_type != "code" {
+        return Err(OAuthError::UnsupportedResponseType);
+    }
+    if let Some(scope) = request
+        .scope
+        .split_whitespace()
+        .find(|scope| !SUPPORTED_SCOPES.contains(scope))
+    {
+        return Err(OAuthError::InvalidScope(format!("Unsupported scope {}", scope)));
+    }
+
+    match (&request.code_challenge, request.code_challenge_method.as_deref()) {
+        (Some(challenge), Some("S256")) if challenge.len() == 43 => Ok(()),
+        (Some(_), Some("S256")) => Err(OAuthError::InvalidRequest("Malformed code_challenge".to_string())),
+        (Some(_), _) => Err(OAuthError::InvalidRequest(
+            "code_challenge_method must be S256".to_string(),
+        )),
+        (None, _) => Err(OAuthError::InvalidRequest("code_challenge is required".to_string())),
+    }
+}
+
+/// Resolves the scope of a client_credentials token: all of the client's
+/// scopes by default, otherwise a subset of them.
+pub fn grant_client_scope(client: &OAuthClient, requested: Option<&str>) -> Result<String, OAuthError> {
+    if client.client_secret_hash.is_none() {
+        return Err(OAuthError::UnauthorizedClient);
+    }
+
+    match requested {
+        None => Ok(client.scopes.join(" ")),
+        Some(requested) => {
+            if let Some(scope) = requested.split_whitespace().find(|scope| !client.scopes.iter().any(|s| s == scope)) {
+                return Err(OAuthError::InvalidScope(format!("Scope {} is not allowed for this client", scope)));
+            }
+            Ok(normalize_scope(requested))
+        }
+    }
+}
+
+/// Redirect URIs must match a registered one exactly, RFC 6749 section 3.1.2.3.
+pub fn redirect_uri_is_registered(client: &OAuthClient, redirect_uri: &str) -> bool {
+    client.redirect_uris.iter().any(|uri| uri == redirect_uri)
+}
+
+fn check_code_redemption(
+    authorization_code: &AuthorizationCode,
+    client: &OAuthClient,
+    redirect_uri: Option<&str>,
+    code_verifier: Option<&str>,
+) -> Result<(), OAuthError> {
+    if authorization_code.expires_at <= Utc::now() {
+        return Err(OAuthError::InvalidGrant("Authorization code expired".to_string()));
+    }
+    if authorization_code.client_id != client.client_id {
+        return Err(OAuthError::InvalidGrant("Authorization code was issued to another client".to_string()));
+    }
+    if redirect_uri != Some(authorization_code.redirect_uri.as_str()) {
+        return Err(OAuthError::InvalidGrant("redirect_uri does not match".to_string()));
+    }
+
+    let code_verifier = code_verifier.ok_or_else(|| OAuthError::InvalidRequest("code_verifier is required".to_string()))?;
+    if !verify_pkce(code_verifier, &authorization_code.code_challenge) {
+        return Err(OAuthError::InvalidGrant("code_verifier does not match".to_string()));
+    }
+    Ok(())
+}
+
+/// S256 PKCE check, RFC 7636 section 4.6.
+pub fn verify_pkce(code_verifier: &str, code_challenge: &str) -> bool {
+    (43..=128).contains(&code_verifier.len())
+        && URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes())) == code_challenge
+}
+
+/// Splits an `Authorization: Basic` value into client id and secret.
+pub fn parse_basic_auth(header: &str) -> Option<(String, String)> {
+    let encoded = header.strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(STANDARD.decode(encoded.trim()).ok()?).ok()?;
+    let (client_id, client_secret) = decoded.split_once(':')?;
+    Some((client_id.to_string(), client_secret.to_string()))
+}
+
+fn normalize_scope(scope: &str) -> String {
+    scope.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VERIFIER: &str = "dBjftJeZ4CVP-mJ0kzLEhFwQmLhTZPfEXb-Jm9GlFq0";
+    const CHALLENGE: &str = "tcjePoQvYKqHB7lsJkj53pnMzTWqgSG6CZxmNJcBaeY";
+
+    fn client() -> OAuthClient {
+        OAuthClient {
+            client_id: "web".to_string(),
+            client_secret_hash: None,
+            name: "Web".to_string(),
+            redirect_uris: vec!["https://app.example.com/callback".to_string()],
+            scopes: vec![],
+            created_at: Utc::now(),
+            revoked_at: None,
+        }
+    }
+
+    fn machine_client() -> OAuthClient {
+        OAuthClient {
+            client_secret_hash: Some("hash".to_string()),
+            scopes: vec!["reports:read".to_string(), "reports:write".to_string()],
+            ..client()
+        }
+    }
+
+    fn request() -> AuthorizationRequest {
+        AuthorizationRequest {
+            response_type: "code".to_string(),
+            client_id: "web".to_string(),
+            redirect_uri: "https://app.example.com/callback".to_string(),
+            scope: "openid email".to_string(),
+            state: Some("xyz".to_string()),
+            nonce: None,
+            code_challenge: Some(CHALLENGE.to_string()),
+            code_challenge_method: Some("S256".to_string()),
+        }
+    }
+
+    fn authorization_code() -> AuthorizationCode {
+        AuthorizationCode {
+            client_id: "web".to_string(),
+            user_id: Uuid::new_v4(),
+            redirect_uri: "https://app.example.com/callback".to_string(),
+            scope: "openid".to_string(),
+            nonce: None,
+            code_challenge: CHALLENGE.to_string(),
+            expires_at: Utc::now() + Duration::seconds(AUTHORIZATION_CODE_TTL_SECONDS),
+        }
+    }
+
+    #[test]
+    fn test_verify_pkce() {
+        assert!(verify_pkce(VERIFIER, CHALLENGE));
+        assert!(!verify_pkce(&VERIFIER.replace('d', "e"), CHALLENGE));
+        assert!(!verify_pkce("short", CHALLENGE));
+    }
+
+    #[test]
+    fn test_authorization_request_requires_s256_pkce() {
+        assert!(check_authorization_request(&request()).is_ok());
+
+        let mut plain = request();
+        plain.code_challenge_method = Some("plain".to_string());
+        assert!(matches!(check_authorization_request(&plain), Err(OAuthError::InvalidRequest(_))));
+
+        let mut missing = request();
+        missing.code_challenge = None;
+        assert!(matches!(check_authorization_request(&missing), Err(OAuthError::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn test_authorization_request_rejects_unknown_scope_and_response_type() {
+        let mut scope = request();
+        scope.scope = "openid admin".to_string();
+        assert!(matches!(check_authorization_request(&scope), Err(OAuthError::InvalidScope(_))));
+
+        let mut token = request();
+        token.response_type = "token".to_string();
+        assert!(matches!(
+            check_authorization_request(&token),
+            Err(OAuthError::UnsupportedResponseType)
+        ));
+    }
+
+    #[test]
+    fn test_client_scope_defaults_to_all_allowed_scopes() {
+        assert_eq!(
+            grant_client_scope(&machine_client(), None).unwrap(),
+            "reports:read reports:write"
+        );
+        assert_eq!(
+            grant_client_scope(&machine_client(), Some(" reports:read ")).unwrap(),
+            "reports:read"
+        );
+    }
+
+    #[test]
+    fn test_client_scope_rejects_unlisted_scope_and_public_clients() {
+        assert!(matches!(
+            grant_client_scope(&machine_client(), Some("reports:read admin")),
+            Err(OAuthError::InvalidScope(_))
+        ));
+        assert!(matches!(
+            grant_client_scope(&client(), None),
+            Err(OAuthError::UnauthorizedClient)
+        ));
+    }
+
+    #[test]
+    fn test_redirect_uri_must_match_exactly() {
+        assert!(redirect_uri_is_registered(&client(), "https://app.example.com/callback"));
+        assert!(!redirect_uri_is_registered(&client(), "https://app.example.com/callback/"));
+        assert!(!redirect_uri_is_registered(&client(), "https://evil.example.com/callback"));
+    }
+
+    #[test]
+    fn test_code_redemption_checks_client_redirect_and_verifier() {
+        let redirect = Some("https://app.example.com/callback");
+        assert!(check_code_redemption(&authorization_code(), &client(), redirect, Some(VERIFIER)).is_ok());
+
+        let mut other_client = client();
+        other_client.client_id = "other".to_string();
+        assert!(check_code_redemption(&authorization_code(), &other_client, redirect, Some(VERIFIER)).is_err());
+        assert!(check_code_redemption(&authorization_code(), &client(), None, Some(VERIFIER)).is_err());
+        assert!(check_code_redemption(&authorization_code(), &client(), redirect, None).is_err());
+
+        let mut expired = authorization_code();
+        expired.expires_at = Utc::now() - Duration::seconds(1);
+        assert!(check_code_redemption(&expired, &client(), redirect, Some(VERIFIER)).is_err());
+    }
+
+    #[test]
+    fn test_parse_basic_auth() {
+        let header = format!("Basic {}", STANDARD.encode("web:s3cret:with-colon"));
+        assert_eq!(
+            parse_basic_auth(&header),
+            Some(("web".to_string(), "s3cret:with-colon".to_string()))
+        );
+        assert_eq!(parse_basic_auth("Bearer abc"), None);
+    }
+}
@@ -0,0 +1,547 @@
+use crate::config::Config;
+use crate::error::organization::OrganizationError;
+use crate::models::organization::{
+    InvitationRecord, Organization, OrganizationInvitation, OrganizationMember, OrganizationRole,
+};
+use crate::models::user::User;
+use crate::services::email::EmailService;
+use crate::services::traits::EmailServiceBase;
+use crate::utils::security::{generate_token, hash_token};
+use chrono::{Duration, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use tracing::{error, info};
+use uuid::Uuid;
+
+const INVITATION_TTL_DAYS: i64 = 7;
+
+pub struct Organizations {
+    pool: PgPool,
+    config: Arc<Config>,
+}
+
+impl Organizations {
+    pub fn new(pool: PgPool, config: Arc<Config>) -> Self {
+        Self { pool, config }
+    }
+
+    /// Creates an organization in the user's tenant, with the user as its owner.
+    pub async fn create_organization(&self, user: &User, name: &str) -> Result<Organization, OrganizationError> {
+        let organization_id = Uuid::new_v4();
+        let result: Result<Organization, sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            let created_at = sqlx::query_scalar(
+                "INSERT INTO organizations (id, tenant_id, name) VALUES ($1, $2, $3) RETURNING created_at",
+            )
+            .bind(organization_id)
+            .bind(&user.tenant_id)
+            .bind(name)
+            .fetch_one(&mut *tx)
+            .await?;
+            sqlx::query("INSERT INTO organization_members (organization_id, user_id, role) VALUES ($1, $2, $3)")
+                .bind(organization_id)
+                .bind(user.id)
+                .bind(OrganizationRole::Owner.as_str())
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            Ok(Organization {
+                id: organization_id,
+                name: name.to_string(),
+                role: OrganizationRole::Owner,
+                created_at,
+            })
+        }
+        .await;
+
+        match result {
+            Ok(organization) => {
+                info!("Organization {} created by user {}", organization_id, user.id);
+                Ok(organization)
+            }
+            Err(e) => {
+                error!("Failed to create organization: {}", e);
+                Err(OrganizationError::InternalServerError)
+            }
+        }
+    }
+
+    /// Organizations the user belongs to, with the user's role in each.
+    pub async fn list_organizations(&self, user_id: &Uuid) -> Result<Vec<Organization>, OrganizationError> {
+        match sqlx::query_as::<_, Organization>(
+            r#"
+            SELECT o.id, o.name, m.role, o.created_at
+            FROM organization_members m
+            JOIN organizations o ON o.id = m.organization_id
+            WHERE m.user_id = $1
+            ORDER BY o.name, o.id
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        {
+            Ok(organizations) => Ok(organizations),
+            Err(e) => {
+                error!("Failed to list organizations of user {}: {}", user_id, e);
+                Err(OrganizationError::InternalServerError)
+            }
+        }
+    }
+
+    /// The organization as seen by `user_id`, who must be a member of it.
+    pub async fn get_organization(
+        &self,
+        organization_id: &Uuid,
+        user_id: &Uuid,
+    ) -> Result<Organization, OrganizationError> {
+        match sqlx::query_as::<_, Organization>(
+            r#"
+            SELECT o.id, o.name, m.role, o.created_at
+            FROM organization_members m
+            JOIN organizations o ON o.id = m.organization_id
+            WHERE m.organization_id = $1 AND m.user_id = $2
+            "#,
+        )
+        .bind(organization_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        {
+            Ok(Some(organization)) => Ok(organization),
+            Ok(None) => Err(OrganizationError::OrganizationNotFound),
+            Err(e) => {
+                error!("Failed to fetch organization {}: {}", organization_id, e);
+                Err(OrganizationError::InternalServerError)
+            }
+        }
+    }
+
+    /// The role of `user_id` in the organization, failing unless it is at least `required`.
+    pub async fn require_role(
+        &self,
+        organization_id: &Uuid,
+        user_id: &Uuid,
+        required: OrganizationRole,
+    ) -> Result<OrganizationRole, OrganizationError> {
+        let organization = self.get_organization(organization_id, user_id).await?;
+        if organization.role < required {
+            return Err(OrganizationError::InsufficientRole);
+        }
+        Ok(organization.role)
+    }
+
+    pub async fn delete_organization(&self, organization_id: &Uuid, user_id: &Uuid) -> Result<(), OrganizationError> {
+        self.require_role(organization_id, user_id, OrganizationRole::Owner).await?;
+
+        match sqlx::query("DELETE FROM organizations WHERE id = $1")
+            .bind(organization_id)
+            .execute(&self.pool)
+            .await
+        {
+            Ok(_) => {
+                info!("Organization {} deleted by user {}", organization_id, user_id);
+                Ok(())
+            }
+            Err(e) => {
+                error!("Failed to delete organization {}: {}", organization_id, e);
+                Err(OrganizationError::InternalServerError)
+            }
+        }
+    }
+
+    pub async fn list_members(&self, organization_id: &Uuid) -> Result<Vec<OrganizationMember>, OrganizationError> {
+        match sqlx::query_as::<_, OrganizationMember>(
+            r#"
+            SELECT u.id AS user_id, u.username, u.email, m.role, m.created_at AS joined_at
+            FROM organization_members m
+            JOIN users u ON u.id = m.user_id
+            WHERE m.organization_id = $1
+            ORDER BY m.created_at, u.id
+            "#,
+        )
+        .bind(organization_id)
+        .fetch_all(&self.pool)
+        .await
+        {
+            Ok(members) => Ok(members),
+            Err(e) => {
+                error!("Failed to list members of organization {}: {}", organization_id, e);
+                Err(OrganizationError::InternalServerError)
+            }
+        }
+    }
+
+    /// Changes a member's role. Admins manage members and admins; only owners
+    /// can grant or take away ownership, and the last owner cannot step down.
+    pub async fn update_member_role(
+        &self,
+        organization_id: &Uuid,
+        actor_id: &Uuid,
+        member_id: &Uuid,
+        role: OrganizationRole,
+    ) -> Result<(), OrganizationError> {
+        let actor_role = self.require_role(organization_id, actor_id, OrganizationRole::Admin).await?;
+
+        let mut tx = self.begin(organization_id).await?;
+        let current = member_role(&mut tx, organization_id, member_id).await?;
+        if (current == OrganizationRole::Owner || role == OrganizationRole::Owner) && actor_role < OrganizationRole::Owner {
+            return Err(OrganizationError::InsufficientRole);
+        }
+        if current == OrganizationRole::Owner && role != OrganizationRole::Owner {
+            ensure_other_owner(&mut tx, organization_id, member_id).await?;
+        }
+
+        sqlx::query("UPDATE organization_members SET role = $1 WHERE organization_id = $2 AND user_id = $3")
+            .bind(role.as_str())
+            .bind(organization_id)
+            .bind(member_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                error!("Failed to update member {} of organization {}: {}", member_id, organization_id, e);
+                OrganizationError::InternalServerError
+            })?;
+        commit(tx).await?;
+        info!("Member {} of organization {} is now {}", member_id, organization_id, role.as_str());
+        Ok(())
+    }
+
+    /// Removes a member, or lets a member leave when `actor_id` is `member_id`.
+    pub async fn remove_member(
+        &self,
+        organization_id: &Uuid,
+        actor_id: &Uuid,
+        member_id: &Uuid,
+    ) -> Result<(), OrganizationError> {
+        let required = if actor_id == member_id {
+            OrganizationRole::Member
+        } else {
+            OrganizationRole::Admin
+        };
+        let actor_role = self.require_role(organization_id, actor_id, required).await?;
+
+        let mut tx = self.begin(organization_id).await?;
+        let current = member_role(&mut tx, organization_id, member_id).await?;
+        if current == OrganizationRole::Owner {
+            if actor_role < OrganizationRole::Owner {
+                return Err(OrganizationError::InsufficientRole);
+            }
+            ensure_other_owner(&mut tx, organization_id, member_id).await?;
+        }
+
+        sqlx::query("DELETE FROM organization_members WHERE organization_id = $1 AND user_id = $2")
+            .bind(organization_id)
+            .bind(member_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                error!("Failed to remove member {} of organization {}: {}", member_id, organization_id, e);
+                OrganizationError::InternalServerError
+            })?;
+        commit(tx).await?;
+        info!("Member {} removed from organization {}", member_id, organization_id);
+        Ok(())
+    }
+
+    /// Emails an invitation to join the organization, replacing any invitation
+    /// still pending for the address. Only owners can invite owners.
+    pub async fn invite(
+        &self,
+        email_service: &EmailService,
+        organization_id: &Uuid,
+        inviter: &User,
+        email: &str,
+        role: OrganizationRole,
+    ) -> Result<OrganizationInvitation, OrganizationError> {
+        let organization = self.get_organization(organization_id, &inviter.id).await?;
+        if organization.role < OrganizationRole::Admin
+            || (role == OrganizationRole::Owner && organization.role < OrganizationRole::Owner)
+        {
+            return Err(OrganizationError::InsufficientRole);
+        }
+
+        let token = generate_token();
+        let result: Result<OrganizationInvitation, sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            sqlx::query(
+                r#"
+                UPDATE organization_invitations
+                SET revoked_at = NOW()
+                WHERE organization_id = $1 AND lower(email) = lower($2)
+                    AND accepted_at IS NULL AND revoked_at IS NULL
+                "#,
+            )
+            .bind(organization_id)
+            .bind(email)
+            .execute(&mut *tx)
+            .await?;
+            let invitation = sqlx::query_as::<_, OrganizationInvitation>(
+                r#"
+                INSERT INTO organization_invitations (id, organization_id, email, role, token_hash, invited_by, expires_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                RETURNING id, email, role, invited_by, expires_at, created_at
+                "#,
+            )
+            .bind(Uuid::new_v4())
+            .bind(organization_id)
+            .bind(email)
+            .bind(role.as_str())
+            .bind(hash_token(&token))
+            .bind(inviter.id)
+            .bind(Utc::now() + Duration::days(INVITATION_TTL_DAYS))
+            .fetch_one(&mut *tx)
+            .await?;
+            tx.commit().await?;
+            Ok(invitation)
+        }
+        .await;
+        let invitation = match result {
+            Ok(invitation) => invitation,
+            Err(e) => {
+                error!("Failed to save invitation to organization {}: {}", organization_id, e);
+                return Err(OrganizationError::InternalServerError);
+            }
+        };
+        info!("User {} invited {} to organization {}", inviter.id, email, organization_id);
+
+        let accept_url = format!("{}?token={}", self.config.app_for(&inviter.tenant_id).invitation_url, token);
+        let template_string = format!(
+            r#"
+            Hello,
+
+            {} invited you to join {} as {}. Click the link below to accept:
+            <a href={}>accept invitation</a>
+
+            The invitation expires in {} days. If you were not expecting it, you can ignore this email.
+
+                Best regards,
+                Your App Team
+                "#,
+            inviter.username,
+            organization.name,
+            role.as_str(),
+            accept_url,
+            INVITATION_TTL_DAYS,
+        );
+        let _ = email_service
+            .send_email(
+                &inviter.tenant_id,
+                email.to_string(),
+                vec![],
+                vec![],
+                format!("Invitation to join {}", organization.name),
+                template_string,
+            )
+            .await;
+
+        Ok(invitation)
+    }
+
+    /// Invitations of the organization that can still be accepted.
+    pub async fn list_invitations(
+        &self,
+        organization_id: &Uuid,
+    ) -> Result<Vec<OrganizationInvitation>, OrganizationError> {
+        match sqlx::query_as::<_, OrganizationInvitation>(
+            r#"
+            SELECT id, email, role, invited_by, expires_at, created_at
+            FROM organization_invitations
+            WHERE organization_id = $1 AND accepted_at IS NULL AND revoked_at IS NULL AND expires_at > NOW()
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(organization_id)
+        .fetch_all(&self.pool)
+        .await
+        {
+            Ok(invitations) => Ok(invitations),
+            Err(e) => {
+                error!("Failed to list invitations of organization {}: {}", organization_id, e);
+                Err(OrganizationError::InternalServerError)
+            }
+        }
+    }
+
+    pub async fn revoke_invitation(
+        &self,
+        organization_id: &Uuid,
+        actor_id: &Uuid,
+        invitation_id: &Uuid,
+    ) -> Result<(), OrganizationError> {
+        self.require_role(organization_id, actor_id, OrganizationRole::Admin).await?;
+
+        match sqlx::query(
+            r#"
+            UPDATE organization_invitations
+            SET revoked_at = NOW()
+            WHERE id = $1 AND organization_id = $2 AND accepted_at IS NULL AND revoked_at IS NULL
+            "#,
+        )
+        .bind(invitation_id)
+        .bind(organization_id)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(res) if res.rows_affected() == 0 => Err(OrganizationError::InvitationNotFound),
+            Ok(_) => {
+                info!("Invitation {} to organization {} revoked", invitation_id, organization_id);
+                Ok(())
+            }
+            Err(e) => {
+                error!("Failed to revoke invitation {}: {}", invitation_id, e);
+                Err(OrganizationError::InternalServerError)
+            }
+        }
+    }
+
+    /// Adds the user to the organization an invitation was sent for. The user
+    /// must have the invited address; a member keeps a higher role they already have.
+    pub async fn accept_invitation(&self, user: &User, token: &str) -> Result<Organization, OrganizationError> {
+        let mut tx = self.pool.begin().await.map_err(|e| {
+            error!("Failed to start transaction: {}", e);
+            OrganizationError::InternalServerError
+        })?;
+
+        let invitation = sqlx::query_as::<_, InvitationRecord>(
+            r#"
+            SELECT i.id, i.organization_id, o.tenant_id, i.email, i.role, i.expires_at, i.accepted_at, i.revoked_at
+            FROM organization_invitations i
+            JOIN organizations o ON o.id = i.organization_id
+            WHERE i.token_hash = $1
+            FOR UPDATE OF i
+            "#,
+        )
+        .bind(hash_token(token))
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(|e| {
+            error!("Failed to fetch invitation: {}", e);
+            OrganizationError::InternalServerError
+        })?;
+        let invitation = match invitation {
+            Some(invitation)
+                if invitation.accepted_at.is_none()
+                    && invitation.revoked_at.is_none()
+                    && invitation.expires_at > Utc::now()
+                    && invitation.tenant_id == user.tenant_id =>
+            {
+                invitation
+            }
+            _ => return Err(OrganizationError::InvalidInvitation),
+        };
+        if !invitation.email.eq_ignore_ascii_case(&user.email) {
+            return Err(OrganizationError::InvitationForAnotherEmail);
+        }
+
+        let role = match member_role(&mut tx, &invitation.organization_id, &user.id).await {
+            Ok(current) => current.max(invitation.role),
+            Err(OrganizationError::MemberNotFound) => invitation.role,
+            Err(e) => return Err(e),
+        };
+        let result: Result<(), sqlx::Error> = async {
+            sqlx::query(
+                r#"
+                INSERT INTO organization_members (organization_id, user_id, role)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (organization_id, user_id) DO UPDATE SET role = EXCLUDED.role
+                "#,
+            )
+            .bind(invitation.organization_id)
+            .bind(user.id)
+            .bind(role.as_str())
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query("UPDATE organization_invitations SET accepted_at = NOW() WHERE id = $1")
+                .bind(invitation.id)
+                .execute(&mut *tx)
+                .await?;
+            Ok(())
+        }
+        .await;
+        if let Err(e) = result {
+            error!("Failed to accept invitation {}: {}", invitation.id, e);
+            return Err(OrganizationError::InternalServerError);
+        }
+        commit(tx).await?;
+        info!("User {} joined organization {}", user.id, invitation.organization_id);
+
+        self.get_organization(&invitation.organization_id, &user.id).await
+    }
+
+    /// Starts a transaction holding the organization's row, so that membership
+    /// changes checking for other owners do not interleave.
+    async fn begin(&self, organization_id: &Uuid) -> Result<Transaction<'_, Postgres>, OrganizationError> {
+        let result: Result<_, sqlx::Error> = async {
+            let mut tx = self.pool.begin().await?;
+            sqlx::query("SELECT id FROM organizations WHERE id = $1 FOR UPDATE")
+                .bind(organization_id)
+                .execute(&mut *tx)
+                .await?;
+            Ok(tx)
+        }
+        .await;
+        result.map_err(|e| {
+            error!("Failed to lock organization {}: {}", organization_id, e);
+            OrganizationError::InternalServerError
+        })
+    }
+}
+
+async fn member_role(
+    tx: &mut Transaction<'_, Postgres>,
+    organization_id: &Uuid,
+    user_id: &Uuid,
+) -> Result<OrganizationRole, OrganizationError> {
+    let role = sqlx::query_scalar::<_, String>(
+        "SELECT role FROM organization_members WHERE organization_id = $1 AND user_id = $2",
+    )
+    .bind(organization_id)
+    .bind(user_id)
+    .fetch_optional(&mut **tx)
+    .await
+    .map_err(|e| {
+        error!("Failed to fetch member {} of organization {}: {}", user_id, organization_id, e);
+        OrganizationError::InternalServerError
+    })?
+    .ok_or(OrganizationError::MemberNotFound)?;
+    OrganizationRole::try_from(role).map_err(|e| {
+        error!("{}", e);
+        OrganizationError::InternalServerError
+    })
+}
+
+async fn ensure_other_owner(
+    tx: &mut Transaction<'_, Postgres>,
+    organization_id: &Uuid,
+    user_id: &Uuid,
+) -> Result<(), OrganizationError> {
+    let other_owner = sqlx::query_scalar::<_, bool>(
+        r#"
+        SELECT EXISTS(
+            SELECT 1 FROM organization_members
+            WHERE organization_id = $1 AND user_id <> $2 AND role = $3
+        )
+        "#,
+    )
+    .bind(organization_id)
+    .bind(user_id)
+    .bind(OrganizationRole::Owner.as_str())
+    .fetch_one(&mut **tx)
+    .await
+    .map_err(|e| {
+        error!("Failed to check owners of organization {}: {}", organization_id, e);
+        OrganizationError::InternalServerError
+    })?;
+    if !other_owner {
+        return Err(OrganizationError::LastOwner);
+    }
+    Ok(())
+}
+
+async fn commit(tx: Transaction<'_, Postgres>) -> Result<(), OrganizationError> {
+    tx.commit().await.map_err(|e| {
+        error!("Failed to commit organization change: {}", e);
+        OrganizationError::InternalServerError
+    })
+}
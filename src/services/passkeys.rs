@@ -0,0 +1,592 @@
+use crate::config::Config;
+use crate::error::authentication::AuthenticationError;
+use crate::models::passkey::{
+    AuthenticationCredential, AuthenticatorSelection, CreationOptions, CredentialDescriptor,
+    CredentialParameter, Passkey, PasskeyUser, RegistrationCredential, RelyingParty, RequestOptions,
+    VerifiedAssertion, VerifiedRegistration,
+};
+use crate::models::user::User;
+use crate::utils::security::{generate_token, hash_token};
+use crate::utils::webauthn::{
+    AuthenticatorData, ClientData, CoseKey, SUPPORTED_ALGORITHMS, attestation_auth_data, signed_data,
+};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{Duration, Utc};
+use sqlx::{Error, PgPool};
+use std::sync::Arc;
+use tracing::log::error;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+const CHALLENGE_TTL_SECONDS: i64 = 300;
+const CEREMONY_REGISTRATION: &str = "registration";
+const CEREMONY_AUTHENTICATION: &str = "authentication";
+
+pub struct Passkeys {
+    pool: PgPool,
+    config: Arc<Config>,
+}
+
+impl Passkeys {
+    pub fn new(pool: PgPool, config: Arc<Config>) -> Self {
+        Self { pool, config }
+    }
+
+    pub async fn begin_registration(&self, user: &User) -> Result<CreationOptions, AuthenticationError> {
+        let existing = self.list_passkeys(&user.id).await?;
+        let challenge = self.create_challenge(Some(&user.id), CEREMONY_REGISTRATION).await?;
+
+        Ok(CreationOptions {
+            challenge,
+            rp: RelyingParty {
+                id: self.config.webauthn.rp_id.clone(),
+                name: self.config.webauthn.rp_name.clone(),
+            },
+            user: PasskeyUser {
+                id: URL_SAFE_NO_PAD.encode(user.id.as_bytes()),
+                name: user.email.clone(),
+                display_name: user.username.clone(),
+            },
+            pub_key_cred_params: SUPPORTED_ALGORITHMS
+                .iter()
+                .map(|alg| CredentialParameter {
+                    kind: "public-key".to_string(),
+                    alg: *alg,
+                })
+                .collect(),
+            timeout: (CHALLENGE_TTL_SECONDS * 1000) as u64,
+            exclude_credentials: existing
+                .into_iter()
+                .map(|passkey| CredentialDescriptor {
+                    kind: "public-key".to_string(),
+                    id: passkey.credential_id,
+                })
+                .collect(),
+            authenticator_selection: AuthenticatorSelection {
+                resident_key: "required".to_string(),
+                user_verification: "preferred".to_string(),
+            },
+            attestation: "none".to_string(),
+        })
+    }
+
+    pub async fn finish_registration(
+        &self,
+        user: &User,
+        name: Option<String>,
+        credential: &RegistrationCredential,
+    ) -> Result<Passkey, AuthenticationError> {
+        let (_, client_data) = ClientData::from_base64(&credential.response.client_data_json)
+            .ok_or_else(|| invalid_registration("Malformed client data"))?;
+        self.consume_challenge(&client_data.challenge, CEREMONY_REGISTRATION, Some(&user.id))
+            .await?;
+        let verified = self.verify_registration(credential, &client_data.challenge)?;
+
+        let result = sqlx::query_as::<_, Passkey>(
+            r#"
+            INSERT INTO passkey_credentials (id, user_id, credential_id, public_key, sign_count, name)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING *
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user.id)
+        .bind(&verified.credential_id)
+        .bind(&verified.public_key)
+        .bind(i64::from(verified.sign_count))
+        .bind(name)
+        .fetch_one(&self.pool)
+        .await;
+
+        match result {
+            Ok(passkey) => {
+                info!("Passkey {} registered for user {}", passkey.id, user.id);
+                Ok(passkey)
+            }
+            Err(Error::Database(db_err)) if db_err.constraint() == Some("passkey_credentials_credential_id_key") => {
+                Err(invalid_registration("Passkey is already registered"))
+            }
+            Err(e) => {
+                error!("Failed to save passkey for user {}: {}", user.id, e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    /// Starts a discoverable-credential login; the authenticator picks the account.
+    pub async fn begin_authentication(&self) -> Result<RequestOptions, AuthenticationError> {
+        let challenge = self.create_challenge(None, CEREMONY_AUTHENTICATION).await?;
+
+        Ok(RequestOptions {
+            challenge,
+            rp_id: self.config.webauthn.rp_id.clone(),
+            timeout: (CHALLENGE_TTL_SECONDS * 1000) as u64,
+            allow_credentials: vec![],
+            user_verification: "preferred".to_string(),
+        })
+    }
+
+    /// Verifies an assertion and advances the credential's sign counter,
+    /// returning the owning user's id. Passkeys of users in other tenants
+    /// are treated as unknown.
+    pub async fn finish_authentication(
+        &self,
+        tenant_id: &str,
+        credential: &AuthenticationCredential,
+    ) -> Result<(Uuid, VerifiedAssertion), AuthenticationError> {
+        let (_, client_data) = ClientData::from_base64(&credential.response.client_data_json)
+            .ok_or(AuthenticationError::InvalidCredentials)?;
+        self.consume_challenge(&client_data.challenge, CEREMONY_AUTHENTICATION, None)
+            .await?;
+
+        let passkey = match sqlx::query_as::<_, Passkey>(
+            r#"
+            SELECT passkey_credentials.* FROM passkey_credentials
+            JOIN users ON users.id = passkey_credentials.user_id
+            WHERE passkey_credentials.credential_id = $1 AND users.tenant_id = $2
+            "#,
+        )
+        .bind(&credential.raw_id)
+        .bind(tenant_id)
+        .fetch_optional(&self.pool)
+        .await
+        {
+            Ok(Some(passkey)) => passkey,
+            Ok(None) => return Err(AuthenticationError::InvalidCredentials),
+            Err(e) => {
+                error!("Failed to fetch passkey: {}", e);
+                return Err(AuthenticationError::InternalServerError);
+            }
+        };
+        let assertion = self.verify_assertion(credential, &client_data.challenge, &passkey)?;
+
+        // Guard on the old counter so two concurrent assertions cannot both succeed.
+        match sqlx::query(
+            r#"
+            UPDATE passkey_credentials
+            SET sign_count = $1, last_used_at = NOW()
+            WHERE id = $2 AND sign_count = $3
+            "#,
+        )
+        .bind(i64::from(assertion.sign_count))
+        .bind(passkey.id)
+        .bind(passkey.sign_count)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(res) if res.rows_affected() == 0 => Err(AuthenticationError::InvalidCredentials),
+            Ok(_) => Ok((passkey.user_id, assertion)),
+            Err(e) => {
+                error!("Failed to update passkey {}: {}", passkey.id, e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    pub async fn list_passkeys(&self, user_id: &Uuid) -> Result<Vec<Passkey>, AuthenticationError> {
+        match sqlx::query_as::<_, Passkey>(
+            "SELECT * FROM passkey_credentials WHERE user_id = $1 ORDER BY created_at",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        {
+            Ok(passkeys) => Ok(passkeys),
+            Err(e) => {
+                error!("Failed to fetch passkeys for user {}: {}", user_id, e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    pub async fn delete_passkey(&self, user_id: &Uuid, passkey_id: &Uuid) -> Result<(), AuthenticationError> {
+        match sqlx::query("DELETE FROM passkey_credentials WHERE id = $1 AND user_id = $2")
+            .bind(passkey_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+        {
+            Ok(res) if res.rows_affected() == 0 => Err(AuthenticationError::PasskeyNotFound),
+            Ok(_) => {
+                info!("Passkey {} removed by user {}", passkey_id, user_id);
+                Ok(())
+            }
+            Err(e) => {
+                error!("Failed to delete passkey {}: {}", passkey_id, e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    fn verify_registration(
+        &self,
+        credential: &RegistrationCredential,
+        expected_challenge: &str,
+    ) -> Result<VerifiedRegistration, AuthenticationError> {
+        let (_, client_data) = ClientData::from_base64(&credential.response.client_data_json)
+            .ok_or_else(|| invalid_registration("Malformed client data"))?;
+        if !self.client_data_matches(&client_data, "webauthn.create", expected_challenge) {
+            return Err(invalid_registration("Client data does not match the registration"));
+        }
+
+        let auth_data = URL_SAFE_NO_PAD
+            .decode(&credential.response.attestation_object)
+            .ok()
+            .and_then(|attestation| attestation_auth_data(&attestation))
+            .and_then(|auth_data| AuthenticatorData::parse(&auth_data))
+            .ok_or_else(|| invalid_registration("Malformed attestation object"))?;
+        if !auth_data.matches_rp_id(&self.config.webauthn.rp_id) || !auth_data.user_present() {
+            return Err(invalid_registration("Authenticator data does not match the registration"));
+        }
+
+        let attested = auth_data
+            .attested_credential
+            .ok_or_else(|| invalid_registration("Attestation is missing the credential"))?;
+        if URL_SAFE_NO_PAD.decode(&credential.raw_id).ok().as_ref() != Some(&attested.credential_id) {
+            return Err(invalid_registration("Credential id does not match the attestation"));
+        }
+        if CoseKey::from_cbor(&attested.public_key).is_none() {
+            return Err(invalid_registration("Unsupported credential public key"));
+        }
+
+        Ok(VerifiedRegistration {
+            credential_id: URL_SAFE_NO_PAD.encode(&attested.credential_id),
+            public_key: attested.public_key,
+            sign_count: auth_data.sign_count,
+        })
+    }
+
+    fn verify_assertion(
+        &self,
+        credential: &AuthenticationCredential,
+        expected_challenge: &str,
+        passkey: &Passkey,
+    ) -> Result<VerifiedAssertion, AuthenticationError> {
+        let response = &credential.response;
+        let (client_data_json, client_data) = ClientData::from_base64(&response.client_data_json)
+            .ok_or(AuthenticationError::InvalidCredentials)?;
+        if !self.client_data_matches(&client_data, "webauthn.get", expected_challenge) {
+            return Err(AuthenticationError::InvalidCredentials);
+        }
+
+        let raw_auth_data = URL_SAFE_NO_PAD
+            .decode(&response.authenticator_data)
+            .map_err(|_| AuthenticationError::InvalidCredentials)?;
+        let auth_data = AuthenticatorData::parse(&raw_auth_data).ok_or(AuthenticationError::InvalidCredentials)?;
+        if !auth_data.matches_rp_id(&self.config.webauthn.rp_id) || !auth_data.user_present() {
+            return Err(AuthenticationError::InvalidCredentials);
+        }
+
+        if let Some(user_handle) = &response.user_handle
+            && URL_SAFE_NO_PAD.decode(user_handle).ok().as_deref() != Some(passkey.user_id.as_bytes().as_slice())
+        {
+            return Err(AuthenticationError::InvalidCredentials);
+        }
+
+        let key = CoseKey::from_cbor(&passkey.public_key).ok_or_else(|| {
+            error!("Stored public key of passkey {} cannot be parsed", passkey.id);
+            AuthenticationError::InternalServerError
+        })?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(&response.signature)
+            .map_err(|_| AuthenticationError::InvalidCredentials)?;
+        if !key.verify(&signed_data(&raw_auth_data, &client_data_json), &signature) {
+            return Err(AuthenticationError::InvalidCredentials);
+        }
+
+        // Authenticators that keep a counter must increase it; one that goes
+        // backwards suggests the credential was cloned.
+        let sign_count = i64::from(auth_data.sign_count);
+        if (sign_count != 0 || passkey.sign_count != 0) && sign_count <= passkey.sign_count {
+            warn!("Sign counter of passkey {} did not increase", passkey.id);
+            return Err(AuthenticationError::InvalidCredentials);
+        }
+
+        Ok(VerifiedAssertion {
+            sign_count: auth_data.sign_count,
+            user_verified: auth_data.user_verified(),
+        })
+    }
+
+    fn client_data_matches(&self, client_data: &ClientData, ceremony: &str, expected_challenge: &str) -> bool {
+        client_data.ceremony == ceremony
+            && client_data.challenge == expected_challenge
+            && client_data.origin == self.config.webauthn.origin
+    }
+
+    async fn create_challenge(&self, user_id: Option<&Uuid>, ceremony: &str) -> Result<String, AuthenticationError> {
+        let challenge = generate_token();
+
+        match sqlx::query(
+            r#"
+            INSERT INTO webauthn_challenges (id, user_id, ceremony, challenge_hash, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(ceremony)
+        .bind(hash_token(&challenge))
+        .bind(Utc::now() + Duration::seconds(CHALLENGE_TTL_SECONDS))
+        .execute(&self.pool)
+        .await
+        {
+            Ok(_) => Ok(challenge),
+            Err(e) => {
+                error!("Failed to save {} challenge: {}", ceremony, e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+
+    /// Deletes a pending challenge so each one backs a single ceremony.
+    async fn consume_challenge(
+        &self,
+        challenge: &str,
+        ceremony: &str,
+        user_id: Option<&Uuid>,
+    ) -> Result<(), AuthenticationError> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM webauthn_challenges
+            WHERE challenge_hash = $1 AND ceremony = $2 AND expires_at > NOW()
+                AND user_id IS NOT DISTINCT FROM $3
+            "#,
+        )
+        .bind(hash_token(challenge))
+        .bind(ceremony)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await;
+
+        match result {
+            Ok(res) if res.rows_affected() == 0 => Err(AuthenticationError::InvalidToken),
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("Failed to consume {} challenge: {}", ceremony, e);
+                Err(AuthenticationError::InternalServerError)
+            }
+        }
+    }
+}
+
+fn invalid_registration(message: &str) -> AuthenticationError {
+    AuthenticationError::InvalidInput(message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+    use serde_json::Value;
+
+    /// Ceremonies captured from a software authenticator against `rp_id` "localhost".
+    struct Fixture {
+        user_id: Uuid,
+        registration_challenge: String,
+        registration: RegistrationCredential,
+        authentication_challenge: String,
+        authentication: AuthenticationCredential,
+    }
+
+    fn fixture(json: &str) -> Fixture {
+        let value: Value = serde_json::from_str(json).unwrap();
+        Fixture {
+            user_id: value["user_id"].as_str().unwrap().parse().unwrap(),
+            registration_challenge: value["registration"]["challenge"].as_str().unwrap().to_string(),
+            registration: serde_json::from_value(value["registration"]["credential"].clone()).unwrap(),
+            authentication_challenge: value["authentication"]["challenge"].as_str().unwrap().to_string(),
+            authentication: serde_json::from_value(value["authentication"]["credential"].clone()).unwrap(),
+        }
+    }
+
+    fn es256() -> Fixture {
+        fixture(include_str!("../../tests/fixtures/webauthn/es256.json"))
+    }
+
+    fn eddsa() -> Fixture {
+        fixture(include_str!("../../tests/fixtures/webauthn/eddsa.json"))
+    }
+
+    fn test_service() -> Passkeys {
+        let config = Arc::new(test_config());
+        let pool = PgPool::connect_lazy(&config.database.url).unwrap();
+        Passkeys::new(pool, config)
+    }
+
+    fn stored(fixture: &Fixture, registration: VerifiedRegistration) -> Passkey {
+        Passkey {
+            id: Uuid::new_v4(),
+            user_id: fixture.user_id,
+            credential_id: registration.credential_id,
+            public_key: registration.public_key,
+            sign_count: i64::from(registration.sign_count),
+            name: None,
+            created_at: Utc::now(),
+            last_used_at: None,
+        }
+    }
+
+    fn register(service: &Passkeys, fixture: &Fixture) -> Passkey {
+        let registration = service
+            .verify_registration(&fixture.registration, &fixture.registration_challenge)
+            .unwrap();
+        stored(fixture, registration)
+    }
+
+    #[tokio::test]
+    async fn test_registration_and_assertion_round_trip() {
+        let service = test_service();
+        for fixture in [es256(), eddsa()] {
+            let passkey = register(&service, &fixture);
+            assert_eq!(passkey.credential_id, fixture.registration.raw_id);
+
+            let assertion = service
+                .verify_assertion(&fixture.authentication, &fixture.authentication_challenge, &passkey)
+                .unwrap();
+            assert!(assertion.user_verified);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_registration_rejects_other_challenge() {
+        let service = test_service();
+        let fixture = es256();
+        assert!(matches!(
+            service.verify_registration(&fixture.registration, &fixture.authentication_challenge),
+            Err(AuthenticationError::InvalidInput(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_registration_rejects_other_origin() {
+        let mut config = test_config();
+        config.webauthn.origin = "https://evil.example".to_string();
+        let pool = PgPool::connect_lazy(&config.database.url).unwrap();
+        let service = Passkeys::new(pool, Arc::new(config));
+
+        let fixture = es256();
+        assert!(
+            service
+                .verify_registration(&fixture.registration, &fixture.registration_challenge)
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_registration_rejects_other_rp_id() {
+        let mut config = test_config();
+        config.webauthn.rp_id = "example.com".to_string();
+        let pool = PgPool::connect_lazy(&config.database.url).unwrap();
+        let service = Passkeys::new(pool, Arc::new(config));
+
+        let fixture = eddsa();
+        assert!(
+            service
+                .verify_registration(&fixture.registration, &fixture.registration_challenge)
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_assertion_rejects_tampered_signature() {
+        let service = test_service();
+        let mut fixture = es256();
+        let passkey = register(&service, &fixture);
+
+        let mut signature = URL_SAFE_NO_PAD.decode(&fixture.authentication.response.signature).unwrap();
+        let last = signature.len() - 1;
+        signature[last] ^= 0x01;
+        fixture.authentication.response.signature = URL_SAFE_NO_PAD.encode(signature);
+
+        assert!(matches!(
+            service.verify_assertion(&fixture.authentication, &fixture.authentication_challenge, &passkey),
+            Err(AuthenticationError::InvalidCredentials)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_assertion_rejects_key_of_another_credential() {
+        let service = test_service();
+        let fixture = es256();
+        let other = eddsa();
+        let mut passkey = register(&service, &fixture);
+        passkey.public_key = register(&service, &other).public_key;
+
+        assert!(
+            service
+                .verify_assertion(&fixture.authentication, &fixture.authentication_challenge, &passkey)
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_assertion_rejects_sign_count_that_did_not_increase() {
+        let service = test_service();
+        let fixture = es256();
+        let mut passkey = register(&service, &fixture);
+        // The recorded assertion carries counter 2.
+        passkey.sign_count = 2;
+
+        assert!(
+            service
+                .verify_assertion(&fixture.authentication, &fixture.authentication_challenge, &passkey)
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_assertion_rejects_mismatched_user_handle() {
+        let service = test_service();
+        let fixture = eddsa();
+        let mut passkey = register(&service, &fixture);
+        passkey.user_id = Uuid::new_v4();
+
+        assert!(
+            service
+                .verify_assertion(&fixture.authentication, &fixture.authentication_challenge, &passkey)
+                .is_err()
+        );
+    }
+
+    #[sqlx::test]
+    async fn test_authentication_rejects_passkey_of_another_tenant(pool: PgPool) {
+        let service = Passkeys::new(pool.clone(), Arc::new(test_config()));
+        let fixture = es256();
+        let passkey = register(&service, &fixture);
+        sqlx::query("INSERT INTO users (id, tenant_id, username, email, password_hash) VALUES ($1, 'acme', 'bob', 'bob@example.com', '')")
+            .bind(fixture.user_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO passkey_credentials (id, user_id, credential_id, public_key, sign_count) VALUES ($1, $2, $3, $4, $5)")
+            .bind(passkey.id)
+            .bind(passkey.user_id)
+            .bind(&passkey.credential_id)
+            .bind(&passkey.public_key)
+            .bind(passkey.sign_count)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let issue_challenge = || {
+            sqlx::query(
+                "INSERT INTO webauthn_challenges (id, ceremony, challenge_hash, expires_at) VALUES ($1, $2, $3, NOW() + INTERVAL '5 minutes')",
+            )
+            .bind(Uuid::new_v4())
+            .bind(CEREMONY_AUTHENTICATION)
+            .bind(hash_token(&fixture.authentication_challenge))
+            .execute(&pool)
+        };
+
+        issue_challenge().await.unwrap();
+        assert!(matches!(
+            service.finish_authentication("default", &fixture.authentication).await,
+            Err(AuthenticationError::InvalidCredentials)
+        ));
+
+        issue_challenge().await.unwrap();
+        let (user_id, _) = service.finish_authentication("acme", &fixture.authentication).await.unwrap();
+        assert_eq!(user_id, fixture.user_id);
+    }
+}
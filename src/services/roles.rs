@@ -0,0 +1,238 @@
+use crate::error::role::RoleError;
+use crate::models::request::CreateRole;
+use crate::models::role::{Permission, Role};
+use sqlx::{Error, PgPool};
+use tracing::{error, info};
+use uuid::Uuid;
+
+pub struct Roles {
+    pool: PgPool,
+}
+
+impl Roles {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn list_roles(&self) -> Result<Vec<Role>, RoleError> {
+        match sqlx::query_as::<_, Role>(
+            r#"
+            SELECT r.id, r.name, r.description, r.created_at,
+                COALESCE(array_agg(p.name ORDER BY p.name) FILTER (WHERE p.name IS NOT NULL), '{}') AS permissions
+            FROM roles r
+            LEFT JOIN role_permissions rp ON rp.role_id = r.id
+            LEFT JOIN permissions p ON p.id = rp.permission_id
+            GROUP BY r.id
+            ORDER BY r.name
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        {
+            Ok(roles) => Ok(roles),
+            Err(e) => {
+                error!("Failed to fetch roles: {}", e);
+                Err(RoleError::InternalServerError)
+            }
+        }
+    }
+
+    pub async fn list_permissions(&self) -> Result<Vec<Permission>, RoleError> {
+        match sqlx::query_as::<_, Permission>("SELECT name, description FROM permissions ORDER BY name")
+            .fetch_all(&self.pool)
+            .await
+        {
+            Ok(permissions) => Ok(permissions),
+            Err(e) => {
+                error!("Failed to fetch permissions: {}", e);
+                Err(RoleError::InternalServerError)
+            }
+        }
+    }
+
+    pub async fn create_role(&self, request: &CreateRole) -> Result<Role, RoleError> {
+        let permissions = sqlx::query_as::<_, (Uuid, String)>("SELECT id, name FROM permissions WHERE name = ANY($1)")
+            .bind(&request.permissions)
+            .fetch_all(&self.pool)
+            .await;
+        let permissions = match permissions {
+            Ok(permissions) => permissions,
+            Err(e) => {
+                error!("Failed to fetch permissions: {}", e);
+                return Err(RoleError::InternalServerError);
+            }
+        };
+        if let Some(unknown) = request
+            .permissions
+            .iter()
+            .find(|name| !permissions.iter().any(|(_, known)| known == *name))
+        {
+            return Err(RoleError::UnknownPermission(unknown.clone()));
+        }
+
+        let role_id = Uuid::new_v4();
+        let result: Result<Role, Error> = async {
+            let mut tx = self.pool.begin().await?;
+            let role = sqlx::query_as::<_, Role>(
+                r#"
+                INSERT INTO roles (id, name, description)
+                VALUES ($1, $2, $3)
+                RETURNING id, name, description, created_at, '{}'::TEXT[] AS permissions
+                "#,
+            )
+            .bind(role_id)
+            .bind(&request.name)
+            .bind(&request.description)
+            .fetch_one(&mut *tx)
+            .await?;
+            for (permission_id, _) in &permissions {
+                sqlx::query("INSERT INTO role_permissions (role_id, permission_id) VALUES ($1, $2)")
+                    .bind(role_id)
+                    .bind(permission_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            tx.commit().await?;
+            Ok(role)
+        }
+        .await;
+
+        match result {
+            Ok(mut role) => {
+                info!("Role {} created", role.name);
+                role.permissions = permissions.into_iter().map(|(_, name)| name).collect();
+                role.permissions.sort();
+                Ok(role)
+            }
+            Err(Error::Database(db_err)) if db_err.constraint() == Some("roles_name_key") => {
+                Err(RoleError::RoleAlreadyExists)
+            }
+            Err(e) => {
+                error!("Failed to create role {}: {}", request.name, e);
+                Err(RoleError::InternalServerError)
+            }
+        }
+    }
+
+    /// Roles of a user of `tenant_id`.
+    pub async fn user_roles(&self, tenant_id: &str, user_id: &Uuid) -> Result<Vec<Role>, RoleError> {
+        self.require_tenant_user(tenant_id, user_id).await?;
+
+        match sqlx::query_as::<_, Role>(
+            r#"
+            SELECT r.id, r.name, r.description, r.created_at,
+                COALESCE(array_agg(p.name ORDER BY p.name) FILTER (WHERE p.name IS NOT NULL), '{}') AS permissions
+            FROM user_roles ur
+            JOIN roles r ON r.id = ur.role_id
+            LEFT JOIN role_permissions rp ON rp.role_id = r.id
+            LEFT JOIN permissions p ON p.id = rp.permission_id
+            WHERE ur.user_id = $1
+            GROUP BY r.id
+            ORDER BY r.name
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await
+        {
+            Ok(roles) => Ok(roles),
+            Err(e) => {
+                error!("Failed to fetch roles of user {}: {}", user_id, e);
+                Err(RoleError::InternalServerError)
+            }
+        }
+    }
+
+    /// Grants a role to a user of `tenant_id`; assigning a role the user
+    /// already has is not an error.
+    pub async fn assign_role(&self, tenant_id: &str, user_id: &Uuid, role_name: &str) -> Result<(), RoleError> {
+        self.require_tenant_user(tenant_id, user_id).await?;
+
+        match sqlx::query(
+            r#"
+            INSERT INTO user_roles (user_id, role_id)
+            SELECT u.id, r.id FROM users u, roles r
+            WHERE u.id = $1 AND u.tenant_id = $3 AND r.name = $2
+            ON CONFLICT (user_id, role_id) DO NOTHING
+            "#,
+        )
+        .bind(user_id)
+        .bind(role_name)
+        .bind(tenant_id)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(_) => {}
+            Err(Error::Database(db_err)) if db_err.constraint() == Some("user_roles_user_id_fkey") => {
+                return Err(RoleError::UserNotFound);
+            }
+            Err(e) => {
+                error!("Failed to assign role {} to user {}: {}", role_name, user_id, e);
+                return Err(RoleError::InternalServerError);
+            }
+        }
+
+        // Nothing is inserted for an unknown role, nor for one already assigned.
+        match sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM roles WHERE name = $1)")
+            .bind(role_name)
+            .fetch_one(&self.pool)
+            .await
+        {
+            Ok(true) => {
+                info!("Role {} assigned to user {}", role_name, user_id);
+                Ok(())
+            }
+            Ok(false) => Err(RoleError::RoleNotFound),
+            Err(e) => {
+                error!("Failed to check role {}: {}", role_name, e);
+                Err(RoleError::InternalServerError)
+            }
+        }
+    }
+
+    pub async fn remove_role(&self, tenant_id: &str, user_id: &Uuid, role_name: &str) -> Result<(), RoleError> {
+        self.require_tenant_user(tenant_id, user_id).await?;
+
+        match sqlx::query(
+            r#"
+            DELETE FROM user_roles ur
+            USING roles r, users u
+            WHERE ur.role_id = r.id AND ur.user_id = u.id
+                AND u.id = $1 AND u.tenant_id = $3 AND r.name = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(role_name)
+        .bind(tenant_id)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(res) if res.rows_affected() == 0 => Err(RoleError::RoleNotAssigned),
+            Ok(_) => {
+                info!("Role {} removed from user {}", role_name, user_id);
+                Ok(())
+            }
+            Err(e) => {
+                error!("Failed to remove role {} from user {}: {}", role_name, user_id, e);
+                Err(RoleError::InternalServerError)
+            }
+        }
+    }
+
+    /// Roles are shared by all tenants, the users they are granted to are not.
+    async fn require_tenant_user(&self, tenant_id: &str, user_id: &Uuid) -> Result<(), RoleError> {
+        match sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM users WHERE id = $1 AND tenant_id = $2)")
+            .bind(user_id)
+            .bind(tenant_id)
+            .fetch_one(&self.pool)
+            .await
+        {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(RoleError::UserNotFound),
+            Err(e) => {
+                error!("Failed to fetch user {}: {}", user_id, e);
+                Err(RoleError::InternalServerError)
+            }
+        }
+    }
+}
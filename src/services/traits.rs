@@ -1,11 +1,15 @@
+use crate::error::authentication::AuthenticationError;
 use crate::error::email::EmailError;
 
+use chrono::{DateTime, Utc};
 use std::future::Future;
 use std::pin::Pin;
 
 pub trait EmailServiceBase: Send + Sync {
+    /// Sends an email to a user of `tenant_id`, from the tenant's sender.
     fn send_email(
         &self,
+        tenant_id: &str,
         to: String,
         cc: Vec<String>,
         bcc: Vec<String>,
@@ -13,3 +17,17 @@ pub trait EmailServiceBase: Send + Sync {
         content: String,
     ) -> Pin<Box<dyn Future<Output = Result<(), EmailError>> + Send>>;
 }
+
+pub type DenylistFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, AuthenticationError>> + Send + 'a>>;
+
+/// Store of access tokens revoked before their expiry, keyed by `jti`.
+pub trait TokenDenylist: Send + Sync {
+    /// Denies `jti` until `expires_at`, extending an existing entry if later.
+    fn deny(&self, jti: String, expires_at: DateTime<Utc>) -> DenylistFuture<'_, ()>;
+
+    /// When `jti` stops being denied, or `None` if it never was.
+    fn denied_until(&self, jti: String) -> DenylistFuture<'_, Option<DateTime<Utc>>>;
+
+    /// Removes entries that have expired, returning how many.
+    fn purge_expired(&self) -> DenylistFuture<'_, u64>;
+}
@@ -1,11 +1,8 @@
 use crate::config::Config;
-use crate::error::authentication::AuthenticationError;
 use crate::error::user::UserError;
 use crate::models::request::RegisterUser;
-use crate::models::user::User;
-use crate::services::traits::EmailServiceBase;
+use crate::models::user::{User, UserPage};
 use crate::utils::security::hash_password;
-use sqlx::postgres::PgPoolOptions;
 use sqlx::{Error, PgPool};
 use std::sync::Arc;
 use tracing::log::error;
@@ -13,6 +10,7 @@ use uuid::Uuid;
 
 pub struct Users {
     pool: PgPool,
+    #[allow(dead_code)]
     config: Arc<Config>,
 }
 
@@ -21,7 +19,7 @@ impl Users {
         Self { pool, config }
     }
 
-    pub async fn create_user(&self, user_payload: RegisterUser) -> Result<User, UserError> {
+    pub async fn create_user(&self, tenant_id: &str, user_payload: RegisterUser) -> Result<User, UserError> {
         let user_id = Uuid::new_v4();
         let password_hash = match hash_password(&user_payload.password) {
             Ok(hash) => hash,
@@ -30,12 +28,13 @@ impl Users {
         let is_active = false;
         match sqlx::query(
             r#"
-            INSERT INTO users (id, username, email, password_hash, is_active)
-            VALUES ($1, $2, $3, $4, $5)
+            INSERT INTO users (id, tenant_id, username, email, password_hash, is_active)
+            VALUES ($1, $2, $3, $4, $5, $6)
             "#,
         )
         .bind(user_id)
-        .bind(user_payload.username)
+        .bind(tenant_id)
+        .bind(&user_payload.username)
         .bind(user_payload.email.clone())
         .bind(password_hash.clone())
         .bind(is_active)
@@ -44,33 +43,38 @@ impl Users {
         {
             Ok(_) => Ok(()),
             Err(e) => {
-                if let Error::Database(db_err) = &e {
-                    if db_err.constraint() == Some("users_username_key")
-                        || db_err.constraint() == Some("users_email_key")
-                    {
-                        return Err(UserError::AccountAlreadyExists);
-                    }
+                if let Error::Database(db_err) = &e
+                    && (db_err.constraint() == Some("users_tenant_id_username_key")
+                        || db_err.constraint() == Some("users_tenant_id_email_key"))
+                {
+                    return Err(UserError::AccountAlreadyExists);
                 }
-                error!("Failed to save users: {}", e.to_string());
+                error!("Failed to save users: {}", e);
                 return Err(UserError::InternalServerError);
             }
         }?;
         Ok(User {
             id: user_id,
+            tenant_id: tenant_id.to_string(),
             email: user_payload.email,
             password_hash,
-            username: "".to_string(),
+            username: user_payload.username,
+            pending_email: None,
+            totp_secret: None,
+            totp_enabled_at: None,
             is_active,
+            is_verified: false,
             created_at: Default::default(),
             updated_at: Default::default(),
         })
     }
 
-    pub async fn get_user_by_email_or_username(&self, identity: &str) -> Result<User, UserError> {
+    pub async fn get_user_by_email_or_username(&self, tenant_id: &str, identity: &str) -> Result<User, UserError> {
 
         let user_exists = sqlx::query_as::<_, User>(
-            "SELECT * FROM users WHERE username = $1 OR email = $1"
+            "SELECT * FROM users WHERE tenant_id = $1 AND (username = $2 OR email = $2)"
         )
+            .bind(tenant_id)
             .bind(identity)
             .fetch_one(&self.pool)
             .await;
@@ -84,4 +88,295 @@ impl Users {
         }
 
     }
+
+    pub async fn get_user_by_email(&self, tenant_id: &str, email: &str) -> Result<User, UserError> {
+        match sqlx::query_as::<_, User>("SELECT * FROM users WHERE tenant_id = $1 AND email = $2")
+            .bind(tenant_id)
+            .bind(email)
+            .fetch_optional(&self.pool)
+            .await
+        {
+            Ok(Some(user)) => Ok(user),
+            Ok(None) => Err(UserError::UserNotFound("User not found".to_string())),
+            Err(e) => {
+                error!("Failed to fetch user by email: {}", e);
+                Err(UserError::InternalServerError)
+            }
+        }
+    }
+
+    pub async fn update_password(&self, user_id: &Uuid, password: &str) -> Result<(), UserError> {
+        let password_hash = match hash_password(password) {
+            Ok(hash) => hash,
+            Err(_) => return Err(UserError::InternalServerError),
+        };
+
+        match sqlx::query(
+            r#"
+            UPDATE users
+            SET password_hash = $1, updated_at = NOW()
+            WHERE id = $2
+            "#,
+        )
+        .bind(password_hash)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(res) if res.rows_affected() == 0 => {
+                Err(UserError::UserNotFound("User not found".to_string()))
+            }
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("Failed to update password for user {}: {}", user_id, e);
+                Err(UserError::InternalServerError)
+            }
+        }
+    }
+
+    /// Records `email` as awaiting confirmation. Whether the address is taken
+    /// is only checked on confirmation, so the request does not reveal which
+    /// addresses have an account.
+    pub async fn set_pending_email(&self, user_id: &Uuid, email: &str) -> Result<(), UserError> {
+        match sqlx::query("UPDATE users SET pending_email = $1, updated_at = NOW() WHERE id = $2")
+            .bind(email)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("Failed to set pending email for user {}: {}", user_id, e);
+                Err(UserError::InternalServerError)
+            }
+        }
+    }
+
+    /// Swaps the confirmed pending email in as the account email.
+    pub async fn confirm_pending_email(&self, user_id: &Uuid) -> Result<(), UserError> {
+        match sqlx::query(
+            r#"
+            UPDATE users
+            SET email = pending_email, pending_email = NULL, updated_at = NOW()
+            WHERE id = $1 AND pending_email IS NOT NULL
+            "#,
+        )
+        .bind(user_id)
+        .execute(&self.pool)
+        .await
+        {
+            Ok(res) if res.rows_affected() == 0 => {
+                Err(UserError::UserNotFound("No pending email change".to_string()))
+            }
+            Ok(_) => Ok(()),
+            Err(e) => {
+                // Someone may have registered the address since it was requested.
+                if let Error::Database(db_err) = &e
+                    && db_err.constraint() == Some("users_tenant_id_email_key")
+                {
+                    return Err(UserError::AccountAlreadyExists);
+                }
+                error!("Failed to confirm email change for user {}: {}", user_id, e);
+                Err(UserError::InternalServerError)
+            }
+        }
+    }
+
+    pub async fn get_user_by_id(&self, user_id: &Uuid) -> Result<User, UserError> {
+        match sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await
+        {
+            Ok(Some(user)) => Ok(user),
+            Ok(None) => Err(UserError::UserNotFound("User not found".to_string())),
+            Err(e) => {
+                error!("Failed to fetch user {}: {}", user_id, e);
+                Err(UserError::InternalServerError)
+            }
+        }
+    }
+
+    /// A user of `tenant_id`, for administration; users of other tenants are not found.
+    pub async fn get_tenant_user(&self, tenant_id: &str, user_id: &Uuid) -> Result<User, UserError> {
+        match sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1 AND tenant_id = $2")
+            .bind(user_id)
+            .bind(tenant_id)
+            .fetch_optional(&self.pool)
+            .await
+        {
+            Ok(Some(user)) => Ok(user),
+            Ok(None) => Err(UserError::NotInTenant),
+            Err(e) => {
+                error!("Failed to fetch user {}: {}", user_id, e);
+                Err(UserError::InternalServerError)
+            }
+        }
+    }
+
+    /// A page of the users of `tenant_id`, newest first, optionally only those
+    /// whose email or username contains `search`.
+    pub async fn list_users(
+        &self,
+        tenant_id: &str,
+        search: Option<&str>,
+        page: i64,
+        per_page: i64,
+    ) -> Result<UserPage, UserError> {
+        let pattern = search.map(|search| {
+            let escaped = search.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+            format!("%{}%", escaped)
+        });
+
+        let total = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM users WHERE tenant_id = $1 AND ($2::TEXT IS NULL OR email ILIKE $2 OR username ILIKE $2)",
+        )
+        .bind(tenant_id)
+        .bind(&pattern)
+        .fetch_one(&self.pool)
+        .await;
+        let users = sqlx::query_as::<_, User>(
+            r#"
+            SELECT * FROM users
+            WHERE tenant_id = $1 AND ($2::TEXT IS NULL OR email ILIKE $2 OR username ILIKE $2)
+            ORDER BY created_at DESC, id
+            LIMIT $3 OFFSET $4
+            "#,
+        )
+        .bind(tenant_id)
+        .bind(&pattern)
+        .bind(per_page)
+        .bind((page - 1) * per_page)
+        .fetch_all(&self.pool)
+        .await;
+
+        match (users, total) {
+            (Ok(users), Ok(total)) => Ok(UserPage { users, page, per_page, total }),
+            (Err(e), _) | (_, Err(e)) => {
+                error!("Failed to list users: {}", e);
+                Err(UserError::InternalServerError)
+            }
+        }
+    }
+
+    pub async fn set_active(&self, tenant_id: &str, user_id: &Uuid, is_active: bool) -> Result<User, UserError> {
+        match sqlx::query_as::<_, User>(
+            "UPDATE users SET is_active = $1, updated_at = NOW() WHERE id = $2 AND tenant_id = $3 RETURNING *",
+        )
+        .bind(is_active)
+        .bind(user_id)
+        .bind(tenant_id)
+        .fetch_optional(&self.pool)
+        .await
+        {
+            Ok(Some(user)) => Ok(user),
+            Ok(None) => Err(UserError::NotInTenant),
+            Err(e) => {
+                error!("Failed to update user {}: {}", user_id, e);
+                Err(UserError::InternalServerError)
+            }
+        }
+    }
+
+    /// Marks the email address as verified without a token, which also activates the account.
+    pub async fn mark_verified(&self, tenant_id: &str, user_id: &Uuid) -> Result<User, UserError> {
+        match sqlx::query_as::<_, User>(
+            r#"
+            UPDATE users SET is_verified = true, is_active = true, updated_at = NOW()
+            WHERE id = $1 AND tenant_id = $2
+            RETURNING *
+            "#,
+        )
+        .bind(user_id)
+        .bind(tenant_id)
+        .fetch_optional(&self.pool)
+        .await
+        {
+            Ok(Some(user)) => Ok(user),
+            Ok(None) => Err(UserError::NotInTenant),
+            Err(e) => {
+                error!("Failed to verify user {}: {}", user_id, e);
+                Err(UserError::InternalServerError)
+            }
+        }
+    }
+
+    /// Deletes a user with everything attached to the account.
+    pub async fn delete_user(&self, tenant_id: &str, user_id: &Uuid) -> Result<(), UserError> {
+        let result: Result<u64, Error> = async {
+            let mut tx = self.pool.begin().await?;
+            let found = sqlx::query_scalar::<_, bool>(
+                "SELECT EXISTS(SELECT 1 FROM users WHERE id = $1 AND tenant_id = $2)",
+            )
+            .bind(user_id)
+            .bind(tenant_id)
+            .fetch_one(&mut *tx)
+            .await?;
+            if !found {
+                return Ok(0);
+            }
+            sqlx::query("DELETE FROM verification_tokens WHERE user_id = $1")
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query("DELETE FROM login_failures WHERE scope = 'account' AND subject = $1")
+                .bind(user_id.to_string())
+                .execute(&mut *tx)
+                .await?;
+            let deleted = sqlx::query("DELETE FROM users WHERE id = $1 AND tenant_id = $2")
+                .bind(user_id)
+                .bind(tenant_id)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+            tx.commit().await?;
+            Ok(deleted)
+        }
+        .await;
+
+        match result {
+            Ok(0) => Err(UserError::NotInTenant),
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("Failed to delete user {}: {}", user_id, e);
+                Err(UserError::InternalServerError)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::test_config;
+
+    async fn insert_user(pool: &PgPool, email: &str) -> Uuid {
+        let user_id = Uuid::new_v4();
+        sqlx::query("INSERT INTO users (id, username, email, password_hash) VALUES ($1, $2, $3, '')")
+            .bind(user_id)
+            .bind(user_id.simple().to_string())
+            .bind(email)
+            .execute(pool)
+            .await
+            .unwrap();
+        user_id
+    }
+
+    #[sqlx::test]
+    async fn test_taken_email_is_only_refused_on_confirmation(pool: PgPool) {
+        let service = Users::new(pool.clone(), Arc::new(test_config()));
+        let user_id = insert_user(&pool, "bob@example.com").await;
+        insert_user(&pool, "alice@example.com").await;
+
+        service.set_pending_email(&user_id, "alice@example.com").await.unwrap();
+        assert!(matches!(
+            service.confirm_pending_email(&user_id).await,
+            Err(UserError::AccountAlreadyExists)
+        ));
+        assert_eq!(service.get_user_by_id(&user_id).await.unwrap().email, "bob@example.com");
+
+        service.set_pending_email(&user_id, "carol@example.com").await.unwrap();
+        service.confirm_pending_email(&user_id).await.unwrap();
+        assert_eq!(service.get_user_by_id(&user_id).await.unwrap().email, "carol@example.com");
+    }
 }
@@ -1 +1,5 @@
+pub mod clock;
 pub mod security;
+pub mod totp;
+pub mod user_agent;
+pub mod webauthn;
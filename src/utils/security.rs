@@ -1,6 +1,11 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
 use argon2::password_hash::SaltString;
-use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
 use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use sha2::{Digest, Sha256};
 
 pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
     let salt = SaltString::generate(&mut OsRng);
@@ -18,3 +23,103 @@ pub fn verify_password(password: &str, hashed_password: &str) -> bool {
     };
     Argon2::default().verify_password(password.as_bytes(), &parsed_hash).is_ok()
 }
+
+/// Generates an opaque, URL-safe token from 32 random bytes.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Generates a uniformly random numeric code of `digits` length, e.g. `"042917"`.
+pub fn generate_numeric_code(digits: u32) -> String {
+    let modulus = 10u32.pow(digits);
+    // Reject the top of the range so every code is equally likely.
+    let limit = u32::MAX - u32::MAX % modulus;
+    loop {
+        let value = OsRng.next_u32();
+        if value < limit {
+            return format!("{:0width$}", value % modulus, width = digits as usize);
+        }
+    }
+}
+
+/// Hashes a high-entropy token for storage and lookup.
+///
+/// Unlike passwords these tokens are random, so a fast SHA-256 digest is enough
+/// and lets us query by the hash directly.
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Encrypts a secret at rest with AES-256-GCM, returning base64 of `nonce || ciphertext`.
+pub fn encrypt_secret(key: &[u8; 32], plaintext: &[u8]) -> String {
+    let cipher = Aes256Gcm::new(key.into());
+    let mut nonce = [0u8; 12];
+    OsRng.fill_bytes(&mut nonce);
+
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .expect("AES-GCM encryption cannot fail for in-memory buffers");
+    STANDARD.encode([nonce.as_slice(), &ciphertext].concat())
+}
+
+/// Reverses [`encrypt_secret`], returning `None` if the value was tampered with or the key is wrong.
+pub fn decrypt_secret(key: &[u8; 32], encoded: &str) -> Option<Vec<u8>> {
+    let data = STANDARD.decode(encoded).ok()?;
+    if data.len() < 12 {
+        return None;
+    }
+    let (nonce, ciphertext) = data.split_at(12);
+    Aes256Gcm::new(key.into())
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_and_verify_password() {
+        let hash = hash_password("Valid1@pass").unwrap();
+        assert!(verify_password("Valid1@pass", &hash));
+        assert!(!verify_password("Wrong1@pass", &hash));
+    }
+
+    #[test]
+    fn test_generate_token_is_unique() {
+        let first = generate_token();
+        let second = generate_token();
+        assert_eq!(first.len(), 43);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_generate_numeric_code() {
+        let code = generate_numeric_code(6);
+        assert_eq!(code.len(), 6);
+        assert!(code.chars().all(|c| c.is_ascii_digit()));
+    }
+
+    #[test]
+    fn test_encrypt_secret_round_trip() {
+        let key = [7u8; 32];
+        let encrypted = encrypt_secret(&key, b"totp secret");
+
+        assert_eq!(decrypt_secret(&key, &encrypted).unwrap(), b"totp secret");
+        assert!(decrypt_secret(&[8u8; 32], &encrypted).is_none());
+        assert_ne!(encrypted, encrypt_secret(&key, b"totp secret"));
+    }
+
+    #[test]
+    fn test_hash_token_is_deterministic() {
+        let token = generate_token();
+        assert_eq!(hash_token(&token), hash_token(&token));
+        assert_ne!(hash_token(&token), hash_token(&generate_token()));
+        assert_eq!(hash_token(&token).len(), 64);
+    }
+}
@@ -0,0 +1,129 @@
+//! Time-based one-time passwords (RFC 6238) using HMAC-SHA1, 6 digits and 30 second steps,
+//! the parameters every authenticator app supports.
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+use subtle::ConstantTimeEq;
+use url::form_urlencoded;
+
+const PERIOD: i64 = 30;
+const DIGITS: u32 = 6;
+/// Steps accepted on either side of the current one to absorb clock drift.
+const SKEW: i64 = 1;
+
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; 20];
+    OsRng.fill_bytes(&mut secret);
+    secret
+}
+
+pub fn time_step(now: DateTime<Utc>) -> i64 {
+    now.timestamp().div_euclid(PERIOD)
+}
+
+pub fn code_at(secret: &[u8], step: i64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(&step.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    // Dynamic truncation, RFC 4226 section 5.3.
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    format!("{:0width$}", binary % 10u32.pow(DIGITS), width = DIGITS as usize)
+}
+
+/// Returns the time step `code` is valid for at `now`, if any. Codes are
+/// compared in constant time, so timing does not reveal matching digits.
+pub fn verify(secret: &[u8], code: &str, now: DateTime<Utc>) -> Option<i64> {
+    let current = time_step(now);
+    (current - SKEW..=current + SKEW)
+        .find(|step| bool::from(code_at(secret, *step).as_bytes().ct_eq(code.as_bytes())))
+}
+
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &[u8]) -> String {
+    // The label is a path segment, where spaces must be %20 rather than `+`.
+    let label = form_urlencoded::byte_serialize(format!("{}:{}", issuer, account).as_bytes())
+        .collect::<String>()
+        .replace('+', "%20");
+    let query = form_urlencoded::Serializer::new(String::new())
+        .append_pair("secret", &base32_encode(secret))
+        .append_pair("issuer", issuer)
+        .append_pair("algorithm", "SHA1")
+        .append_pair("digits", &DIGITS.to_string())
+        .append_pair("period", &PERIOD.to_string())
+        .finish();
+    format!("otpauth://totp/{}?{}", label, query)
+}
+
+/// Unpadded RFC 4648 base32, the secret encoding authenticator apps expect.
+pub fn base32_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut output = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            output.push(ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        output.push(ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    // RFC 6238 appendix B, SHA1 secret, truncated to 6 digits.
+    const SECRET: &[u8] = b"12345678901234567890";
+
+    fn at(timestamp: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(timestamp, 0).unwrap()
+    }
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        assert_eq!(code_at(SECRET, time_step(at(59))), "287082");
+        assert_eq!(code_at(SECRET, time_step(at(1111111109))), "081804");
+        assert_eq!(code_at(SECRET, time_step(at(1234567890))), "005924");
+        assert_eq!(code_at(SECRET, time_step(at(2000000000))), "279037");
+    }
+
+    #[test]
+    fn test_verify_allows_one_step_of_drift() {
+        let now = at(1111111109);
+        let previous = code_at(SECRET, time_step(now) - 1);
+        let too_old = code_at(SECRET, time_step(now) - 2);
+
+        assert_eq!(verify(SECRET, &previous, now), Some(time_step(now) - 1));
+        assert_eq!(verify(SECRET, &too_old, now), None);
+    }
+
+    #[test]
+    fn test_base32_encode() {
+        assert_eq!(base32_encode(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(base32_encode(SECRET), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+    }
+
+    #[test]
+    fn test_otpauth_uri() {
+        let uri = otpauth_uri("Auth Service", "alice@example.com", b"foobar");
+        assert_eq!(
+            uri,
+            "otpauth://totp/Auth%20Service%3Aalice%40example.com?secret=MZXW6YTBOI&issuer=Auth+Service&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+}
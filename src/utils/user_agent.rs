@@ -0,0 +1,67 @@
+/// A short label like "Firefox on Linux" for a `User-Agent`, shown in session
+/// lists. Only common browsers and platforms are recognized.
+pub fn describe_device(user_agent: &str) -> Option<String> {
+    // Order matters: Edge and Opera also claim Chrome, Chrome also claims Safari.
+    const BROWSERS: [(&str, &str); 6] = [
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Firefox/", "Firefox"),
+        ("Chrome/", "Chrome"),
+        ("CriOS/", "Chrome"),
+        ("Safari/", "Safari"),
+    ];
+    // Android and ChromeOS also claim Linux, iOS also claims Mac OS X.
+    const PLATFORMS: [(&str, &str); 7] = [
+        ("Android", "Android"),
+        ("CrOS", "ChromeOS"),
+        ("iPhone", "iOS"),
+        ("iPad", "iPadOS"),
+        ("Windows", "Windows"),
+        ("Mac OS X", "macOS"),
+        ("Linux", "Linux"),
+    ];
+
+    let find = |table: &[(&str, &'static str)]| {
+        table
+            .iter()
+            .find(|(marker, _)| user_agent.contains(marker))
+            .map(|(_, name)| *name)
+    };
+    match (find(&BROWSERS), find(&PLATFORMS)) {
+        (Some(browser), Some(platform)) => Some(format!("{} on {}", browser, platform)),
+        (Some(name), None) | (None, Some(name)) => Some(name.to_string()),
+        (None, None) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_device() {
+        let cases = [
+            (
+                "Mozilla/5.0 (X11; Linux x86_64; rv:138.0) Gecko/20100101 Firefox/138.0",
+                Some("Firefox on Linux"),
+            ),
+            (
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/136.0.0.0 Safari/537.36 Edg/136.0.0.0",
+                Some("Edge on Windows"),
+            ),
+            (
+                "Mozilla/5.0 (iPhone; CPU iPhone OS 18_4 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/18.4 Mobile/15E148 Safari/604.1",
+                Some("Safari on iOS"),
+            ),
+            (
+                "Mozilla/5.0 (Linux; Android 15; Pixel 9) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/136.0.0.0 Mobile Safari/537.36",
+                Some("Chrome on Android"),
+            ),
+            ("curl/8.5.0", None),
+        ];
+
+        for (user_agent, expected) in cases {
+            assert_eq!(describe_device(user_agent).as_deref(), expected, "{}", user_agent);
+        }
+    }
+}
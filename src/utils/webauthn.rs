@@ -0,0 +1,231 @@
+//! The WebAuthn data formats passkey ceremonies exchange: client data JSON,
+//! authenticator data and COSE public keys (ES256, EdDSA and RS256).
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use ciborium::value::Value;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::io::Cursor;
+
+pub const FLAG_USER_PRESENT: u8 = 0x01;
+pub const FLAG_USER_VERIFIED: u8 = 0x04;
+pub const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+
+/// COSE algorithm identifiers offered to authenticators, in order of preference.
+pub const SUPPORTED_ALGORITHMS: [i64; 3] = [COSE_ES256, COSE_EDDSA, COSE_RS256];
+const COSE_ES256: i64 = -7;
+const COSE_EDDSA: i64 = -8;
+const COSE_RS256: i64 = -257;
+
+#[derive(Debug, Deserialize)]
+pub struct ClientData {
+    #[serde(rename = "type")]
+    pub ceremony: String,
+    pub challenge: String,
+    pub origin: String,
+}
+
+impl ClientData {
+    pub fn from_base64(encoded: &str) -> Option<(Vec<u8>, ClientData)> {
+        let raw = URL_SAFE_NO_PAD.decode(encoded).ok()?;
+        let client_data = serde_json::from_slice(&raw).ok()?;
+        Some((raw, client_data))
+    }
+}
+
+pub struct AttestedCredential {
+    pub credential_id: Vec<u8>,
+    /// The credential's COSE_Key, kept in its encoded form for storage.
+    pub public_key: Vec<u8>,
+}
+
+pub struct AuthenticatorData {
+    pub rp_id_hash: [u8; 32],
+    pub flags: u8,
+    pub sign_count: u32,
+    pub attested_credential: Option<AttestedCredential>,
+}
+
+impl AuthenticatorData {
+    /// Parses the binary layout from WebAuthn section 6.1; extensions are ignored.
+    pub fn parse(data: &[u8]) -> Option<AuthenticatorData> {
+        if data.len() < 37 {
+            return None;
+        }
+        let rp_id_hash = <[u8; 32]>::try_from(&data[..32]).ok()?;
+        let flags = data[32];
+        let sign_count = u32::from_be_bytes(<[u8; 4]>::try_from(&data[33..37]).ok()?);
+
+        let attested_credential = if flags & FLAG_ATTESTED_CREDENTIAL_DATA != 0 {
+            // 16 byte AAGUID, then a 2 byte length-prefixed credential id and the COSE key.
+            let rest = data.get(37 + 16..)?;
+            let id_len = u16::from_be_bytes(<[u8; 2]>::try_from(rest.get(..2)?).ok()?) as usize;
+            let credential_id = rest.get(2..2 + id_len)?.to_vec();
+            let key_bytes = rest.get(2 + id_len..)?;
+
+            let mut cursor = Cursor::new(key_bytes);
+            let _: Value = ciborium::de::from_reader(&mut cursor).ok()?;
+            let key_len = cursor.position() as usize;
+            Some(AttestedCredential {
+                credential_id,
+                public_key: key_bytes[..key_len].to_vec(),
+            })
+        } else {
+            None
+        };
+
+        Some(AuthenticatorData {
+            rp_id_hash,
+            flags,
+            sign_count,
+            attested_credential,
+        })
+    }
+
+    pub fn matches_rp_id(&self, rp_id: &str) -> bool {
+        self.rp_id_hash.as_slice() == Sha256::digest(rp_id.as_bytes()).as_slice()
+    }
+
+    pub fn user_present(&self) -> bool {
+        self.flags & FLAG_USER_PRESENT != 0
+    }
+
+    pub fn user_verified(&self) -> bool {
+        self.flags & FLAG_USER_VERIFIED != 0
+    }
+}
+
+/// Extracts `authData` from a CBOR attestation object.
+///
+/// Registration asks for `"none"` attestation, so the statement itself is not checked.
+pub fn attestation_auth_data(attestation_object: &[u8]) -> Option<Vec<u8>> {
+    let value: Value = ciborium::de::from_reader(attestation_object).ok()?;
+    value.as_map()?.iter().find_map(|(key, value)| match (key, value) {
+        (Value::Text(key), Value::Bytes(auth_data)) if key == "authData" => Some(auth_data.clone()),
+        _ => None,
+    })
+}
+
+pub enum CoseKey {
+    Es256(p256::ecdsa::VerifyingKey),
+    EdDsa(ed25519_dalek::VerifyingKey),
+    Rs256(rsa::RsaPublicKey),
+}
+
+impl CoseKey {
+    pub fn from_cbor(encoded: &[u8]) -> Option<CoseKey> {
+        let value: Value = ciborium::de::from_reader(encoded).ok()?;
+        let params = value.as_map()?;
+        let int_param = |label: i64| {
+            params.iter().find_map(|(key, value)| match (key.as_integer(), value.as_integer()) {
+                (Some(key), Some(value)) if i128::from(key) == i128::from(label) => {
+                    i64::try_from(value).ok()
+                }
+                _ => None,
+            })
+        };
+        let bytes_param = |label: i64| {
+            params.iter().find_map(|(key, value)| match (key.as_integer(), value.as_bytes()) {
+                (Some(key), Some(value)) if i128::from(key) == i128::from(label) => Some(value.as_slice()),
+                _ => None,
+            })
+        };
+
+        // Labels from RFC 9053: 1 = kty, 3 = alg, -1 = crv / n, -2 = x / e, -3 = y.
+        match (int_param(1)?, int_param(3)?) {
+            (2, COSE_ES256) if int_param(-1)? == 1 => {
+                let x = <[u8; 32]>::try_from(bytes_param(-2)?).ok()?;
+                let y = <[u8; 32]>::try_from(bytes_param(-3)?).ok()?;
+                let point = p256::EncodedPoint::from_affine_coordinates(&x.into(), &y.into(), false);
+                p256::ecdsa::VerifyingKey::from_encoded_point(&point).ok().map(CoseKey::Es256)
+            }
+            (1, COSE_EDDSA) if int_param(-1)? == 6 => {
+                let x = <[u8; 32]>::try_from(bytes_param(-2)?).ok()?;
+                ed25519_dalek::VerifyingKey::from_bytes(&x).ok().map(CoseKey::EdDsa)
+            }
+            (3, COSE_RS256) => {
+                let n = rsa::BigUint::from_bytes_be(bytes_param(-1)?);
+                let e = rsa::BigUint::from_bytes_be(bytes_param(-2)?);
+                rsa::RsaPublicKey::new(n, e).ok().map(CoseKey::Rs256)
+            }
+            _ => None,
+        }
+    }
+
+    /// Checks an assertion signature over `authenticatorData || SHA-256(clientDataJSON)`.
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        use rsa::signature::Verifier;
+
+        match self {
+            CoseKey::Es256(key) => p256::ecdsa::DerSignature::try_from(signature)
+                .is_ok_and(|signature| key.verify(message, &signature).is_ok()),
+            CoseKey::EdDsa(key) => ed25519_dalek::Signature::from_slice(signature)
+                .is_ok_and(|signature| key.verify_strict(message, &signature).is_ok()),
+            CoseKey::Rs256(key) => {
+                let key = rsa::pkcs1v15::VerifyingKey::<Sha256>::new(key.clone());
+                rsa::pkcs1v15::Signature::try_from(signature)
+                    .is_ok_and(|signature| key.verify(message, &signature).is_ok())
+            }
+        }
+    }
+}
+
+/// The bytes an assertion signature covers.
+pub fn signed_data(authenticator_data: &[u8], client_data_json: &[u8]) -> Vec<u8> {
+    [authenticator_data, Sha256::digest(client_data_json).as_slice()].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rejects_truncated_authenticator_data() {
+        assert!(AuthenticatorData::parse(&[0u8; 36]).is_none());
+
+        // Claims attested credential data that is not there.
+        let mut data = vec![0u8; 37];
+        data[32] = FLAG_USER_PRESENT | FLAG_ATTESTED_CREDENTIAL_DATA;
+        assert!(AuthenticatorData::parse(&data).is_none());
+    }
+
+    #[test]
+    fn test_parse_reads_flags_and_counter() {
+        let mut data = Sha256::digest(b"localhost").to_vec();
+        data.push(FLAG_USER_PRESENT | FLAG_USER_VERIFIED);
+        data.extend_from_slice(&7u32.to_be_bytes());
+
+        let parsed = AuthenticatorData::parse(&data).unwrap();
+        assert!(parsed.matches_rp_id("localhost"));
+        assert!(!parsed.matches_rp_id("example.com"));
+        assert!(parsed.user_present() && parsed.user_verified());
+        assert_eq!(parsed.sign_count, 7);
+        assert!(parsed.attested_credential.is_none());
+    }
+
+    #[test]
+    fn test_unsupported_cose_key_is_rejected() {
+        // {1: 2, 3: -35} is an EC2 key using ES384.
+        let mut encoded = Vec::new();
+        let key = Value::Map(vec![
+            (Value::from(1), Value::from(2)),
+            (Value::from(3), Value::from(-35)),
+        ]);
+        ciborium::ser::into_writer(&key, &mut encoded).unwrap();
+        assert!(CoseKey::from_cbor(&encoded).is_none());
+    }
+
+    #[test]
+    fn test_es256_key_with_short_coordinate_is_rejected() {
+        let mut encoded = Vec::new();
+        let key = Value::Map(vec![
+            (Value::from(1), Value::from(2)),
+            (Value::from(3), Value::from(COSE_ES256)),
+            (Value::from(-1), Value::from(1)),
+            (Value::from(-2), Value::Bytes(vec![1; 31])),
+            (Value::from(-3), Value::Bytes(vec![1; 32])),
+        ]);
+        ciborium::ser::into_writer(&key, &mut encoded).unwrap();
+        assert!(CoseKey::from_cbor(&encoded).is_none());
+    }
+}